@@ -0,0 +1,171 @@
+"""Minimal MCP server used by the Rust tests.
+
+Speaks newline-delimited JSON-RPC over stdin/stdout by default. With
+`--http` it serves the streamable HTTP transport on a free port instead,
+printing the URL as its first line of output.
+"""
+
+import base64
+import json
+import sys
+from http.server import BaseHTTPRequestHandler, HTTPServer
+
+PNG = bytes([0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A])
+
+TOOLS = [
+    {
+        "name": "add",
+        "description": "Add two integers.",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "a": {"type": "integer"},
+                "b": {"type": "integer"},
+            },
+            "required": ["a", "b"],
+        },
+    },
+    {
+        "name": "echo",
+        "description": "Repeat the text back.",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "text": {"type": "string"},
+                "times": {"type": "integer", "minimum": 1},
+            },
+            "required": ["text"],
+        },
+    },
+    {
+        "name": "fail",
+        "description": "Always fails.",
+        "inputSchema": {"type": "object", "properties": {}},
+    },
+    {
+        "name": "pixel",
+        "description": "Returns a tiny image.",
+        "inputSchema": {"type": "object", "properties": {}},
+    },
+    {
+        "name": "tag",
+        "description": "Returns the tags it was given.",
+        "inputSchema": {"type": "object", "additionalProperties": {"type": "string"}},
+    },
+]
+
+
+def text(value):
+    return {"type": "text", "text": value}
+
+
+def call_tool(name, args):
+    if name == "add":
+        total = args["a"] + args["b"]
+        return {"content": [text(str(total))], "structuredContent": {"sum": total}}
+    if name == "echo":
+        return {"content": [text(" ".join([args["text"]] * args.get("times", 1)))]}
+    if name == "fail":
+        return {"content": [text("the disk is full")], "isError": True}
+    if name == "tag":
+        return {"content": [text(json.dumps(args))], "structuredContent": args}
+    if name == "pixel":
+        data = base64.b64encode(PNG).decode()
+        return {
+            "content": [
+                text("one pixel"),
+                {"type": "image", "data": data, "mimeType": "image/png"},
+            ]
+        }
+    raise KeyError(name)
+
+
+def handle(message):
+    """Returns the response to a request, or None for notifications."""
+    if "id" not in message:
+        return None
+    method = message.get("method")
+    params = message.get("params") or {}
+    if method == "initialize":
+        result = {
+            "protocolVersion": params.get("protocolVersion"),
+            "capabilities": {"tools": {}},
+            "serverInfo": {"name": "test-server", "version": "1.0"},
+        }
+    elif method == "ping":
+        result = {}
+    elif method == "tools/list":
+        # Two pages, so clients have to follow the cursor.
+        if params.get("cursor") == "page-2":
+            result = {"tools": TOOLS[2:]}
+        else:
+            result = {"tools": TOOLS[:2], "nextCursor": "page-2"}
+    elif method == "tools/call":
+        try:
+            result = call_tool(params["name"], params.get("arguments") or {})
+        except KeyError as e:
+            return {
+                "jsonrpc": "2.0",
+                "id": message["id"],
+                "error": {"code": -32602, "message": "Unknown tool: %s" % e},
+            }
+    else:
+        return {
+            "jsonrpc": "2.0",
+            "id": message["id"],
+            "error": {"code": -32601, "message": "Method not found: %s" % method},
+        }
+    return {"jsonrpc": "2.0", "id": message["id"], "result": result}
+
+
+def serve_stdio():
+    for line in sys.stdin:
+        if not line.strip():
+            continue
+        response = handle(json.loads(line))
+        if response is not None:
+            sys.stdout.write(json.dumps(response) + "\n")
+            sys.stdout.flush()
+
+
+class Handler(BaseHTTPRequestHandler):
+    def do_POST(self):
+        body = self.rfile.read(int(self.headers["Content-Length"]))
+        message = json.loads(body)
+        if message.get("method") != "initialize" and self.headers.get("Mcp-Session-Id") != "s1":
+            self.send_response(400)
+            self.end_headers()
+            return
+        response = handle(message)
+        if response is None:
+            self.send_response(202)
+            self.end_headers()
+            return
+        # Answer as an SSE stream, preceded by a notification the client must skip.
+        events = [
+            {"jsonrpc": "2.0", "method": "notifications/message", "params": {"data": "working"}},
+            response,
+        ]
+        payload = "".join("event: message\ndata: %s\n\n" % json.dumps(e) for e in events)
+        self.send_response(200)
+        self.send_header("Content-Type", "text/event-stream")
+        self.send_header("Mcp-Session-Id", "s1")
+        self.send_header("Content-Length", str(len(payload)))
+        self.end_headers()
+        self.wfile.write(payload.encode())
+
+    def log_message(self, *args):
+        pass
+
+
+def serve_http():
+    server = HTTPServer(("127.0.0.1", 0), Handler)
+    print("http://127.0.0.1:%d/mcp" % server.server_port, flush=True)
+    server.serve_forever()
+
+
+if __name__ == "__main__":
+    if "--http" in sys.argv:
+        serve_http()
+    else:
+        serve_stdio()
@@ -1,3 +1,4 @@
+use crate::agent::budget::Budget;
 use crate::agent::chat_agent::Agent;
 use crate::msg_types::*;
 use crate::msg_types::{chat_msg_types::ChatMessage, AgentId, SubscriptionId, TopicId};
@@ -173,6 +174,7 @@ pub struct AgentRuntime {
     pub outstanding_tasks: Counter,
     pub background_tasks: HashSet<String>,
     pub subscription_manager: SubscriptionManager,
+    pub budget: Budget,
 }
 
 pub struct Handler;
@@ -381,6 +383,7 @@ async fn main() {
             seen_topics: HashSet::<TopicId>::new(),
             subscribed_recipients: HashMap::<TopicId, Vec<AgentId>>::new(),
         },
+        budget: Budget::unlimited("runtime"),
     };
 
     let topic_id = TopicId::new(Some("general_topic"));
@@ -0,0 +1,203 @@
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+use crate::agent::llm_backend::LlmConfig;
+use crate::msg_types::RequestUsage;
+
+/// Upper bounds for a budget scope; `None` leaves that dimension unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BudgetLimit {
+    pub max_tokens: Option<i64>,
+    pub max_cost_usd: Option<f64>,
+}
+
+impl BudgetLimit {
+    pub fn tokens(max_tokens: i64) -> Self {
+        BudgetLimit {
+            max_tokens: Some(max_tokens),
+            max_cost_usd: None,
+        }
+    }
+
+    pub fn cost_usd(max_cost_usd: f64) -> Self {
+        BudgetLimit {
+            max_tokens: None,
+            max_cost_usd: Some(max_cost_usd),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BudgetSpend {
+    pub tokens: i64,
+    pub cost_usd: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct BudgetExceeded {
+    pub scope: String,
+    pub limit: BudgetLimit,
+    pub spent: BudgetSpend,
+}
+
+impl std::fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "budget '{}' exhausted: spent {} tokens / ${:.4} (limit: {} tokens / {})",
+            self.scope,
+            self.spent.tokens,
+            self.spent.cost_usd,
+            self.limit
+                .max_tokens
+                .map(|t| t.to_string())
+                .unwrap_or_else(|| "unlimited".to_string()),
+            self.limit
+                .max_cost_usd
+                .map(|c| format!("${:.4}", c))
+                .unwrap_or_else(|| "unlimited".to_string()),
+        )
+    }
+}
+
+impl Error for BudgetExceeded {}
+
+struct BudgetState {
+    scope: String,
+    limit: BudgetLimit,
+    spent: BudgetSpend,
+}
+
+impl BudgetState {
+    fn is_exhausted(&self) -> bool {
+        self.limit
+            .max_tokens
+            .is_some_and(|max| self.spent.tokens >= max)
+            || self
+                .limit
+                .max_cost_usd
+                .is_some_and(|max| self.spent.cost_usd >= max)
+    }
+}
+
+/// Shared token/cost counter. Budgets form a chain (agent -> group chat -> runtime):
+/// usage recorded on a child is charged to every ancestor, and a call is refused
+/// as soon as any scope in the chain is exhausted.
+#[derive(Clone)]
+pub struct Budget {
+    state: Arc<Mutex<BudgetState>>,
+    parent: Option<Box<Budget>>,
+}
+
+impl Budget {
+    pub fn new(scope: impl Into<String>, limit: BudgetLimit) -> Self {
+        Budget {
+            state: Arc::new(Mutex::new(BudgetState {
+                scope: scope.into(),
+                limit,
+                spent: BudgetSpend::default(),
+            })),
+            parent: None,
+        }
+    }
+
+    pub fn unlimited(scope: impl Into<String>) -> Self {
+        Budget::new(scope, BudgetLimit::default())
+    }
+
+    pub fn child(&self, scope: impl Into<String>, limit: BudgetLimit) -> Self {
+        let mut child = Budget::new(scope, limit);
+        child.parent = Some(Box::new(self.clone()));
+        child
+    }
+
+    pub fn set_limit(&self, limit: BudgetLimit) {
+        self.state.lock().unwrap().limit = limit;
+    }
+
+    pub fn spent(&self) -> BudgetSpend {
+        self.state.lock().unwrap().spent
+    }
+
+    pub fn check(&self) -> Result<(), BudgetExceeded> {
+        {
+            let state = self.state.lock().unwrap();
+            if state.is_exhausted() {
+                return Err(BudgetExceeded {
+                    scope: state.scope.clone(),
+                    limit: state.limit,
+                    spent: state.spent,
+                });
+            }
+        }
+        match &self.parent {
+            Some(parent) => parent.check(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn record(&self, usage: &RequestUsage, llm_config: &LlmConfig) {
+        let cost = llm_config.cost(usage);
+        let mut budget = Some(self);
+        while let Some(current) = budget {
+            {
+                let mut state = current.state.lock().unwrap();
+                state.spent.tokens += usage.total_tokens();
+                state.spent.cost_usd += cost;
+            }
+            budget = current.parent.as_deref();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::llm_backend::{ANTHROPIC_CONFIG, OPENAI_CONFIG};
+
+    fn usage(prompt_tokens: i32, completion_tokens: i32) -> RequestUsage {
+        RequestUsage {
+            prompt_tokens,
+            completion_tokens,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_token_budget_exhausts() {
+        let budget = Budget::new("agent", BudgetLimit::tokens(100));
+        assert!(budget.check().is_ok());
+
+        budget.record(&usage(60, 50), &OPENAI_CONFIG);
+        let err = budget.check().unwrap_err();
+        assert_eq!(err.scope, "agent");
+        assert_eq!(err.spent.tokens, 110);
+    }
+
+    #[test]
+    fn test_child_usage_charges_parent() {
+        let runtime = Budget::new("runtime", BudgetLimit::cost_usd(0.001));
+        let group = runtime.child("group_chat", BudgetLimit::default());
+        let agent = group.child("agent", BudgetLimit::tokens(1_000_000));
+
+        agent.record(&usage(1000, 1000), &OPENAI_CONFIG);
+
+        assert_eq!(runtime.spent().tokens, 2000);
+        let err = agent.check().unwrap_err();
+        assert_eq!(err.scope, "runtime");
+    }
+
+    #[test]
+    fn test_cached_prompt_tokens_use_cache_rates() {
+        let cached = RequestUsage {
+            prompt_tokens: 1_000_000,
+            completion_tokens: 0,
+            cache_read_tokens: 800_000,
+            cache_creation_tokens: 100_000,
+        };
+        // 100k uncached at $3, 800k read at $0.30 and 100k written at $3.75.
+        let cost = ANTHROPIC_CONFIG.cost(&cached);
+        assert!((cost - (0.3 + 0.24 + 0.375)).abs() < 1e-9);
+        assert!(cost < ANTHROPIC_CONFIG.cost(&usage(1_000_000, 0)));
+    }
+}
@@ -1,28 +1,60 @@
+use crate::agent::budget::{Budget, BudgetExceeded};
+use crate::agent::context_policy::{ContextPolicy, KeepAll};
+use crate::agent::llm_backend::audio::{SpeechClient, TranscriptionClient};
+use crate::agent::llm_backend::image_gen::{save_images, ImageGenClient, ImageGenRequest};
+use crate::agent::llm_backend::{client_for, AgentCapability, CreateRequest, LlmClient, LlmConfig};
+use crate::agent::memory::{Memory, DEFAULT_TOP_K};
 use crate::msg_types::chat_msg_types::{
-    MultiModalMessage, TextMessage, ToolCallResultContent, ToolCallResultMessage,
+    AudioMessage, MultiModalMessage, TextMessage, ToolCallContent, ToolCallMessage,
+    ToolCallResultContent, ToolCallResultMessage,
 };
 use crate::msg_types::{
     chat_msg_types::ChatMessage, llm_msg_types::LlmMessage, ChatMessageContext, CodeBlock,
     CodeResult, FinishReason, MultiModalContent, RequestUsage, ResponseFormat, TextContent,
 };
-use crate::msg_types::{AgentId, FunctionExecutionResult, ImageContent, TopicId};
-use crate::tool_types::{FunctionCallInput, Tool};
-use once_cell::sync::Lazy;
+use crate::msg_types::llm_msg_types::FunctionExecutionResultMessage;
+use crate::msg_types::{AgentId, FunctionExecutionResult, TopicId};
+use crate::tool_types::{
+    CompiledSchema, FunctionCallInput, Tool, ToolFuture, ToolOutput, ToolPermission, ToolPolicy,
+    ToolRegistry, ToolRenderMode, DEFAULT_TOOL_TIMEOUT,
+};
 use serde_json::Value;
 use std::collections::HashMap;
-use std::sync::Mutex;
-
-pub static STORE: Lazy<Mutex<HashMap<String, Tool>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Semaphore};
 
 #[derive(Debug, Clone)]
 pub struct LlmCompletionContext {
     pub messages: Vec<LlmMessage>,
     pub state: HashMap<String, Vec<LlmMessage>>,
+    /// Applied after every `add_message`; its output replaces `messages`.
+    pub policy: Arc<dyn ContextPolicy>,
+}
+
+impl Default for LlmCompletionContext {
+    fn default() -> Self {
+        LlmCompletionContext::with_policy(Arc::new(KeepAll))
+    }
 }
 
 impl LlmCompletionContext {
+    pub fn with_policy(policy: Arc<dyn ContextPolicy>) -> Self {
+        LlmCompletionContext {
+            messages: Vec::new(),
+            state: HashMap::new(),
+            policy,
+        }
+    }
+
+    /// Appends the message, then lets the policy trim or compact the history. If
+    /// the policy fails (e.g. the summarizer is unreachable) everything is kept.
     pub async fn add_message(&mut self, message: LlmMessage) {
         self.messages.push(message);
+        match self.policy.apply(self.messages.clone()).await {
+            Ok(messages) => self.messages = messages,
+            Err(e) => println!("Context policy failed, keeping full history: {}", e),
+        }
     }
     pub async fn get_message(self) -> Vec<LlmMessage> {
         self.messages.clone()
@@ -49,7 +81,7 @@ pub struct ToolUseAgent {
     pub agent_base: Agent,
     pub llm_context: LlmCompletionContext,
     pub tool_schema: Vec<Value>,
-    pub registered_tools: Vec<Tool>,
+    pub registered_tools: ToolRegistry,
 }
 
 pub struct LlmCompletionAgent {
@@ -58,7 +90,26 @@ pub struct LlmCompletionAgent {
     pub model_client: LlmCompletionClient,
     pub system_messages: Vec<LlmMessage>,
     pub tool_schema: Vec<Value>,
-    pub registered_tools: Vec<Tool>,
+    /// The only tools the model is offered and allowed to call.
+    pub registered_tools: ToolRegistry,
+    /// Images are downscaled to fit this many pixels per side before reaching the model.
+    pub max_image_side: Option<u32>,
+    /// Turns incoming voice messages into text for the model.
+    pub transcriber: Option<Arc<dyn TranscriptionClient>>,
+    /// When set, text replies are also spoken and sent as an `AudioMessage`.
+    pub speech: Option<Arc<dyn SpeechClient>>,
+    /// Recalled into the prompt before each response; user turns are stored in it.
+    pub memory: Option<Arc<dyn Memory>>,
+    /// Model calls per response before giving up on a tool loop.
+    pub max_iterations: usize,
+    /// Tool calls from one model turn that may run at the same time.
+    pub max_parallel_tools: usize,
+    /// How tool return values are written out for the model.
+    pub tool_render: ToolRenderMode,
+    /// Checked before every tool call; denied tools are also hidden from the model.
+    pub tool_policy: ToolPolicy,
+    /// Receives each tool call and its results while a response is generated.
+    pub observer: Option<mpsc::Sender<ChatMessage>>,
 }
 
 pub struct CodeExecAgent {
@@ -69,6 +120,14 @@ pub struct CodeExecAgent {
 
 pub struct Engine;
 
+/// Answers text requests with generated images.
+pub struct ImageGenAgent {
+    pub agent_base: Agent,
+    pub image_client: Arc<dyn ImageGenClient>,
+    /// When set, every generated image is also written here.
+    pub output_dir: Option<PathBuf>,
+}
+
 impl Agent {
     pub async fn on_message(&mut self, message: ChatMessage) -> ChatMessage {
         todo!()
@@ -103,35 +162,184 @@ impl CodeExecAgent {
     }
 }
 
+impl ImageGenAgent {
+    /// Uses the text of a `TextMessage` (or the text parts of a
+    /// `MultiModalMessage`) as the prompt and replies with a `MultiModalMessage`
+    /// holding the images.
+    pub async fn on_message(
+        &mut self,
+        message: ChatMessage,
+        ctx: ChatMessageContext,
+    ) -> ChatMessage {
+        let prompt = match message {
+            ChatMessage::TextMessage(tex) => tex.content.text,
+            ChatMessage::MultiModalMessage(mm) => mm
+                .content
+                .into_iter()
+                .filter_map(|part| match part {
+                    MultiModalContent::Text(text) => Some(text.text),
+                    MultiModalContent::Image(_) => None,
+                })
+                .collect::<Vec<String>>()
+                .join("\n"),
+            other => {
+                return ChatMessage::StopMessage(format!(
+                    "{} only answers text requests, got {:?}",
+                    self.agent_base.name, other
+                ))
+            }
+        };
+
+        let images = match self
+            .image_client
+            .generate(&ImageGenRequest::new(prompt))
+            .await
+        {
+            Ok(images) => images,
+            Err(e) => return ChatMessage::StopMessage(format!("Image generation failed: {}", e)),
+        };
+
+        if let Some(dir) = &self.output_dir {
+            match save_images(&images, dir, &self.agent_base.name) {
+                Ok(paths) => println!("Saved generated images: {:?}", paths),
+                Err(e) => println!("Could not save generated images: {}", e),
+            }
+        }
+
+        ChatMessage::MultiModalMessage(MultiModalMessage {
+            content: images.into_iter().map(MultiModalContent::Image).collect(),
+            source: ctx.sender,
+        })
+    }
+}
+
 impl LlmCompletionAgent {
+    pub fn new(agent_base: Agent, model_client: LlmCompletionClient) -> Self {
+        LlmCompletionAgent {
+            agent_base,
+            llm_context: LlmCompletionContext::default(),
+            model_client,
+            system_messages: Vec::new(),
+            tool_schema: Vec::new(),
+            registered_tools: ToolRegistry::new(),
+            max_image_side: None,
+            transcriber: None,
+            speech: None,
+            memory: None,
+            max_iterations: 10,
+            max_parallel_tools: 4,
+            tool_render: ToolRenderMode::CompactJson,
+            tool_policy: ToolPolicy::default(),
+            observer: None,
+        }
+    }
+
+    /// Wraps the agent as a tool taking a `task`, so other agents or MCP clients
+    /// can delegate to it. Calls are answered one at a time and share the
+    /// agent's conversation.
+    pub fn into_tool(self) -> Tool {
+        let name = self.agent_base.name.clone();
+        let tool_def_obj = serde_json::json!({
+            "name": name,
+            "description": self.agent_base.description,
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "task": {"type": "string", "description": "What to ask the agent"}
+                },
+                "required": ["task"]
+            }
+        })
+        .to_string();
+        let schema =
+            CompiledSchema::for_tool_def(&tool_def_obj).expect("the agent tool schema is valid");
+        let agent = Arc::new(tokio::sync::Mutex::new(self));
+
+        let function = Arc::new(move |args: Vec<Value>| -> ToolFuture {
+            let agent = agent.clone();
+            Box::pin(async move {
+                let task = args.first().and_then(Value::as_str).unwrap_or_default();
+                let caller = AgentId::new(None);
+                let ctx = || ChatMessageContext {
+                    sender: caller.clone(),
+                    topic_id: TopicId::new(None),
+                    is_rpc: true,
+                };
+                let mut agent = agent.lock().await;
+                let task = ChatMessage::TextMessage(TextMessage {
+                    content: task.into(),
+                    source: caller.clone(),
+                });
+                agent.on_message(task, ctx()).await;
+                match agent.generate_response(ResponseFormat::Text, ctx()).await {
+                    ChatMessage::TextMessage(tm) => ToolOutput::new(tm.content.text),
+                    ChatMessage::MultiModalMessage(mm) => Ok(ToolOutput {
+                        value: Value::String(String::new()),
+                        attachments: mm.content,
+                    }),
+                    ChatMessage::AudioMessage(am) => {
+                        ToolOutput::new(am.transcript.unwrap_or_default())
+                    }
+                    ChatMessage::StopMessage(reason) => Err(reason.into()),
+                    other => Err(format!("Unexpected reply from agent: {:?}", other).into()),
+                }
+            })
+        });
+
+        Tool {
+            name,
+            function,
+            tool_def_obj,
+            arg_names: vec!["task".to_string()],
+            arg_types: vec!["String".to_string()],
+            arg_optional: vec![false],
+            // Several model calls and tool runs may happen before the answer.
+            timeout: DEFAULT_TOOL_TIMEOUT * 10,
+            schema,
+        }
+    }
+
     async fn on_message(&mut self, message: ChatMessage, ctx: ChatMessageContext) {
         let msg: LlmMessage = match message {
             ChatMessage::TextMessage(tex) => {
                 LlmMessage::user_text(tex.content.text, ctx.sender)
             }
-            ChatMessage::MultiModalMessage(mm) => match mm.content {
-                MultiModalContent::Text(tex) => {
-                    LlmMessage::user_text(tex.text, ctx.sender)
-                }
-                MultiModalContent::Image(img) => {
-                    LlmMessage::user_image(img.image.into(), ctx.sender)
-                }
-            },
+            ChatMessage::MultiModalMessage(mm) => {
+                let parts = mm
+                    .content
+                    .into_iter()
+                    .map(|part| match (part, self.max_image_side) {
+                        (MultiModalContent::Image(img), Some(max_side)) => {
+                            MultiModalContent::Image(img.downscale(max_side).unwrap_or_else(|e| {
+                                println!("Could not downscale image, sending original: {}", e);
+                                img
+                            }))
+                        }
+                        (part, _) => part,
+                    })
+                    .collect();
+                LlmMessage::user_multimodal(parts, ctx.sender)
+            }
+            ChatMessage::AudioMessage(am) => {
+                let text = match (am.transcript, &self.transcriber) {
+                    (Some(transcript), _) => transcript,
+                    (None, Some(transcriber)) => match transcriber.transcribe(&am.content).await {
+                        Ok(text) => text,
+                        Err(e) => {
+                            println!("Could not transcribe voice message: {}", e);
+                            "[voice message could not be transcribed]".to_string()
+                        }
+                    },
+                    (None, None) => "[voice message, no transcriber configured]".to_string(),
+                };
+                LlmMessage::user_text(text, ctx.sender)
+            }
             ChatMessage::ToolCallMessage(tcm) => {
-                let mut res = Vec::<String>::new();
-                for fc in tcm.content.content {
-                    let func_name = fc.function_name;
-                    let arguments_w_val = fc.arguments_obj;
-
-                    let binding = STORE.lock().unwrap();
-                    let func = binding.get(&func_name).unwrap();
-                    let raw_result: String = func.run(arguments_w_val).expect("failed run");
-
-                    res.push(raw_result);
-                }
-                let call_id = TopicId::new(Some("placeholder"));
-                let source = AgentId::new(Some("placehlder"));
-                LlmMessage::function_result(res.join(", "), call_id, source)
+                let res = self.run_tools(&tcm.content.content).await;
+                LlmMessage::FunctionExecutionResultMessage(FunctionExecutionResultMessage {
+                    content: res,
+                    source: ctx.sender,
+                })
             }
             ChatMessage::ToolCallResultMessage(tcrm) => {
                 let text = tcrm
@@ -172,85 +380,305 @@ impl LlmCompletionAgent {
         response
     }
 
+    /// Looks up memories related to the latest user turn and returns them as a
+    /// system message, then stores that turn for later conversations. The final
+    /// reply is stored by `reply_with_text`.
+    async fn consult_memory(&self) -> Option<LlmMessage> {
+        let memory = self.memory.as_ref()?;
+        let query = self
+            .llm_context
+            .messages
+            .iter()
+            .rev()
+            .find_map(|msg| match msg {
+                LlmMessage::UserMessage(um) => Some(
+                    um.content
+                        .iter()
+                        .filter_map(|part| match part {
+                            MultiModalContent::Text(text) => Some(text.text.as_str()),
+                            MultiModalContent::Image(_) => None,
+                        })
+                        .collect::<Vec<&str>>()
+                        .join("\n"),
+                ),
+                _ => None,
+            })
+            .filter(|query| !query.is_empty())?;
+
+        let hits = match memory.query(&query, DEFAULT_TOP_K).await {
+            Ok(hits) => hits,
+            Err(e) => {
+                println!("Memory lookup failed: {}", e);
+                Vec::new()
+            }
+        };
+        self.remember(&query, "user").await;
+        if hits.is_empty() {
+            return None;
+        }
+
+        let recalled = hits
+            .iter()
+            .map(|hit| format!("- {}", hit.text))
+            .collect::<Vec<String>>()
+            .join("\n");
+        Some(LlmMessage::system(
+            format!("Relevant memories:\n{}", recalled),
+            AgentId::new(Some("memory")),
+        ))
+    }
+
+    async fn remember(&self, text: &str, role: &str) {
+        let Some(memory) = &self.memory else {
+            return;
+        };
+        let metadata = HashMap::from([("role".to_string(), role.to_string())]);
+        if let Err(e) = memory.add(text, metadata).await {
+            println!("Could not store message in memory: {}", e);
+        }
+    }
+
+    /// Calls the model, runs every tool it asks for and calls it again with the
+    /// results, until it answers or `max_iterations` calls have been made.
     async fn generate_response(
         &mut self,
         response_format: ResponseFormat,
         ctx: ChatMessageContext,
     ) -> ChatMessage {
-        let mut messages = self.system_messages.clone();
-        messages.extend(self.llm_context.clone().get_message().await.into_iter());
+        let recalled = self.consult_memory().await;
+        let source = AgentId::new(Some(&self.agent_base.name));
 
-        let response = self
-            .model_client
-            .clone()
-            .create(
-                messages,
-                self.registered_tools.clone(),
-                response_format == ResponseFormat::JsonObject,
-                HashMap::new(),
-            )
+        for _ in 0..self.max_iterations {
+            let mut messages = self.system_messages.clone();
+            messages.extend(recalled.clone());
+            messages.extend(self.llm_context.messages.iter().cloned());
+
+            let response = match self
+                .model_client
+                .clone()
+                .create(
+                    messages,
+                    self.offered_tools(),
+                    response_format == ResponseFormat::JsonObject,
+                    HashMap::new(),
+                )
+                .await
+            {
+                Ok(response) => response,
+                Err(e) => {
+                    if let Some(exceeded) = e.downcast_ref::<BudgetExceeded>() {
+                        return ChatMessage::StopMessage(exceeded.to_string());
+                    }
+                    return ChatMessage::StopMessage(format!("LLM call failed: {}", e));
+                }
+            };
+
+            let calls = match response.content {
+                ResultContent::TextContent(tc)
+                | ResultContent::MultiModalContent(MultiModalContent::Text(tc)) => {
+                    return self.reply_with_text(tc, source, ctx).await;
+                }
+                ResultContent::MultiModalContent(MultiModalContent::Image(ic)) => {
+                    return ChatMessage::MultiModalMessage(MultiModalMessage {
+                        content: vec![MultiModalContent::Image(ic)],
+                        source: ctx.sender,
+                    });
+                }
+                ResultContent::FunctionCallContent(calls) => calls,
+            };
+
+            self.publish(ChatMessage::ToolCallMessage(ToolCallMessage {
+                content: ToolCallContent {
+                    content: calls.clone(),
+                },
+                source: source.clone(),
+            }))
             .await;
 
-        match response.content {
-            ResultContent::TextContent(tc) => {
-                let msg = LlmMessage::assistant_text(tc.text.clone(), AgentId::new(Some("source")));
-                self.llm_context.add_message(msg).await;
+            // One assistant turn per call, each followed by its result, is accepted
+            // by every backend and keeps calls and results adjacent for policies.
+            let results = self.run_tools(&calls).await;
+            for (call, result) in calls.into_iter().zip(results.clone()) {
+                self.llm_context
+                    .add_message(LlmMessage::assistant_function_run(call, source.clone()))
+                    .await;
+                self.llm_context
+                    .add_message(LlmMessage::FunctionExecutionResultMessage(
+                        FunctionExecutionResultMessage {
+                            content: vec![result],
+                            source: source.clone(),
+                        },
+                    ))
+                    .await;
+            }
+            let attachments = self.tool_attachments(&results);
+            if !attachments.is_empty() {
+                self.llm_context
+                    .add_message(LlmMessage::user_multimodal(attachments, source.clone()))
+                    .await;
+            }
 
-                ChatMessage::TextMessage(TextMessage {
-                    content: tc,
-                    source: ctx.sender,
-                })
+            self.publish(ChatMessage::ToolCallResultMessage(ToolCallResultMessage {
+                content: ToolCallResultContent { content: results },
+                source: source.clone(),
+            }))
+            .await;
+        }
+
+        ChatMessage::StopMessage(format!(
+            "No answer after {} model calls",
+            self.max_iterations
+        ))
+    }
+
+    async fn reply_with_text(
+        &mut self,
+        tc: TextContent,
+        source: AgentId,
+        ctx: ChatMessageContext,
+    ) -> ChatMessage {
+        self.llm_context
+            .add_message(LlmMessage::assistant_text(tc.text.clone(), source))
+            .await;
+        self.remember(&tc.text, "assistant").await;
+
+        if let Some(speech) = &self.speech {
+            match speech.synthesize(&tc.text).await {
+                Ok(audio) => {
+                    return ChatMessage::AudioMessage(AudioMessage {
+                        content: audio,
+                        transcript: Some(tc.text),
+                        source: ctx.sender,
+                    })
+                }
+                Err(e) => println!("Could not synthesize speech, replying with text: {}", e),
             }
-            ResultContent::FunctionCallContent(fcc) => {
-                let func_name = fcc.function_name;
-                let arguments_w_val = fcc.arguments_obj;
-
-                let binding = STORE.lock().unwrap();
-                let func = binding.get(&func_name);
-                let raw_result: String = func.expect("failed to get tool").run(arguments_w_val).expect("failed run");
-
-                let tcrm: ToolCallResultContent = ToolCallResultContent {
-                    content: vec![FunctionExecutionResult {
-                        content: raw_result,
-                        call_id: TopicId::new(Some("place")),
-                    }],
-                };
+        }
 
-                ChatMessage::ToolCallResultMessage(ToolCallResultMessage {
-                    content: tcrm,
-                    source: ctx.sender,
+        ChatMessage::TextMessage(TextMessage {
+            content: tc,
+            source: ctx.sender,
+        })
+    }
+
+    fn offered_tools(&self) -> Vec<Tool> {
+        self.registered_tools
+            .to_vec()
+            .into_iter()
+            .filter(|tool| self.tool_policy.permission(&tool.name) != ToolPermission::Deny)
+            .collect()
+    }
+
+    /// Runs the calls concurrently, at most `max_parallel_tools` at a time, and
+    /// returns their results in call order. Failures, timeouts and panics come
+    /// back as error results the model can read and correct.
+    async fn run_tools(&self, calls: &[FunctionCallInput]) -> Vec<FunctionExecutionResult> {
+        let permits = Arc::new(Semaphore::new(self.max_parallel_tools.max(1)));
+        let handles = calls
+            .iter()
+            .cloned()
+            .map(|call| {
+                let registry = self.registered_tools.clone();
+                let policy = self.tool_policy.clone();
+                let permits = permits.clone();
+                let requester = AgentId::new(Some(&self.agent_base.name));
+                tokio::spawn(async move {
+                    // Waiting for approval does not hold up calls that can run now.
+                    policy.check(&call, &requester).await?;
+                    let _permit = permits.acquire_owned().await;
+                    registry.run(&call).await
                 })
-            }
-            ResultContent::MultiModalContent(mmc) => {
-                match mmc {
-                    MultiModalContent::Text(tc) => ChatMessage::TextMessage(TextMessage {
-                        content: tc,
-                        source: ctx.sender,
-                    }),
+            })
+            .collect::<Vec<_>>();
 
-                    MultiModalContent::Image(ic) => {
-                        ChatMessage::MultiModalMessage(MultiModalMessage {
-                            content: MultiModalContent::Image(ImageContent { image: ic.image }),
-                            source: ctx.sender,
-                        })
+        let mut results = Vec::new();
+        for (call, handle) in calls.iter().zip(handles) {
+            let outcome = match handle.await {
+                Ok(outcome) => outcome,
+                Err(e) => Err(format!("Tool {} panicked: {}", call.function_name, e).into()),
+            };
+            results.push(match outcome {
+                Ok(output) => FunctionExecutionResult {
+                    content: output.render(self.tool_render),
+                    call_id: call.id.clone(),
+                    is_error: false,
+                    value: output.value,
+                    attachments: output.attachments,
+                },
+                Err(e) => {
+                    println!("Tool call {} failed: {}", call.function_name, e);
+                    FunctionExecutionResult {
+                        content: format!("Error: {}", e),
+                        call_id: call.id.clone(),
+                        is_error: true,
+                        value: Value::Null,
+                        attachments: Vec::new(),
                     }
-                };
-                todo!()
+                }
+            });
+        }
+        results
+    }
+
+    /// Tool results only carry text on every backend, so attached images and
+    /// files follow them in a user turn. Models without vision get a note instead.
+    fn tool_attachments(&self, results: &[FunctionExecutionResult]) -> Vec<MultiModalContent> {
+        let vision = self
+            .model_client
+            .llm_config
+            .supports(&AgentCapability::Vision);
+        results
+            .iter()
+            .flat_map(|result| {
+                result
+                    .attachments
+                    .iter()
+                    .map(move |part| (&result.call_id, part))
+            })
+            .map(|(call_id, part)| match (part, self.max_image_side) {
+                (MultiModalContent::Image(_), _) if !vision => MultiModalContent::Text(
+                    format!(
+                        "[Tool call {} returned an image this model cannot view]",
+                        call_id
+                    )
+                    .into(),
+                ),
+                (MultiModalContent::Image(img), Some(max_side)) => {
+                    MultiModalContent::Image(img.downscale(max_side).unwrap_or_else(|e| {
+                        println!("Could not downscale image, sending original: {}", e);
+                        img.clone()
+                    }))
+                }
+                (part, _) => part.clone(),
+            })
+            .collect()
+    }
+
+    async fn publish(&self, message: ChatMessage) {
+        if let Some(observer) = &self.observer {
+            if observer.send(message).await.is_err() {
+                println!("Observer of {} has gone away", self.agent_base.name);
             }
         }
     }
 }
 
+#[derive(Debug, Clone)]
 pub enum ResultContent {
     TextContent(TextContent),
     MultiModalContent(MultiModalContent),
-    FunctionCallContent(FunctionCallInput),
+    /// Every tool call the model requested in one turn, in order.
+    FunctionCallContent(Vec<FunctionCallInput>),
 }
 
+#[derive(Debug, Clone)]
 pub struct CreateResult {
     pub finish_reason: FinishReason,
     pub content: ResultContent,
     pub usage: RequestUsage,
+    /// Config of the model that actually answered, which may differ from the
+    /// requested one behind a `FallbackClient`; budgets are charged at its pricing.
+    pub served_by: LlmConfig,
 }
 
 pub struct ModelCapabilities {
@@ -265,17 +693,77 @@ pub struct LlmCompletionClient {
     tools: Vec<Tool>,
     json_output: bool,
     extra_create_args: HashMap<String, Value>,
+    pub llm_config: LlmConfig,
+    pub budget: Option<Budget>,
+    pub max_tokens: u16,
+    backend: Arc<dyn LlmClient>,
 }
 
 impl LlmCompletionClient {
+    pub fn new(llm_config: LlmConfig, budget: Option<Budget>) -> Self {
+        LlmCompletionClient::with_backend(client_for(llm_config), budget)
+    }
+
+    /// Uses `backend` as is, e.g. a `FallbackClient` or a cassette player.
+    pub fn with_backend(backend: Arc<dyn LlmClient>, budget: Option<Budget>) -> Self {
+        LlmCompletionClient {
+            messages: Vec::new(),
+            tools: Vec::new(),
+            json_output: false,
+            extra_create_args: HashMap::new(),
+            llm_config: backend.llm_config().clone(),
+            budget,
+            max_tokens: 1000,
+            backend,
+        }
+    }
+
+    /// Refuses the call with `BudgetExceeded` once any scope of the client's budget
+    /// is exhausted, and charges the returned usage to it afterwards.
     pub async fn create(
         self,
         messages: Vec<LlmMessage>,
         tools: Vec<Tool>,
         json_output: bool,
         extra_create_args: HashMap<String, Value>,
-    ) -> CreateResult {
-        todo!()
+    ) -> anyhow::Result<CreateResult> {
+        if let Some(budget) = &self.budget {
+            budget.check()?;
+        }
+
+        let budget = self.budget.clone();
+        let result = self
+            .dispatch(messages, tools, json_output, extra_create_args)
+            .await?;
+
+        if let Some(budget) = budget {
+            budget.record(&result.usage, &result.served_by);
+        }
+        Ok(result)
+    }
+
+    async fn dispatch(
+        self,
+        messages: Vec<LlmMessage>,
+        tools: Vec<Tool>,
+        json_output: bool,
+        extra_create_args: HashMap<String, Value>,
+    ) -> anyhow::Result<CreateResult> {
+        let mut tool_defs = Vec::new();
+        for tool in &tools {
+            let def = serde_json::from_str::<Value>(&tool.tool_def_obj)
+                .map_err(|e| anyhow::anyhow!("Invalid definition for tool {}: {}", tool.name, e))?;
+            tool_defs.push(def);
+        }
+
+        let request = CreateRequest {
+            messages,
+            tools: tool_defs,
+            json_output,
+            max_tokens: self.max_tokens,
+            extra_create_args,
+        };
+        self.backend.create(&request).await
     }
 
     pub fn capabilities(self) -> ModelCapabilities {
@@ -308,3 +796,510 @@ pub struct PublishNow {
 }
 
 pub struct Reset;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::llm_backend::audio::OpenAiTranscriptionClient;
+    use crate::agent::llm_backend::embeddings::OpenAiEmbeddingClient;
+    use crate::agent::llm_backend::image_gen::OpenAiImageClient;
+    use crate::agent::llm_backend::mock_server::{MockLlmServer, MockReply};
+    use crate::agent::llm_backend::ANTHROPIC_CONFIG;
+    use crate::agent::llm_backend::{
+        DALLE_CONFIG, OPENAI_CONFIG, OPENAI_EMBEDDING_CONFIG, WHISPER_CONFIG,
+    };
+    use crate::agent::memory::{VectorMemory, VectorStore};
+    use crate::msg_types::AudioContent;
+    use crate::msg_types::ImageContent;
+    use crate::msg_types::TopicId;
+    use crate::tool_types::{
+        get_current_weather_tool, Approver, CompiledSchema, IntoToolOutput, ToolFuture, ToolOutput,
+        DEFAULT_TOOL_TIMEOUT,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tool_builder::create_tool_with_function;
+
+    fn completion_agent() -> LlmCompletionAgent {
+        LlmCompletionAgent::new(
+            Agent {
+                name: "assistant".to_string(),
+                description: "answers questions".to_string(),
+                chat_context: Vec::new(),
+            },
+            LlmCompletionClient::new(OPENAI_CONFIG, None),
+        )
+    }
+
+    fn ctx() -> ChatMessageContext {
+        ChatMessageContext {
+            sender: AgentId::new(None),
+            topic_id: TopicId::new(None),
+            is_rpc: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_image_gen_agent_replies_with_images() {
+        let png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        let server = MockLlmServer::start(vec![
+            MockReply::Images(vec![png.clone(), png]),
+            MockReply::error(400, "content policy violation"),
+        ])
+        .await
+        .unwrap();
+        let client =
+            OpenAiImageClient::new(server.config_for_path(&DALLE_CONFIG, "/v1/images/generations"))
+                .unwrap();
+        let mut agent = ImageGenAgent {
+            agent_base: Agent {
+                name: "illustrator".to_string(),
+                description: "draws pictures".to_string(),
+                chat_context: Vec::new(),
+            },
+            image_client: Arc::new(client),
+            output_dir: None,
+        };
+        let request = || {
+            ChatMessage::TextMessage(TextMessage {
+                content: "a dog telling a joke".into(),
+                source: AgentId::new(None),
+            })
+        };
+
+        match agent.on_message(request(), ctx()).await {
+            ChatMessage::MultiModalMessage(mm) => assert_eq!(mm.content.len(), 2),
+            other => panic!("expected images, got {:?}", other),
+        }
+        match agent.on_message(request(), ctx()).await {
+            ChatMessage::StopMessage(reason) => assert!(reason.contains("content policy")),
+            other => panic!("expected a stop message, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_voice_message_is_transcribed_before_the_llm() {
+        let server = MockLlmServer::start(vec![MockReply::text("what time is it?")])
+            .await
+            .unwrap();
+        let transcriber = OpenAiTranscriptionClient::new(
+            server.config_for_path(&WHISPER_CONFIG, "/v1/audio/transcriptions"),
+        )
+        .unwrap();
+        let mut agent = completion_agent();
+        agent.transcriber = Some(Arc::new(transcriber));
+        let voice = ChatMessage::AudioMessage(AudioMessage {
+            content: AudioContent::from_bytes(b"OggS\x00\x02".to_vec()).unwrap(),
+            transcript: None,
+            source: AgentId::new(None),
+        });
+
+        agent.on_message(voice, ctx()).await;
+
+        match &agent.llm_context.messages[0] {
+            LlmMessage::UserMessage(msg) => match &msg.content[..] {
+                [MultiModalContent::Text(text)] => assert_eq!(text.text, "what time is it?"),
+                other => panic!("expected transcribed text, got {:?}", other),
+            },
+            other => panic!("expected a user message, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_memories_are_recalled_into_the_prompt() {
+        let server = MockLlmServer::start(vec![
+            MockReply::Embeddings(vec![vec![1.0, 0.0], vec![0.0, 1.0]]),
+            MockReply::Embeddings(vec![vec![0.9, 0.1]]),
+            MockReply::Embeddings(vec![vec![0.9, 0.1]]),
+        ])
+        .await
+        .unwrap();
+        let embedder = OpenAiEmbeddingClient::new(
+            server.config_for_path(&OPENAI_EMBEDDING_CONFIG, "/v1/embeddings"),
+        )
+        .unwrap();
+        let memory = Arc::new(VectorMemory::new(Arc::new(embedder), VectorStore::new()));
+        memory
+            .add_documents(&[
+                "the user's dog is called Rex".to_string(),
+                "unrelated".to_string(),
+            ])
+            .await
+            .unwrap();
+
+        let mut agent = completion_agent();
+        agent.memory = Some(memory.clone());
+        agent
+            .llm_context
+            .add_message(LlmMessage::user_text(
+                "what is my dog called?",
+                AgentId::new(None),
+            ))
+            .await;
+
+        match agent.consult_memory().await {
+            Some(LlmMessage::SystemMessage(msg)) => {
+                assert!(msg
+                    .content
+                    .text
+                    .starts_with("Relevant memories:\n- the user's dog"))
+            }
+            other => panic!("expected recalled memories, got {:?}", other),
+        }
+        assert_eq!(memory.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_answers_are_remembered_across_turns() {
+        let server = MockLlmServer::start(vec![
+            MockReply::Embeddings(vec![vec![1.0, 0.0], vec![0.0, 1.0]]),
+            // First turn: look up, store the question, answer, store the answer.
+            MockReply::Embeddings(vec![vec![0.9, 0.1]]),
+            MockReply::Embeddings(vec![vec![0.9, 0.1]]),
+            MockReply::text("Your dog is called Rex."),
+            MockReply::Embeddings(vec![vec![0.8, 0.2]]),
+            // Second turn asks again; neither message is stored twice.
+            MockReply::Embeddings(vec![vec![0.9, 0.1]]),
+            MockReply::text("Your dog is called Rex."),
+        ])
+        .await
+        .unwrap();
+        let embedder = OpenAiEmbeddingClient::new(
+            server.config_for_path(&OPENAI_EMBEDDING_CONFIG, "/v1/embeddings"),
+        )
+        .unwrap();
+        let memory = Arc::new(VectorMemory::new(Arc::new(embedder), VectorStore::new()));
+        memory
+            .add_documents(&[
+                "the user's dog is called Rex".to_string(),
+                "unrelated".to_string(),
+            ])
+            .await
+            .unwrap();
+
+        let mut agent = completion_agent();
+        agent.model_client = LlmCompletionClient::new(server.config(&OPENAI_CONFIG), None);
+        agent.memory = Some(memory.clone());
+        for _ in 0..2 {
+            agent
+                .llm_context
+                .add_message(LlmMessage::user_text(
+                    "what is my dog called?",
+                    AgentId::new(None),
+                ))
+                .await;
+            agent.generate_response(ResponseFormat::Text, ctx()).await;
+        }
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 7);
+        let first_prompt = requests[3].body["messages"].to_string();
+        assert!(first_prompt.contains("Relevant memories:\\n- the user's dog is called Rex"));
+        let second_prompt = requests[6].body["messages"].to_string();
+        assert!(second_prompt.contains("- Your dog is called Rex."));
+        assert_eq!(memory.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_tool_loop_runs_every_call_until_text() {
+        let server = MockLlmServer::start(vec![
+            MockReply::ToolCalls(vec![
+                (
+                    "get_current_weather".to_string(),
+                    serde_json::json!({"location": "New York", "unit": "celsius"}),
+                ),
+                (
+                    "get_current_weather".to_string(),
+                    serde_json::json!({"location": "New Delhi", "unit": "celsius"}),
+                ),
+            ]),
+            MockReply::text("Both are warm."),
+        ])
+        .await
+        .unwrap();
+        let (tx, mut rx) = mpsc::channel(8);
+        let mut agent = completion_agent();
+        agent.model_client = LlmCompletionClient::new(server.config(&OPENAI_CONFIG), None);
+        agent.registered_tools = ToolRegistry::new().with_tool(get_current_weather_tool());
+        agent.observer = Some(tx);
+        agent
+            .llm_context
+            .add_message(LlmMessage::user_text(
+                "weather in New York and New Delhi?",
+                AgentId::new(None),
+            ))
+            .await;
+
+        let reply = agent.generate_response(ResponseFormat::Text, ctx()).await;
+
+        match reply {
+            ChatMessage::TextMessage(tm) => assert_eq!(tm.content.text, "Both are warm."),
+            other => panic!("expected the final answer, got {:?}", other),
+        }
+        match rx.recv().await {
+            Some(ChatMessage::ToolCallMessage(tcm)) => assert_eq!(tcm.content.content.len(), 2),
+            other => panic!("expected the tool calls, got {:?}", other),
+        }
+        match rx.recv().await {
+            Some(ChatMessage::ToolCallResultMessage(tcrm)) => {
+                assert_eq!(
+                    tcrm.content.content[1].content,
+                    "Weather for New Delhi in celsius"
+                )
+            }
+            other => panic!("expected the tool results, got {:?}", other),
+        }
+        // user, (call, result) x 2, answer
+        assert_eq!(agent.llm_context.messages.len(), 6);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[0].body["tools"][0]["function"]["name"],
+            "get_current_weather"
+        );
+        assert!(requests[1].body["messages"]
+            .to_string()
+            .contains("Weather for New York in celsius"));
+    }
+
+    #[tokio::test]
+    async fn test_tool_loop_stops_at_max_iterations() {
+        let call = || {
+            MockReply::tool_call(
+                "get_current_weather",
+                serde_json::json!({"location": "New York", "unit": "celsius"}),
+            )
+        };
+        let server = MockLlmServer::start(vec![call(), call(), call()])
+            .await
+            .unwrap();
+        let mut agent = completion_agent();
+        agent.model_client = LlmCompletionClient::new(server.config(&OPENAI_CONFIG), None);
+        agent.registered_tools = ToolRegistry::new().with_tool(get_current_weather_tool());
+        agent.max_iterations = 2;
+
+        let reply = agent.generate_response(ResponseFormat::Text, ctx()).await;
+
+        match reply {
+            ChatMessage::StopMessage(reason) => assert!(reason.contains("2 model calls")),
+            other => panic!("expected a stop message, got {:?}", other),
+        }
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_tool_failures_are_returned_to_the_model() {
+        let server = MockLlmServer::start(vec![
+            MockReply::ToolCalls(vec![
+                ("get_stock_price".to_string(), serde_json::json!({})),
+                (
+                    "get_current_weather".to_string(),
+                    serde_json::json!({"location": "Paris", "unit": "celsius"}),
+                ),
+                (
+                    "get_current_weather".to_string(),
+                    serde_json::json!({"location": "New York", "unit": 5}),
+                ),
+                (
+                    "get_current_weather".to_string(),
+                    serde_json::json!(r#"{"location": "Oslo", "unit": "#),
+                ),
+            ]),
+            MockReply::text("Sorry, I could not look that up."),
+        ])
+        .await
+        .unwrap();
+        let (tx, mut rx) = mpsc::channel(8);
+        let mut agent = completion_agent();
+        agent.model_client = LlmCompletionClient::new(server.config(&OPENAI_CONFIG), None);
+        agent.registered_tools = ToolRegistry::new().with_tool(get_current_weather_tool());
+        agent.observer = Some(tx);
+
+        let reply = agent.generate_response(ResponseFormat::Text, ctx()).await;
+
+        assert!(matches!(reply, ChatMessage::TextMessage(_)));
+        rx.recv().await;
+        let results = match rx.recv().await {
+            Some(ChatMessage::ToolCallResultMessage(tcrm)) => tcrm.content.content,
+            other => panic!("expected the tool results, got {:?}", other),
+        };
+        assert!(results.iter().all(|result| result.is_error));
+        assert_eq!(
+            results[0].content,
+            "Error: Unknown tool get_stock_price. Available tools: get_current_weather"
+        );
+        assert_eq!(results[1].content, "Error: Weather for Paris in celsius");
+        assert!(results[2]
+            .content
+            .contains(r#"- unit: 5 is not one of ["celsius","fahrenheit"]"#));
+        assert!(results[3]
+            .content
+            .starts_with("Error: arguments are not valid JSON: "));
+        let second_prompt = server.requests()[1].body["messages"].to_string();
+        assert!(second_prompt.contains("Unknown tool get_stock_price"));
+    }
+
+    #[tokio::test]
+    async fn test_tool_policy_gates_calls() {
+        let server = MockLlmServer::start(vec![
+            MockReply::ToolCalls(vec![(
+                "get_current_weather".to_string(),
+                serde_json::json!({"location": "Paris", "unit": "celsius"}),
+            )]),
+            MockReply::text("I was not allowed to check."),
+        ])
+        .await
+        .unwrap();
+        let (approver, mut requests) = Approver::channel(AgentId::new(Some("user")));
+        let mut agent = completion_agent();
+        agent.model_client = LlmCompletionClient::new(server.config(&OPENAI_CONFIG), None);
+        agent.registered_tools = ToolRegistry::new()
+            .with_tool(get_current_weather_tool())
+            .with_tool(define_tool());
+        agent.tool_policy = ToolPolicy::new(ToolPermission::RequireApproval)
+            .with_permission("define", ToolPermission::Deny)
+            .with_approver(approver);
+        tokio::spawn(async move {
+            while let Some(request) = requests.recv().await {
+                request.reject("not today");
+            }
+        });
+
+        agent.generate_response(ResponseFormat::Text, ctx()).await;
+
+        let requests = server.requests();
+        let offered = requests[0].body["tools"].to_string();
+        assert!(offered.contains("get_current_weather"));
+        assert!(!offered.contains("define"));
+        let second_prompt = requests[1].body["messages"].to_string();
+        assert!(second_prompt.contains("Tool get_current_weather was not approved: no: not today"));
+    }
+
+    #[tokio::test]
+    async fn test_agent_as_tool() {
+        let server = MockLlmServer::start(vec![MockReply::text("Paris.")])
+            .await
+            .unwrap();
+        let mut agent = completion_agent();
+        agent.model_client = LlmCompletionClient::new(server.config(&OPENAI_CONFIG), None);
+        let tool = agent.into_tool();
+        assert_eq!(tool.name, "assistant");
+
+        let answer = tool
+            .run(serde_json::json!({"task": "Capital of France?"}))
+            .await
+            .unwrap();
+        assert_eq!(answer.value, "Paris.");
+        assert!(server.requests()[0].body["messages"]
+            .to_string()
+            .contains("Capital of France?"));
+        assert!(tool.run(serde_json::json!({})).await.is_err());
+    }
+
+    type MyResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+    static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+    static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+    /// Looks a word up in a slow dictionary.
+    ///
+    /// # Arguments
+    /// * `word` - The word to define
+    #[create_tool_with_function]
+    async fn define(word: String) -> MyResult<String> {
+        let now = IN_FLIGHT.fetch_add(1, Ordering::SeqCst) + 1;
+        PEAK.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+        Ok(format!("{}: a word", word))
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_run_concurrently_up_to_the_limit() {
+        let calls = ["alpha", "beta", "gamma", "delta"]
+            .iter()
+            .enumerate()
+            .map(|(i, word)| FunctionCallInput {
+                id: format!("call_{}", i),
+                arguments_obj: serde_json::json!({ "word": word }),
+                function_name: "define".to_string(),
+                return_type: "".to_string(),
+            })
+            .collect::<Vec<_>>();
+        let mut agent = completion_agent();
+        agent.registered_tools = ToolRegistry::new().with_tool(define_tool());
+        agent.max_parallel_tools = 3;
+
+        let results = agent.run_tools(&calls).await;
+
+        assert_eq!(PEAK.load(Ordering::SeqCst), 3);
+        let ids = results
+            .iter()
+            .map(|r| r.call_id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["call_0", "call_1", "call_2", "call_3"]);
+        assert_eq!(results[3].content, "delta: a word");
+    }
+
+    #[derive(serde::Serialize)]
+    struct Chart {
+        city: String,
+        highs: Vec<i32>,
+    }
+
+    /// Plots the week's high temperatures for a city.
+    ///
+    /// # Arguments
+    /// * `city` - The city to plot
+    #[create_tool_with_function]
+    fn plot_highs(city: String) -> MyResult<ToolOutput> {
+        let png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        Ok(ToolOutput::new(Chart {
+            city,
+            highs: vec![12, 14],
+        })?
+        .with_image(ImageContent::from_bytes(png)?))
+    }
+
+    #[tokio::test]
+    async fn test_structured_tool_results_and_attachments() {
+        let call = || {
+            MockReply::ToolCalls(vec![(
+                "plot_highs".to_string(),
+                serde_json::json!({"city": "Lima"}),
+            )])
+        };
+        let server = MockLlmServer::start(vec![
+            call(),
+            MockReply::text("Warm."),
+            call(),
+            MockReply::text("Warm."),
+        ])
+        .await
+        .unwrap();
+
+        let mut agent = completion_agent();
+        agent.model_client = LlmCompletionClient::new(
+            server.config_for_path(&ANTHROPIC_CONFIG, "/v1/messages"),
+            None,
+        );
+        agent.registered_tools = ToolRegistry::new().with_tool(plot_highs_tool());
+        agent.generate_response(ResponseFormat::Text, ctx()).await;
+
+        let prompt = server.requests()[1].body["messages"].to_string();
+        assert!(
+            prompt.contains(r#"{\"city\":\"Lima\",\"highs\":[12,14]}\n[1 attachment(s) follow]"#)
+        );
+        assert!(prompt.contains(r#""media_type":"image/png""#));
+
+        let mut agent = completion_agent();
+        agent.model_client = LlmCompletionClient::new(server.config(&OPENAI_CONFIG), None);
+        agent.registered_tools = ToolRegistry::new().with_tool(plot_highs_tool());
+        agent.tool_render = ToolRenderMode::Summary { max_chars: 100 };
+        agent.generate_response(ResponseFormat::Text, ctx()).await;
+
+        let prompt = server.requests()[3].body["messages"].to_string();
+        assert!(prompt.contains(r#"city: Lima\nhighs: [12, 14]"#));
+        assert!(prompt.contains("returned an image this model cannot view"));
+        assert!(!prompt.contains("data:image/png;base64"));
+    }
+}
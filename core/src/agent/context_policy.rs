@@ -0,0 +1,370 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::agent::chat_agent::ResultContent;
+use crate::agent::llm_backend::{CreateRequest, LlmClient};
+use crate::msg_types::chat_msg_types::AssistantMessageContent;
+use crate::msg_types::{llm_msg_types::LlmMessage, AgentId, MultiModalContent};
+
+/// Rough cost of an image part; providers charge a few hundred tokens per tile.
+const IMAGE_TOKENS: usize = 765;
+
+const SUMMARY_PROMPT: &str = "Summarize the conversation below in a few sentences. \
+Keep names, decisions, numbers and open questions; drop pleasantries.";
+
+/// Decides which messages of an `LlmCompletionContext` are kept. Policies run each
+/// time a message is added and their output replaces the stored history, so a
+/// summary only has to be produced once.
+#[async_trait]
+pub trait ContextPolicy: Send + Sync + std::fmt::Debug {
+    async fn apply(&self, messages: Vec<LlmMessage>) -> anyhow::Result<Vec<LlmMessage>>;
+}
+
+/// Characters / 4 plus a small per-message overhead; good enough for budgeting
+/// without a tokenizer.
+pub fn estimate_tokens(message: &LlmMessage) -> usize {
+    let text_tokens = |text: &str| text.chars().count().div_ceil(4);
+    4 + match message {
+        LlmMessage::SystemMessage(msg) => text_tokens(&msg.content.text),
+        LlmMessage::UserMessage(msg) => msg
+            .content
+            .iter()
+            .map(|part| match part {
+                MultiModalContent::Text(text) => text_tokens(&text.text),
+                MultiModalContent::Image(_) => IMAGE_TOKENS,
+            })
+            .sum(),
+        LlmMessage::AssistantMessage(msg) => match &msg.content {
+            AssistantMessageContent::TextContent(text) => text_tokens(&text.text),
+            AssistantMessageContent::FunctionCallInput(call) => {
+                text_tokens(&call.function_name) + text_tokens(&call.arguments_obj.to_string())
+            }
+        },
+        LlmMessage::FunctionExecutionResultMessage(msg) => msg
+            .content
+            .iter()
+            .map(|result| text_tokens(&result.content))
+            .sum(),
+    }
+}
+
+/// Tool results are only valid right after the assistant turn that requested
+/// them, so a trimmed history must not start with one.
+fn drop_orphaned_results(mut messages: Vec<LlmMessage>) -> Vec<LlmMessage> {
+    let orphans = messages
+        .iter()
+        .take_while(|msg| matches!(msg, LlmMessage::FunctionExecutionResultMessage(_)))
+        .count();
+    messages.drain(..orphans);
+    messages
+}
+
+/// The unbounded default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KeepAll;
+
+#[async_trait]
+impl ContextPolicy for KeepAll {
+    async fn apply(&self, messages: Vec<LlmMessage>) -> anyhow::Result<Vec<LlmMessage>> {
+        Ok(messages)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LastN(pub usize);
+
+#[async_trait]
+impl ContextPolicy for LastN {
+    async fn apply(&self, mut messages: Vec<LlmMessage>) -> anyhow::Result<Vec<LlmMessage>> {
+        let excess = messages.len().saturating_sub(self.0);
+        messages.drain(..excess);
+        Ok(drop_orphaned_results(messages))
+    }
+}
+
+/// Drops the oldest messages until the estimated total fits `max_tokens`. The
+/// newest message is always kept, even if it alone is over budget.
+#[derive(Debug, Clone, Copy)]
+pub struct TokenBudget {
+    pub max_tokens: usize,
+}
+
+#[async_trait]
+impl ContextPolicy for TokenBudget {
+    async fn apply(&self, messages: Vec<LlmMessage>) -> anyhow::Result<Vec<LlmMessage>> {
+        let mut total = 0;
+        let mut keep = 0;
+        for message in messages.iter().rev() {
+            total += estimate_tokens(message);
+            if keep > 0 && total > self.max_tokens {
+                break;
+            }
+            keep += 1;
+        }
+        let mut messages = messages;
+        messages.drain(..messages.len() - keep);
+        Ok(drop_orphaned_results(messages))
+    }
+}
+
+/// Wraps another policy so that system messages and the first user message (which
+/// usually states the task) survive whatever `inner` trims.
+#[derive(Debug)]
+pub struct KeepPinned {
+    pub inner: Box<dyn ContextPolicy>,
+}
+
+#[async_trait]
+impl ContextPolicy for KeepPinned {
+    async fn apply(&self, messages: Vec<LlmMessage>) -> anyhow::Result<Vec<LlmMessage>> {
+        let first_user = messages
+            .iter()
+            .position(|msg| matches!(msg, LlmMessage::UserMessage(_)));
+        let (pinned, rest): (Vec<_>, Vec<_>) =
+            messages.into_iter().enumerate().partition(|(i, msg)| {
+                Some(*i) == first_user || matches!(msg, LlmMessage::SystemMessage(_))
+            });
+
+        let mut kept = pinned.into_iter().map(|(_, msg)| msg).collect::<Vec<_>>();
+        kept.extend(
+            self.inner
+                .apply(rest.into_iter().map(|(_, msg)| msg).collect())
+                .await?,
+        );
+        Ok(kept)
+    }
+}
+
+/// Once the history grows past `trigger` messages, everything but the last
+/// `keep_last` is replaced by a single system message summarizing it, written by
+/// `llm`. The latest user turn is always kept, however small `keep_last` is.
+/// Earlier summaries are folded into the next one.
+pub struct Summarize {
+    pub llm: Arc<dyn LlmClient>,
+    pub trigger: usize,
+    pub keep_last: usize,
+    pub max_tokens: u16,
+}
+
+impl std::fmt::Debug for Summarize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Summarize")
+            .field("model", &self.llm.llm_config().model)
+            .field("trigger", &self.trigger)
+            .field("keep_last", &self.keep_last)
+            .finish()
+    }
+}
+
+impl Summarize {
+    pub fn new(llm: Arc<dyn LlmClient>, trigger: usize, keep_last: usize) -> Self {
+        Summarize {
+            llm,
+            trigger,
+            keep_last,
+            max_tokens: 500,
+        }
+    }
+}
+
+fn transcript_line(message: &LlmMessage) -> String {
+    match message {
+        LlmMessage::SystemMessage(msg) => format!("system: {}", msg.content.text),
+        LlmMessage::UserMessage(msg) => {
+            let parts = msg
+                .content
+                .iter()
+                .map(|part| match part {
+                    MultiModalContent::Text(text) => text.text.clone(),
+                    MultiModalContent::Image(_) => "[image]".to_string(),
+                })
+                .collect::<Vec<String>>();
+            format!("user: {}", parts.join(" "))
+        }
+        LlmMessage::AssistantMessage(msg) => match &msg.content {
+            AssistantMessageContent::TextContent(text) => format!("assistant: {}", text.text),
+            AssistantMessageContent::FunctionCallInput(call) => format!(
+                "assistant called {}({})",
+                call.function_name, call.arguments_obj
+            ),
+        },
+        LlmMessage::FunctionExecutionResultMessage(msg) => {
+            let results = msg
+                .content
+                .iter()
+                .map(|result| result.content.clone())
+                .collect::<Vec<String>>();
+            format!("tool result: {}", results.join(", "))
+        }
+    }
+}
+
+#[async_trait]
+impl ContextPolicy for Summarize {
+    async fn apply(&self, mut messages: Vec<LlmMessage>) -> anyhow::Result<Vec<LlmMessage>> {
+        if messages.len() <= self.trigger {
+            return Ok(messages);
+        }
+        // This runs inside the tool loop too, so the turn being answered (the last
+        // user message and everything after it) is always kept.
+        let mut split = messages.len().saturating_sub(self.keep_last.max(1));
+        if let Some(last_user) = messages
+            .iter()
+            .rposition(|msg| matches!(msg, LlmMessage::UserMessage(_)))
+        {
+            split = split.min(last_user);
+        }
+        // Keep a tool call together with its results rather than cutting between them.
+        while split > 0
+            && matches!(
+                messages[split],
+                LlmMessage::FunctionExecutionResultMessage(_)
+            )
+        {
+            split -= 1;
+        }
+        if split == 0 {
+            return Ok(messages);
+        }
+        let recent = messages.split_off(split);
+
+        let transcript = messages
+            .iter()
+            .map(transcript_line)
+            .collect::<Vec<String>>()
+            .join("\n");
+        let source = AgentId::new(Some("summarizer"));
+        let request = CreateRequest::new(
+            vec![
+                LlmMessage::system(SUMMARY_PROMPT, source.clone()),
+                LlmMessage::user_text(transcript, source.clone()),
+            ],
+            self.max_tokens,
+        );
+        let summary = match self.llm.create(&request).await?.content {
+            ResultContent::TextContent(text) => text.text,
+            other => return Err(anyhow::anyhow!("Expected a text summary, got {:?}", other)),
+        };
+
+        let mut compacted = vec![LlmMessage::system(
+            format!("Summary of the earlier conversation: {}", summary),
+            source,
+        )];
+        compacted.extend(recent);
+        Ok(compacted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::llm_backend::mock_server::{MockLlmServer, MockReply};
+    use crate::agent::llm_backend::openai::OpenAiClient;
+    use crate::agent::llm_backend::OPENAI_CONFIG;
+    use crate::tool_types::FunctionCallInput;
+
+    fn conversation() -> Vec<LlmMessage> {
+        let source = AgentId::new(None);
+        let call = FunctionCallInput {
+            id: "call_0".to_string(),
+            function_name: "get_time".to_string(),
+            arguments_obj: serde_json::json!({}),
+            return_type: "".to_string(),
+        };
+        vec![
+            LlmMessage::system("be brief", source.clone()),
+            LlmMessage::user_text("plan a trip to Paris", source.clone()),
+            LlmMessage::assistant_text("when?", source.clone()),
+            LlmMessage::user_text("in May", source.clone()),
+            LlmMessage::assistant_function_run(call, source.clone()),
+            LlmMessage::function_result("12:00", "call_0", source.clone()),
+            LlmMessage::assistant_text("booked", source),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_trimming_never_starts_with_a_tool_result() {
+        let kept = LastN(2).apply(conversation()).await.unwrap();
+        assert_eq!(kept.len(), 1);
+
+        let budget = TokenBudget { max_tokens: 20 }
+            .apply(conversation())
+            .await
+            .unwrap();
+        assert!(!matches!(
+            budget[0],
+            LlmMessage::FunctionExecutionResultMessage(_)
+        ));
+        assert!(budget.iter().map(estimate_tokens).sum::<usize>() <= 20);
+    }
+
+    #[tokio::test]
+    async fn test_pinned_keeps_system_and_first_user_message() {
+        let policy = KeepPinned {
+            inner: Box::new(LastN(1)),
+        };
+        let kept = policy.apply(conversation()).await.unwrap();
+
+        assert_eq!(kept.len(), 3);
+        assert!(matches!(kept[0], LlmMessage::SystemMessage(_)));
+        assert!(format!("{:?}", kept[1]).contains("plan a trip to Paris"));
+        assert!(format!("{:?}", kept[2]).contains("booked"));
+    }
+
+    #[tokio::test]
+    async fn test_summarize_compacts_old_turns() {
+        let server = MockLlmServer::start(vec![MockReply::text("Trip to Paris in May.")])
+            .await
+            .unwrap();
+        let policy = Summarize::new(
+            Arc::new(OpenAiClient::new(server.config(&OPENAI_CONFIG))),
+            5,
+            2,
+        );
+
+        let compacted = policy.apply(conversation()).await.unwrap();
+
+        // The last user turn is kept whole, past `keep_last`.
+        assert_eq!(compacted.len(), 5);
+        match &compacted[0] {
+            LlmMessage::SystemMessage(msg) => {
+                assert!(msg.content.text.ends_with("Trip to Paris in May."))
+            }
+            other => panic!("expected a summary, got {:?}", other),
+        }
+        let transcript = server.requests()[0].body["messages"][1]["content"].to_string();
+        assert!(transcript.contains("assistant: when?"));
+        assert!(!transcript.contains("in May"));
+    }
+
+    #[tokio::test]
+    async fn test_summarize_mid_tool_loop_keeps_the_current_turn() {
+        let server = MockLlmServer::start(vec![MockReply::text("Planning Paris.")])
+            .await
+            .unwrap();
+        let policy = Summarize::new(
+            Arc::new(OpenAiClient::new(server.config(&OPENAI_CONFIG))),
+            3,
+            0,
+        );
+        // The agent has just added a tool result and is about to call the model.
+        let mut messages = conversation();
+        messages.pop();
+
+        let compacted = policy.apply(messages).await.unwrap();
+
+        assert_eq!(compacted.len(), 4);
+        assert!(matches!(compacted[0], LlmMessage::SystemMessage(_)));
+        assert!(format!("{:?}", compacted[1]).contains("in May"));
+        assert!(matches!(
+            compacted[3],
+            LlmMessage::FunctionExecutionResultMessage(_)
+        ));
+
+        // Nothing before the current turn is left, so there is nothing to summarize.
+        let again = policy.apply(compacted[1..].to_vec()).await.unwrap();
+        assert_eq!(again.len(), 3);
+        assert_eq!(server.requests().len(), 1);
+    }
+}
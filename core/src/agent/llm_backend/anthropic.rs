@@ -0,0 +1,369 @@
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::agent::chat_agent::{CreateResult, ResultContent};
+use crate::agent::llm_backend::http::LlmHttpClient;
+use crate::agent::llm_backend::{arguments_object, CreateRequest, LlmClient, LlmConfig};
+use crate::msg_types::chat_msg_types::AssistantMessageContent;
+use crate::msg_types::{llm_msg_types::LlmMessage, FinishReason, MultiModalContent, RequestUsage};
+use crate::tool_types::FunctionCallInput;
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Client for the Anthropic Messages API (`/v1/messages`). The system prompt is a
+/// top-level field, every message carries a list of content blocks, and tool calls
+/// are `tool_use` blocks answered by `tool_result` blocks in the next user turn.
+#[derive(Clone)]
+pub struct AnthropicClient {
+    pub llm_config: LlmConfig,
+    http: LlmHttpClient,
+}
+
+impl AnthropicClient {
+    pub fn new(llm_config: LlmConfig) -> Self {
+        AnthropicClient {
+            llm_config,
+            http: LlmHttpClient::shared(),
+        }
+    }
+
+    pub fn with_http(llm_config: LlmConfig, http: LlmHttpClient) -> Self {
+        AnthropicClient { llm_config, http }
+    }
+}
+
+#[async_trait]
+impl LlmClient for AnthropicClient {
+    fn llm_config(&self) -> &LlmConfig {
+        &self.llm_config
+    }
+
+    async fn create(&self, request: &CreateRequest) -> anyhow::Result<CreateResult> {
+        let headers = anthropic_headers(&self.llm_config)?;
+        let body_json = anthropic_request_body(&self.llm_config, request);
+
+        let response_body = self
+            .http
+            .post_json(self.llm_config.base_url, headers, &body_json)
+            .await?;
+
+        parse_anthropic_response(&response_body, &self.llm_config)
+    }
+}
+
+/// Anthropic authenticates with `x-api-key` rather than a bearer token.
+pub fn anthropic_headers(llm_config: &LlmConfig) -> anyhow::Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    headers.insert(
+        "anthropic-version",
+        HeaderValue::from_static(ANTHROPIC_VERSION),
+    );
+    if !llm_config.api_key_str.is_empty() {
+        let api_key = std::env::var(llm_config.api_key_str)?;
+        headers.insert("x-api-key", HeaderValue::from_str(&api_key)?);
+    }
+    Ok(headers)
+}
+
+pub fn anthropic_request_body(llm_config: &LlmConfig, request: &CreateRequest) -> Value {
+    let (system, messages) = anthropic_messages(&request.messages);
+    let mut body_json = json!({
+        "model": llm_config.model,
+        "messages": messages,
+        "max_tokens": request.max_tokens,
+        "temperature": 0.3
+    });
+
+    // No JSON mode in the Messages API; ask for it in the system prompt instead.
+    let system = match (system, request.json_output) {
+        (Some(system), true) => Some(format!("{}\n\nRespond with a single JSON object.", system)),
+        (None, true) => Some("Respond with a single JSON object.".to_string()),
+        (system, false) => system,
+    };
+    if let Some(system) = system {
+        body_json["system"] = json!(system);
+    }
+    if !request.tools.is_empty() {
+        body_json["tools"] = request.tools.iter().map(anthropic_tool).collect();
+    }
+    for (key, value) in &request.extra_create_args {
+        body_json[key] = value.clone();
+    }
+    body_json
+}
+
+/// Converts an OpenAI-style function definition (`name`, `description`,
+/// `parameters`) into an Anthropic tool (`input_schema` instead of `parameters`).
+fn anthropic_tool(tool: &Value) -> Value {
+    json!({
+        "name": tool["name"],
+        "description": tool["description"].as_str().unwrap_or_default(),
+        "input_schema": match &tool["parameters"] {
+            Value::Null => json!({"type": "object", "properties": {}}),
+            parameters => parameters.clone(),
+        }
+    })
+}
+
+/// Splits the conversation into the system prompt and the `messages` array.
+/// Consecutive turns of the same role are merged into one message, since the API
+/// requires user and assistant turns to alternate.
+pub fn anthropic_messages(messages: &[LlmMessage]) -> (Option<String>, Vec<Value>) {
+    let mut system: Vec<&str> = Vec::new();
+    let mut out: Vec<Value> = Vec::new();
+
+    let mut push = |role: &str, blocks: Vec<Value>| match out.last_mut() {
+        Some(last) if last["role"] == role => {
+            if let Some(content) = last["content"].as_array_mut() {
+                content.extend(blocks);
+            }
+        }
+        _ => out.push(json!({"role": role, "content": blocks})),
+    };
+
+    for message in messages {
+        match message {
+            LlmMessage::SystemMessage(msg) => system.push(&msg.content.text),
+            LlmMessage::UserMessage(msg) => push(
+                "user",
+                msg.content
+                    .iter()
+                    .map(|part| match part {
+                        MultiModalContent::Text(text) => json!({"type": "text", "text": text.text}),
+                        MultiModalContent::Image(image) => json!({
+                            "type": "image",
+                            "source": {
+                                "type": "base64",
+                                "media_type": image.mime_type,
+                                "data": image.to_base64()
+                            }
+                        }),
+                    })
+                    .collect(),
+            ),
+            LlmMessage::AssistantMessage(msg) => match &msg.content {
+                AssistantMessageContent::TextContent(text) => {
+                    push(
+                        "assistant",
+                        vec![json!({"type": "text", "text": text.text})],
+                    );
+                }
+                AssistantMessageContent::FunctionCallInput(call) => push(
+                    "assistant",
+                    vec![json!({
+                        "type": "tool_use",
+                        "id": call.id,
+                        "name": call.function_name,
+                        "input": arguments_object(&call.arguments_obj)
+                    })],
+                ),
+            },
+            LlmMessage::FunctionExecutionResultMessage(msg) => push(
+                "user",
+                msg.content
+                    .iter()
+                    .map(|result| {
+                        json!({
+                            "type": "tool_result",
+                            "tool_use_id": result.call_id,
+                            "content": result.content,
+                            "is_error": result.is_error
+                        })
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    let system = if system.is_empty() {
+        None
+    } else {
+        Some(system.join("\n\n"))
+    };
+    (system, out)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnthropicResponse {
+    pub content: Vec<AnthropicContentBlock>,
+    pub stop_reason: Option<String>,
+    pub usage: Option<AnthropicUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnthropicUsage {
+    pub input_tokens: i32,
+    pub output_tokens: i32,
+    #[serde(default)]
+    pub cache_creation_input_tokens: Option<i32>,
+    #[serde(default)]
+    pub cache_read_input_tokens: Option<i32>,
+}
+
+impl From<AnthropicUsage> for RequestUsage {
+    fn from(usage: AnthropicUsage) -> Self {
+        // `input_tokens` only counts the uncached part of the prompt.
+        let cache_creation_tokens = usage.cache_creation_input_tokens.unwrap_or(0);
+        let cache_read_tokens = usage.cache_read_input_tokens.unwrap_or(0);
+        RequestUsage {
+            prompt_tokens: usage.input_tokens + cache_creation_tokens + cache_read_tokens,
+            completion_tokens: usage.output_tokens,
+            cache_creation_tokens,
+            cache_read_tokens,
+        }
+    }
+}
+
+pub fn parse_anthropic_response(
+    response_body: &str,
+    llm_config: &LlmConfig,
+) -> anyhow::Result<CreateResult> {
+    let raw_output = serde_json::from_str::<AnthropicResponse>(response_body)?;
+    let usage = raw_output.usage.map(RequestUsage::from).unwrap_or_default();
+
+    let mut text = Vec::new();
+    let mut function_calls = Vec::new();
+    for block in raw_output.content {
+        match block {
+            AnthropicContentBlock::Text { text: t } => text.push(t),
+            AnthropicContentBlock::ToolUse { id, name, input } => {
+                function_calls.push(FunctionCallInput {
+                    id,
+                    function_name: name,
+                    arguments_obj: input,
+                    return_type: "".to_string(),
+                });
+            }
+            AnthropicContentBlock::Other => {}
+        }
+    }
+
+    let (content, finish_reason) = if function_calls.is_empty() {
+        (
+            ResultContent::TextContent(text.join("").into()),
+            FinishReason::from_api(raw_output.stop_reason.as_deref().unwrap_or("end_turn")),
+        )
+    } else {
+        (
+            ResultContent::FunctionCallContent(function_calls),
+            FinishReason::FunctionCall,
+        )
+    };
+
+    Ok(CreateResult {
+        finish_reason,
+        content,
+        usage,
+        served_by: llm_config.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::llm_backend::mock_server::{MockLlmServer, MockReply};
+    use crate::agent::llm_backend::ANTHROPIC_CONFIG;
+    use crate::msg_types::{AgentId, ImageContent};
+
+    #[test]
+    fn test_anthropic_messages_pairs_tool_use_and_result() {
+        let image = ImageContent::from_bytes(vec![0xFF, 0xD8, 0xFF, 0xE0]).unwrap();
+        let source = AgentId::new(None);
+        let call = FunctionCallInput {
+            id: "toolu_1".to_string(),
+            function_name: "get_current_weather".to_string(),
+            arguments_obj: json!({"location": "Paris"}),
+            return_type: "".to_string(),
+        };
+        let messages = vec![
+            LlmMessage::system("be brief", source.clone()),
+            LlmMessage::user_text("what is this?", source.clone()),
+            LlmMessage::user_image(image.clone(), source.clone()),
+            LlmMessage::assistant_function_run(call, source.clone()),
+            LlmMessage::function_result("sunny", "toolu_1", source),
+        ];
+
+        let (system, out) = anthropic_messages(&messages);
+
+        assert_eq!(system.as_deref(), Some("be brief"));
+        assert_eq!(out.len(), 3);
+        assert_eq!(out[0]["content"][1]["type"], "image");
+        assert_eq!(out[0]["content"][1]["source"]["media_type"], "image/jpeg");
+        assert_eq!(out[1]["content"][0]["type"], "tool_use");
+        assert_eq!(out[1]["content"][0]["input"]["location"], "Paris");
+        assert_eq!(out[2]["role"], "user");
+        assert_eq!(out[2]["content"][0]["tool_use_id"], "toolu_1");
+
+        // Arguments another backend could not parse still go out as an object.
+        let malformed = FunctionCallInput {
+            id: "call_2".to_string(),
+            function_name: "get_current_weather".to_string(),
+            arguments_obj: json!(r#"{"location": "#),
+            return_type: "".to_string(),
+        };
+        let (_, out) = anthropic_messages(&[LlmMessage::assistant_function_run(
+            malformed,
+            AgentId::new(None),
+        )]);
+        assert_eq!(
+            out[0]["content"][0]["input"],
+            json!({"_raw": r#"{"location": "#})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_client_tool_call_with_cache_usage() {
+        let server = MockLlmServer::start(vec![MockReply::tool_call(
+            "get_current_weather",
+            json!({"location": "Paris"}),
+        )])
+        .await
+        .unwrap();
+        let client =
+            AnthropicClient::new(server.config_for_path(&ANTHROPIC_CONFIG, "/v1/messages"));
+
+        let mut request = CreateRequest::new(
+            vec![
+                LlmMessage::system("you check the weather", AgentId::new(None)),
+                LlmMessage::user_text("Weather in Paris?", AgentId::new(None)),
+            ],
+            100,
+        );
+        request.tools = vec![json!({"name": "get_current_weather", "parameters": {}})];
+        let result = client.create(&request).await.unwrap();
+
+        match result.content {
+            ResultContent::FunctionCallContent(calls) => {
+                let call = &calls[0];
+                assert_eq!(call.id, "toolu_0");
+                assert_eq!(call.arguments_obj["location"], "Paris");
+            }
+            other => panic!("expected a function call, got {:?}", other),
+        }
+        assert_eq!(result.usage.cache_read_tokens, 4);
+        assert_eq!(result.usage.prompt_tokens, 14);
+
+        let sent = &server.requests()[0].body;
+        assert_eq!(sent["system"], "you check the weather");
+        assert_eq!(sent["messages"][0]["role"], "user");
+        assert!(sent["tools"][0]["input_schema"].is_object());
+    }
+}
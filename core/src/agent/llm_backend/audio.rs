@@ -0,0 +1,198 @@
+use async_trait::async_trait;
+use reqwest::multipart::{Form, Part};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::agent::llm_backend::http::{bearer_headers, LlmHttpClient};
+use crate::agent::llm_backend::{AgentCapability, LlmConfig};
+use crate::msg_types::AudioContent;
+
+/// Speech-to-text backends, held by agents as `Arc<dyn TranscriptionClient>`.
+#[async_trait]
+pub trait TranscriptionClient: Send + Sync {
+    fn llm_config(&self) -> &LlmConfig;
+
+    async fn transcribe(&self, audio: &AudioContent) -> anyhow::Result<String>;
+}
+
+/// Text-to-speech backends, held by agents as `Arc<dyn SpeechClient>`.
+#[async_trait]
+pub trait SpeechClient: Send + Sync {
+    fn llm_config(&self) -> &LlmConfig;
+
+    async fn synthesize(&self, text: &str) -> anyhow::Result<AudioContent>;
+}
+
+fn require_audio(llm_config: &LlmConfig) -> anyhow::Result<()> {
+    if !llm_config.supports(&AgentCapability::Audio) {
+        return Err(anyhow::anyhow!(
+            "Model {} does not support audio",
+            llm_config.model
+        ));
+    }
+    Ok(())
+}
+
+/// Client for OpenAI-compatible `/v1/audio/transcriptions` endpoints, which take
+/// the clip as a multipart file upload.
+#[derive(Clone)]
+pub struct OpenAiTranscriptionClient {
+    pub llm_config: LlmConfig,
+    /// ISO-639-1 hint; improves accuracy and latency when the language is known.
+    pub language: Option<String>,
+    http: LlmHttpClient,
+}
+
+impl OpenAiTranscriptionClient {
+    pub fn new(llm_config: LlmConfig) -> anyhow::Result<Self> {
+        require_audio(&llm_config)?;
+        Ok(OpenAiTranscriptionClient {
+            llm_config,
+            language: None,
+            http: LlmHttpClient::shared(),
+        })
+    }
+
+    pub fn with_http(llm_config: LlmConfig, http: LlmHttpClient) -> Self {
+        OpenAiTranscriptionClient {
+            llm_config,
+            language: None,
+            http,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TranscriptionResponse {
+    text: String,
+}
+
+#[async_trait]
+impl TranscriptionClient for OpenAiTranscriptionClient {
+    fn llm_config(&self) -> &LlmConfig {
+        &self.llm_config
+    }
+
+    async fn transcribe(&self, audio: &AudioContent) -> anyhow::Result<String> {
+        let headers = bearer_headers(&self.llm_config)?;
+        // Checked once here so rebuilding the form on a retry cannot fail.
+        Part::bytes(Vec::new()).mime_str(&audio.mime_type)?;
+        let build_form = || {
+            let file = Part::bytes(audio.data.clone())
+                .file_name(format!("audio.{}", audio.extension()))
+                .mime_str(&audio.mime_type)
+                .expect("MIME type checked above");
+            let form = Form::new()
+                .text("model", self.llm_config.model)
+                .text("response_format", "json")
+                .part("file", file);
+            match &self.language {
+                Some(language) => form.text("language", language.clone()),
+                None => form,
+            }
+        };
+
+        let response = self
+            .http
+            .post_multipart(self.llm_config.base_url, headers, build_form)
+            .await?;
+        let response = response.json::<TranscriptionResponse>().await?;
+        Ok(response.text)
+    }
+}
+
+/// Client for OpenAI-compatible `/v1/audio/speech` endpoints, which answer with
+/// the encoded audio itself rather than JSON.
+#[derive(Clone)]
+pub struct OpenAiSpeechClient {
+    pub llm_config: LlmConfig,
+    pub voice: String,
+    http: LlmHttpClient,
+}
+
+impl OpenAiSpeechClient {
+    pub fn new(llm_config: LlmConfig, voice: impl Into<String>) -> anyhow::Result<Self> {
+        require_audio(&llm_config)?;
+        Ok(OpenAiSpeechClient {
+            llm_config,
+            voice: voice.into(),
+            http: LlmHttpClient::shared(),
+        })
+    }
+
+    pub fn with_http(llm_config: LlmConfig, voice: impl Into<String>, http: LlmHttpClient) -> Self {
+        OpenAiSpeechClient {
+            llm_config,
+            voice: voice.into(),
+            http,
+        }
+    }
+}
+
+#[async_trait]
+impl SpeechClient for OpenAiSpeechClient {
+    fn llm_config(&self) -> &LlmConfig {
+        &self.llm_config
+    }
+
+    async fn synthesize(&self, text: &str) -> anyhow::Result<AudioContent> {
+        let headers = bearer_headers(&self.llm_config)?;
+        let body_json = json!({
+            "model": self.llm_config.model,
+            "input": text,
+            "voice": self.voice,
+            "response_format": "mp3"
+        });
+
+        let response = self
+            .http
+            .post_with_retry(self.llm_config.base_url, headers, &body_json)
+            .await?;
+        let data = response.bytes().await?.to_vec();
+        AudioContent::from_bytes(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::llm_backend::mock_server::{MockLlmServer, MockReply};
+    use crate::agent::llm_backend::{OPENAI_CONFIG, TTS_CONFIG, WHISPER_CONFIG};
+
+    #[tokio::test]
+    async fn test_transcribe_uploads_multipart_file() {
+        let server = MockLlmServer::start(vec![MockReply::text("hello there")])
+            .await
+            .unwrap();
+        let client = OpenAiTranscriptionClient::new(
+            server.config_for_path(&WHISPER_CONFIG, "/v1/audio/transcriptions"),
+        )
+        .unwrap();
+        let audio = AudioContent::from_bytes(b"RIFF\x24\x00\x00\x00WAVEfmt ".to_vec()).unwrap();
+
+        let text = client.transcribe(&audio).await.unwrap();
+
+        assert_eq!(text, "hello there");
+        let sent = server.requests()[0].body.as_str().unwrap().to_string();
+        assert!(sent.contains("filename=\"audio.wav\""));
+        assert!(sent.contains("whisper-1"));
+    }
+
+    #[tokio::test]
+    async fn test_synthesize_returns_audio() {
+        let server = MockLlmServer::start(vec![MockReply::Audio(b"ID3\x04\x00".to_vec())])
+            .await
+            .unwrap();
+        let client = OpenAiSpeechClient::new(
+            server.config_for_path(&TTS_CONFIG, "/v1/audio/speech"),
+            "alloy",
+        )
+        .unwrap();
+
+        let audio = client.synthesize("hello there").await.unwrap();
+
+        assert_eq!(audio.mime_type, "audio/mpeg");
+        assert_eq!(server.requests()[0].body["voice"], "alloy");
+        assert!(OpenAiSpeechClient::new(OPENAI_CONFIG, "alloy").is_err());
+    }
+}
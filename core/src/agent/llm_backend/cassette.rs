@@ -0,0 +1,409 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::agent::chat_agent::{CreateResult, ResultContent};
+use crate::agent::llm_backend::openai::openai_messages;
+use crate::agent::llm_backend::{CreateRequest, LlmClient, LlmConfig};
+use crate::msg_types::{FinishReason, ImageContent, MultiModalContent, RequestUsage};
+use crate::tool_types::FunctionCallInput;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CassetteMode {
+    /// Always call the backend and save every request/response pair.
+    Record,
+    /// Serve answers from the cassette only; a miss is an error.
+    Replay,
+    /// Serve hits from the cassette and record misses: a dev-time response cache.
+    ReplayOrRecord,
+}
+
+#[derive(Debug)]
+pub struct CassetteMiss {
+    pub key: String,
+    pub path: PathBuf,
+}
+
+impl std::fmt::Display for CassetteMiss {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "no recorded response for request {} in cassette {}",
+            self.key,
+            self.path.display()
+        )
+    }
+}
+
+impl Error for CassetteMiss {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CassetteEntry {
+    /// The normalized request, kept so cassette diffs are readable.
+    pub request: Value,
+    pub response: CassetteResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CassetteResponse {
+    pub finish_reason: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// An image reply, as a data URI.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Set when the reply was `ResultContent::MultiModalContent`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub multimodal: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub function_calls: Vec<FunctionCallInput>,
+    pub prompt_tokens: i32,
+    pub completion_tokens: i32,
+    #[serde(default)]
+    pub cache_creation_tokens: i32,
+    #[serde(default)]
+    pub cache_read_tokens: i32,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CassetteFile {
+    entries: BTreeMap<String, CassetteEntry>,
+}
+
+/// Caching layer in front of any `LlmClient`. Requests are keyed by the SHA-256
+/// of their normalized form (model, OpenAI-style messages, tools and options,
+/// with object keys sorted), so the same conversation always hits the same entry.
+pub struct CassetteClient {
+    inner: Option<Arc<dyn LlmClient>>,
+    llm_config: LlmConfig,
+    mode: CassetteMode,
+    path: PathBuf,
+    entries: Mutex<BTreeMap<String, CassetteEntry>>,
+}
+
+impl CassetteClient {
+    pub fn record(inner: Arc<dyn LlmClient>, path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::open(Some(inner), None, CassetteMode::Record, path)
+    }
+
+    pub fn replay(llm_config: LlmConfig, path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        if !path.as_ref().exists() {
+            return Err(anyhow::anyhow!(
+                "cassette {} does not exist",
+                path.as_ref().display()
+            ));
+        }
+        Self::open(None, Some(llm_config), CassetteMode::Replay, path)
+    }
+
+    pub fn replay_or_record(
+        inner: Arc<dyn LlmClient>,
+        path: impl AsRef<Path>,
+    ) -> anyhow::Result<Self> {
+        Self::open(Some(inner), None, CassetteMode::ReplayOrRecord, path)
+    }
+
+    fn open(
+        inner: Option<Arc<dyn LlmClient>>,
+        llm_config: Option<LlmConfig>,
+        mode: CassetteMode,
+        path: impl AsRef<Path>,
+    ) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let entries = if path.exists() {
+            let raw = std::fs::read_to_string(&path)?;
+            serde_json::from_str::<CassetteFile>(&raw)?.entries
+        } else {
+            BTreeMap::new()
+        };
+        let llm_config = match (&inner, llm_config) {
+            (_, Some(config)) => config,
+            (Some(inner), None) => inner.llm_config().clone(),
+            (None, None) => unreachable!("a cassette needs a backend or a config"),
+        };
+
+        Ok(CassetteClient {
+            inner,
+            llm_config,
+            mode,
+            path,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn normalize_request(&self, request: &CreateRequest) -> Value {
+        canonicalize(&json!({
+            "model": self.llm_config.model,
+            "messages": openai_messages(&request.messages),
+            "tools": request.tools,
+            "json_output": request.json_output,
+            "max_tokens": request.max_tokens,
+            "extra_create_args": request.extra_create_args,
+        }))
+    }
+
+    pub fn request_key(&self, request: &CreateRequest) -> String {
+        let normalized = self.normalize_request(request);
+        Sha256::digest(normalized.to_string().as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    async fn record_call(
+        &self,
+        key: String,
+        request: &CreateRequest,
+    ) -> anyhow::Result<CreateResult> {
+        let inner = self
+            .inner
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("cassette has no backend to record from"))?;
+        let result = inner.create(request).await?;
+
+        let entry = CassetteEntry {
+            request: self.normalize_request(request),
+            response: to_cassette_response(&result),
+        };
+        self.entries.lock().unwrap().insert(key, entry);
+        self.save()?;
+        Ok(result)
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let file = CassetteFile {
+            entries: self.entries.lock().unwrap().clone(),
+        };
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&file)?)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LlmClient for CassetteClient {
+    fn llm_config(&self) -> &LlmConfig {
+        &self.llm_config
+    }
+
+    async fn create(&self, request: &CreateRequest) -> anyhow::Result<CreateResult> {
+        let key = self.request_key(request);
+        if self.mode == CassetteMode::Record {
+            return self.record_call(key, request).await;
+        }
+
+        let hit = self.entries.lock().unwrap().get(&key).cloned();
+        match (hit, self.mode) {
+            (Some(entry), _) => from_cassette_response(entry.response, &self.llm_config),
+            (None, CassetteMode::ReplayOrRecord) => self.record_call(key, request).await,
+            (None, _) => Err(CassetteMiss {
+                key,
+                path: self.path.clone(),
+            }
+            .into()),
+        }
+    }
+}
+
+fn canonicalize(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let sorted: BTreeMap<&String, Value> =
+                map.iter().map(|(k, v)| (k, canonicalize(v))).collect();
+            let mut out = serde_json::Map::new();
+            for (k, v) in sorted {
+                out.insert(k.clone(), v);
+            }
+            Value::Object(out)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(canonicalize).collect()),
+        other => other.clone(),
+    }
+}
+
+fn to_cassette_response(result: &CreateResult) -> CassetteResponse {
+    let (text, image, function_calls) = match &result.content {
+        ResultContent::TextContent(text) => (Some(text.text.clone()), None, Vec::new()),
+        ResultContent::FunctionCallContent(calls) => (None, None, calls.clone()),
+        ResultContent::MultiModalContent(MultiModalContent::Text(text)) => {
+            (Some(text.text.clone()), None, Vec::new())
+        }
+        ResultContent::MultiModalContent(MultiModalContent::Image(image)) => {
+            (None, Some(image.to_data_uri()), Vec::new())
+        }
+    };
+    CassetteResponse {
+        finish_reason: match result.finish_reason {
+            FinishReason::Stop => "stop",
+            FinishReason::Length => "length",
+            FinishReason::FunctionCall => "tool_calls",
+            FinishReason::ContentFilter => "content_filter",
+        }
+        .to_string(),
+        text,
+        image,
+        multimodal: matches!(result.content, ResultContent::MultiModalContent(_)),
+        function_calls,
+        prompt_tokens: result.usage.prompt_tokens,
+        completion_tokens: result.usage.completion_tokens,
+        cache_creation_tokens: result.usage.cache_creation_tokens,
+        cache_read_tokens: result.usage.cache_read_tokens,
+    }
+}
+
+fn from_cassette_response(
+    response: CassetteResponse,
+    llm_config: &LlmConfig,
+) -> anyhow::Result<CreateResult> {
+    let content = if let Some(image) = &response.image {
+        ResultContent::MultiModalContent(MultiModalContent::Image(ImageContent::from_data_uri(
+            image,
+        )?))
+    } else if !response.function_calls.is_empty() {
+        ResultContent::FunctionCallContent(response.function_calls)
+    } else {
+        let text = response.text.unwrap_or_default().into();
+        if response.multimodal {
+            ResultContent::MultiModalContent(MultiModalContent::Text(text))
+        } else {
+            ResultContent::TextContent(text)
+        }
+    };
+    Ok(CreateResult {
+        finish_reason: FinishReason::from_api(&response.finish_reason),
+        content,
+        usage: RequestUsage {
+            prompt_tokens: response.prompt_tokens,
+            completion_tokens: response.completion_tokens,
+            cache_creation_tokens: response.cache_creation_tokens,
+            cache_read_tokens: response.cache_read_tokens,
+        },
+        served_by: llm_config.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::llm_backend::mock_server::{MockLlmServer, MockReply};
+    use crate::agent::llm_backend::openai::OpenAiClient;
+    use crate::agent::llm_backend::OPENAI_CONFIG;
+    use crate::msg_types::llm_msg_types::LlmMessage;
+    use crate::msg_types::AgentId;
+
+    /// Always answers with the same image, like an image-generating chat model.
+    struct ImageClient(ImageContent);
+
+    #[async_trait]
+    impl LlmClient for ImageClient {
+        fn llm_config(&self) -> &LlmConfig {
+            &OPENAI_CONFIG
+        }
+
+        async fn create(&self, _request: &CreateRequest) -> anyhow::Result<CreateResult> {
+            Ok(CreateResult {
+                finish_reason: FinishReason::Stop,
+                content: ResultContent::MultiModalContent(MultiModalContent::Image(self.0.clone())),
+                usage: RequestUsage::default(),
+                served_by: OPENAI_CONFIG,
+            })
+        }
+    }
+
+    fn request(text: &str) -> CreateRequest {
+        CreateRequest::new(vec![LlmMessage::user_text(text, AgentId::new(None))], 100)
+    }
+
+    fn cassette_path() -> PathBuf {
+        std::env::temp_dir().join(format!("cassette-{}.json", uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay_offline() {
+        let path = cassette_path();
+        {
+            let server = MockLlmServer::start(vec![MockReply::text("recorded answer")])
+                .await
+                .unwrap();
+            let backend = Arc::new(OpenAiClient::new(server.config(&OPENAI_CONFIG)));
+            let recorder = CassetteClient::record(backend, &path).unwrap();
+            recorder.create(&request("hello")).await.unwrap();
+            assert_eq!(recorder.len(), 1);
+        }
+
+        let replayer = CassetteClient::replay(OPENAI_CONFIG, &path).unwrap();
+        let result = replayer.create(&request("hello")).await.unwrap();
+        assert!(matches!(
+            result.content,
+            ResultContent::TextContent(ref text) if text.text == "recorded answer"
+        ));
+
+        let miss = replayer
+            .create(&request("something else"))
+            .await
+            .unwrap_err();
+        assert!(miss.downcast_ref::<CassetteMiss>().is_some());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_or_record_only_calls_backend_on_miss() {
+        let path = cassette_path();
+        let server =
+            MockLlmServer::start(vec![MockReply::text("first"), MockReply::text("second")])
+                .await
+                .unwrap();
+        let backend = Arc::new(OpenAiClient::new(server.config(&OPENAI_CONFIG)));
+        let cache = CassetteClient::replay_or_record(backend, &path).unwrap();
+
+        cache.create(&request("same question")).await.unwrap();
+        let again = cache.create(&request("same question")).await.unwrap();
+
+        assert!(matches!(
+            again.content,
+            ResultContent::TextContent(ref text) if text.text == "first"
+        ));
+        assert_eq!(server.requests().len(), 1);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_image_reply_round_trips() {
+        let path = cassette_path();
+        let png =
+            ImageContent::from_bytes(vec![0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A]).unwrap();
+        let recorder = CassetteClient::record(Arc::new(ImageClient(png.clone())), &path).unwrap();
+        recorder.create(&request("draw a dot")).await.unwrap();
+
+        let replayer = CassetteClient::replay(OPENAI_CONFIG, &path).unwrap();
+        let result = replayer.create(&request("draw a dot")).await.unwrap();
+        assert!(matches!(
+            result.content,
+            ResultContent::MultiModalContent(MultiModalContent::Image(ref image)) if *image == png
+        ));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
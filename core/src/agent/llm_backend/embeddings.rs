@@ -0,0 +1,116 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::agent::llm_backend::http::{bearer_headers, LlmHttpClient};
+use crate::agent::llm_backend::{AgentCapability, LlmConfig};
+
+/// Text-embedding backends, held by memories as `Arc<dyn EmbeddingClient>`.
+#[async_trait]
+pub trait EmbeddingClient: Send + Sync {
+    fn llm_config(&self) -> &LlmConfig;
+
+    /// One vector per input, in input order.
+    async fn embed(&self, inputs: &[String]) -> anyhow::Result<Vec<Vec<f32>>>;
+}
+
+/// Client for OpenAI-compatible `/v1/embeddings` endpoints (OpenAI, Together,
+/// Ollama and llama.cpp all serve one).
+#[derive(Clone)]
+pub struct OpenAiEmbeddingClient {
+    pub llm_config: LlmConfig,
+    http: LlmHttpClient,
+}
+
+impl OpenAiEmbeddingClient {
+    pub fn new(llm_config: LlmConfig) -> anyhow::Result<Self> {
+        if !llm_config.supports(&AgentCapability::Embedding) {
+            return Err(anyhow::anyhow!(
+                "Model {} does not produce embeddings",
+                llm_config.model
+            ));
+        }
+        Ok(OpenAiEmbeddingClient {
+            llm_config,
+            http: LlmHttpClient::shared(),
+        })
+    }
+
+    pub fn with_http(llm_config: LlmConfig, http: LlmHttpClient) -> Self {
+        OpenAiEmbeddingClient { llm_config, http }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[async_trait]
+impl EmbeddingClient for OpenAiEmbeddingClient {
+    fn llm_config(&self) -> &LlmConfig {
+        &self.llm_config
+    }
+
+    async fn embed(&self, inputs: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+        if inputs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let headers = bearer_headers(&self.llm_config)?;
+        let body_json = json!({
+            "model": self.llm_config.model,
+            "input": inputs,
+            "encoding_format": "float"
+        });
+
+        let response_body = self
+            .http
+            .post_json(self.llm_config.base_url, headers, &body_json)
+            .await?;
+        let mut response = serde_json::from_str::<EmbeddingResponse>(&response_body)?;
+        if response.data.len() != inputs.len() {
+            return Err(anyhow::anyhow!(
+                "Expected {} embeddings, got {}",
+                inputs.len(),
+                response.data.len()
+            ));
+        }
+        response.data.sort_by_key(|d| d.index);
+        Ok(response.data.into_iter().map(|d| d.embedding).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::llm_backend::mock_server::{MockLlmServer, MockReply};
+    use crate::agent::llm_backend::OPENAI_EMBEDDING_CONFIG;
+
+    #[tokio::test]
+    async fn test_embed_keeps_input_order() {
+        let server = MockLlmServer::start(vec![MockReply::Embeddings(vec![
+            vec![1.0, 0.0],
+            vec![0.0, 1.0],
+        ])])
+        .await
+        .unwrap();
+        let client = OpenAiEmbeddingClient::new(
+            server.config_for_path(&OPENAI_EMBEDDING_CONFIG, "/v1/embeddings"),
+        )
+        .unwrap();
+
+        let vectors = client
+            .embed(&["first".to_string(), "second".to_string()])
+            .await
+            .unwrap();
+
+        assert_eq!(vectors, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+        assert_eq!(server.requests()[0].body["input"][1], "second");
+    }
+}
@@ -0,0 +1,186 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::agent::chat_agent::CreateResult;
+use crate::agent::llm_backend::{AgentCapability, CreateRequest, LlmClient, LlmConfig};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RoutingStrategy {
+    /// Always start with the first capable client, moving down the list on failure.
+    Priority,
+    /// Rotate the starting client between calls to spread load across equivalent
+    /// models; the rest are still tried in order on failure.
+    RoundRobin,
+}
+
+/// Composite client over several providers. Only clients whose capabilities
+/// cover the request (vision for image input, tool calling when tools are passed)
+/// are considered; a failed or rate-limited provider falls through to the next.
+pub struct FallbackClient {
+    clients: Vec<Arc<dyn LlmClient>>,
+    strategy: RoutingStrategy,
+    next: AtomicUsize,
+}
+
+impl FallbackClient {
+    pub fn new(clients: Vec<Arc<dyn LlmClient>>, strategy: RoutingStrategy) -> Self {
+        assert!(
+            !clients.is_empty(),
+            "FallbackClient needs at least one client"
+        );
+        FallbackClient {
+            clients,
+            strategy,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    fn candidates(&self, required: &[AgentCapability]) -> Vec<Arc<dyn LlmClient>> {
+        let mut capable: Vec<Arc<dyn LlmClient>> = self
+            .clients
+            .iter()
+            .filter(|client| required.iter().all(|cap| client.supports(cap)))
+            .cloned()
+            .collect();
+
+        if self.strategy == RoutingStrategy::RoundRobin && !capable.is_empty() {
+            let start = self.next.fetch_add(1, Ordering::Relaxed) % capable.len();
+            capable.rotate_left(start);
+        }
+        capable
+    }
+}
+
+#[async_trait]
+impl LlmClient for FallbackClient {
+    fn llm_config(&self) -> &LlmConfig {
+        self.clients[0].llm_config()
+    }
+
+    fn supports(&self, capability: &AgentCapability) -> bool {
+        self.clients
+            .iter()
+            .any(|client| client.supports(capability))
+    }
+
+    async fn create(&self, request: &CreateRequest) -> anyhow::Result<CreateResult> {
+        let required = request.required_capabilities();
+        let candidates = self.candidates(&required);
+        if candidates.is_empty() {
+            return Err(anyhow::anyhow!(
+                "No configured model supports the required capabilities {:?}",
+                required
+            ));
+        }
+
+        let mut failures = Vec::new();
+        for client in candidates {
+            match client.create(request).await {
+                Ok(result) => return Ok(result),
+                Err(e) => {
+                    println!(
+                        "Model {} failed, falling back: {}",
+                        client.llm_config().model,
+                        e
+                    );
+                    failures.push(format!("{}: {}", client.llm_config().model, e));
+                }
+            }
+        }
+
+        Err(anyhow::anyhow!(
+            "All models failed:\n{}",
+            failures.join("\n")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::chat_agent::ResultContent;
+    use crate::agent::llm_backend::{OPENAI_CONFIG, QWEN_CONFIG, TOGETHER_VISION_CONFIG};
+    use crate::msg_types::llm_msg_types::LlmMessage;
+    use crate::msg_types::{AgentId, FinishReason, ImageContent, RequestUsage};
+
+    struct FakeClient {
+        llm_config: LlmConfig,
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl LlmClient for FakeClient {
+        fn llm_config(&self) -> &LlmConfig {
+            &self.llm_config
+        }
+
+        async fn create(&self, _request: &CreateRequest) -> anyhow::Result<CreateResult> {
+            if self.fail {
+                return Err(anyhow::anyhow!("rate limited"));
+            }
+            Ok(CreateResult {
+                finish_reason: FinishReason::Stop,
+                content: ResultContent::TextContent(self.llm_config.model.into()),
+                usage: RequestUsage::default(),
+                served_by: self.llm_config.clone(),
+            })
+        }
+    }
+
+    fn fake(llm_config: LlmConfig, fail: bool) -> Arc<dyn LlmClient> {
+        Arc::new(FakeClient { llm_config, fail })
+    }
+
+    fn text_request() -> CreateRequest {
+        CreateRequest::new(vec![LlmMessage::user_text("hi", AgentId::new(None))], 100)
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_on_failure() {
+        let client = FallbackClient::new(
+            vec![fake(OPENAI_CONFIG, true), fake(QWEN_CONFIG, false)],
+            RoutingStrategy::Priority,
+        );
+
+        let result = client.create(&text_request()).await.unwrap();
+        assert_eq!(result.served_by.model, QWEN_CONFIG.model);
+    }
+
+    #[tokio::test]
+    async fn test_round_robin_rotates() {
+        let client = FallbackClient::new(
+            vec![fake(OPENAI_CONFIG, false), fake(QWEN_CONFIG, false)],
+            RoutingStrategy::RoundRobin,
+        );
+
+        let first = client.create(&text_request()).await.unwrap();
+        let second = client.create(&text_request()).await.unwrap();
+        assert_ne!(first.served_by.model, second.served_by.model);
+    }
+
+    #[tokio::test]
+    async fn test_vision_request_skips_text_only_models() {
+        let image =
+            ImageContent::from_bytes(vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]).unwrap();
+        let client = FallbackClient::new(
+            vec![
+                fake(OPENAI_CONFIG, false),
+                fake(TOGETHER_VISION_CONFIG, false),
+            ],
+            RoutingStrategy::Priority,
+        );
+        let request = CreateRequest::new(
+            vec![LlmMessage::user_image(image.clone(), AgentId::new(None))],
+            100,
+        );
+
+        let result = client.create(&request).await.unwrap();
+        assert_eq!(result.served_by.model, TOGETHER_VISION_CONFIG.model);
+
+        let text_only =
+            FallbackClient::new(vec![fake(OPENAI_CONFIG, false)], RoutingStrategy::Priority);
+        assert!(text_only.create(&request).await.is_err());
+    }
+}
@@ -0,0 +1,432 @@
+use std::error::Error;
+use std::time::{Duration, SystemTime};
+
+use once_cell::sync::Lazy;
+use rand::Rng;
+use reqwest::{
+    header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER},
+    multipart::Form,
+    Client, ClientBuilder, RequestBuilder, Response, StatusCode,
+};
+use serde_json::Value;
+
+use crate::agent::llm_backend::LlmConfig;
+
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    pub connect_timeout: Duration,
+    pub request_timeout: Duration,
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            connect_timeout: Duration::from_secs(10),
+            request_timeout: Duration::from_secs(120),
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LlmHttpError {
+    RateLimited {
+        retry_after: Option<Duration>,
+        body: String,
+    },
+    Client {
+        status: u16,
+        body: String,
+    },
+    Server {
+        status: u16,
+        body: String,
+    },
+    Timeout(String),
+    Transport(String),
+}
+
+impl LlmHttpError {
+    pub fn is_retryable(&self) -> bool {
+        !matches!(self, LlmHttpError::Client { .. })
+    }
+
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            LlmHttpError::RateLimited { .. } => Some(429),
+            LlmHttpError::Client { status, .. } | LlmHttpError::Server { status, .. } => {
+                Some(*status)
+            }
+            LlmHttpError::Timeout(_) | LlmHttpError::Transport(_) => None,
+        }
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            LlmHttpError::RateLimited { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for LlmHttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LlmHttpError::RateLimited { retry_after, body } => {
+                write!(f, "rate limited (retry after {:?}): {}", retry_after, body)
+            }
+            LlmHttpError::Client { status, body } => {
+                write!(f, "request rejected with HTTP {}: {}", status, body)
+            }
+            LlmHttpError::Server { status, body } => {
+                write!(f, "server error HTTP {}: {}", status, body)
+            }
+            LlmHttpError::Timeout(msg) => write!(f, "request timed out: {}", msg),
+            LlmHttpError::Transport(msg) => write!(f, "transport error: {}", msg),
+        }
+    }
+}
+
+impl Error for LlmHttpError {}
+
+static SHARED_CLIENT: Lazy<Client> =
+    Lazy::new(|| build_client(&HttpConfig::default()).expect("failed to build shared HTTP client"));
+
+fn build_client(config: &HttpConfig) -> reqwest::Result<Client> {
+    ClientBuilder::new()
+        .user_agent("MyClient/1.0.0")
+        .connect_timeout(config.connect_timeout)
+        .build()
+}
+
+/// `Content-Type` plus `Authorization: Bearer <key>`, where the key is read from the
+/// env var named by `api_key_str`. An empty `api_key_str` means no auth header.
+pub fn bearer_headers(llm_config: &LlmConfig) -> anyhow::Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    if !llm_config.api_key_str.is_empty() {
+        let api_key = std::env::var(llm_config.api_key_str)?;
+        let bearer_token = format!("Bearer {}", api_key);
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(&bearer_token)?);
+    }
+    Ok(headers)
+}
+
+/// HTTP layer shared by the LLM backends: one pooled `reqwest::Client`, error
+/// classification by status code, and retries with jittered exponential backoff
+/// that honours `Retry-After` on 429s.
+#[derive(Clone)]
+pub struct LlmHttpClient {
+    client: Client,
+    config: HttpConfig,
+}
+
+impl LlmHttpClient {
+    pub fn shared() -> Self {
+        LlmHttpClient {
+            client: SHARED_CLIENT.clone(),
+            config: HttpConfig::default(),
+        }
+    }
+
+    pub fn with_config(config: HttpConfig) -> anyhow::Result<Self> {
+        let client = build_client(&config)?;
+        Ok(LlmHttpClient { client, config })
+    }
+
+    pub fn config(&self) -> &HttpConfig {
+        &self.config
+    }
+
+    pub async fn post_json(
+        &self,
+        url: &str,
+        headers: HeaderMap,
+        body: &Value,
+    ) -> Result<String, LlmHttpError> {
+        let response = self.post_with_retry(url, headers, body).await?;
+        response
+            .text()
+            .await
+            .map_err(|e| LlmHttpError::Transport(e.to_string()))
+    }
+
+    /// Sends the request and returns the successful response without reading the
+    /// body, so callers can consume it as a stream.
+    pub async fn post_with_retry(
+        &self,
+        url: &str,
+        headers: HeaderMap,
+        body: &Value,
+    ) -> Result<Response, LlmHttpError> {
+        self.send_with_retry(|| self.client.post(url).headers(headers.clone()).json(body))
+            .await
+    }
+
+    /// Posts a `multipart/form-data` body. Forms can't be cloned, so `build_form`
+    /// is called again for every attempt.
+    pub async fn post_multipart(
+        &self,
+        url: &str,
+        mut headers: HeaderMap,
+        build_form: impl Fn() -> Form,
+    ) -> Result<Response, LlmHttpError> {
+        // reqwest sets the multipart content type with its boundary.
+        headers.remove(CONTENT_TYPE);
+        self.send_with_retry(|| {
+            self.client
+                .post(url)
+                .headers(headers.clone())
+                .multipart(build_form())
+        })
+        .await
+    }
+
+    async fn send_with_retry(
+        &self,
+        build_request: impl Fn() -> RequestBuilder,
+    ) -> Result<Response, LlmHttpError> {
+        let mut attempt = 0;
+        loop {
+            match self.send_once(build_request()).await {
+                Ok(response) => return Ok(response),
+                Err(err) if err.is_retryable() && attempt < self.config.max_retries => {
+                    let delay = self.backoff_delay(attempt, err.retry_after());
+                    println!(
+                        "LLM request attempt {} failed ({}), retrying in {:?}",
+                        attempt + 1,
+                        err,
+                        delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    async fn send_once(&self, request: RequestBuilder) -> Result<Response, LlmHttpError> {
+        let response = request
+            .timeout(self.config.request_timeout)
+            .send()
+            .await
+            .map_err(|e| {
+                if e.is_timeout() {
+                    LlmHttpError::Timeout(e.to_string())
+                } else {
+                    LlmHttpError::Transport(e.to_string())
+                }
+            })?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_retry_after);
+        let body = response.text().await.unwrap_or_default();
+
+        Err(classify_status(status, retry_after, body))
+    }
+
+    fn backoff_delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(self.config.max_backoff);
+        }
+        let exp = self
+            .config
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.config.max_backoff);
+        // Equal jitter: pick uniformly in [exp / 2, exp] so concurrent agents spread out.
+        let millis = exp.as_millis() as u64;
+        Duration::from_millis(rand::thread_rng().gen_range(millis / 2..=millis))
+    }
+}
+
+/// Splits a streaming response body into lines, for SSE events and NDJSON records.
+pub struct LineStream {
+    response: Response,
+    buf: Vec<u8>,
+}
+
+impl LineStream {
+    pub fn new(response: Response) -> Self {
+        LineStream {
+            response,
+            buf: Vec::new(),
+        }
+    }
+
+    pub async fn next_line(&mut self) -> Result<Option<String>, LlmHttpError> {
+        loop {
+            if let Some(pos) = self.buf.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = self.buf.drain(..=pos).collect();
+                return Ok(Some(String::from_utf8_lossy(&line).trim_end().to_string()));
+            }
+            let chunk = self
+                .response
+                .chunk()
+                .await
+                .map_err(|e| LlmHttpError::Transport(e.to_string()))?;
+            match chunk {
+                Some(chunk) => self.buf.extend_from_slice(&chunk),
+                None if self.buf.is_empty() => return Ok(None),
+                None => {
+                    let line = std::mem::take(&mut self.buf);
+                    return Ok(Some(String::from_utf8_lossy(&line).trim_end().to_string()));
+                }
+            }
+        }
+    }
+}
+
+pub fn classify_status(
+    status: StatusCode,
+    retry_after: Option<Duration>,
+    body: String,
+) -> LlmHttpError {
+    match status.as_u16() {
+        429 => LlmHttpError::RateLimited { retry_after, body },
+        408 => LlmHttpError::Timeout(body),
+        code if status.is_server_error() => LlmHttpError::Server { status: code, body },
+        code => LlmHttpError::Client { status: code, body },
+    }
+}
+
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<f64>() {
+        // Negative, `NaN`, `inf` and values past `Duration::MAX` all parse as f64.
+        return Duration::try_from_secs_f64(secs).ok();
+    }
+    httpdate::parse_http_date(value)
+        .ok()
+        .map(|at| at.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::llm_backend::mock_server::{MockLlmServer, MockReply};
+
+    fn fast_client(max_retries: u32) -> LlmHttpClient {
+        LlmHttpClient::with_config(HttpConfig {
+            max_retries,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(5),
+            ..HttpConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_retries_server_error_then_succeeds() {
+        let server = MockLlmServer::start(vec![
+            MockReply::error(503, "overloaded"),
+            MockReply::Error {
+                status: 429,
+                body: "slow down".to_string(),
+                retry_after: Some("0".to_string()),
+            },
+            MockReply::text("hello"),
+        ])
+        .await
+        .unwrap();
+
+        let body = fast_client(3)
+            .post_json(&server.chat_url(), HeaderMap::new(), &serde_json::json!({}))
+            .await
+            .unwrap();
+
+        assert!(body.contains("hello"));
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_client_error_is_not_retried() {
+        let server = MockLlmServer::start(vec![
+            MockReply::error(400, "bad request"),
+            MockReply::text("unreachable"),
+        ])
+        .await
+        .unwrap();
+
+        let err = fast_client(3)
+            .post_json(&server.chat_url(), HeaderMap::new(), &serde_json::json!({}))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, LlmHttpError::Client { status: 400, .. }));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_retries() {
+        let server = MockLlmServer::start(vec![
+            MockReply::error(500, "boom"),
+            MockReply::error(502, "bad gateway"),
+        ])
+        .await
+        .unwrap();
+
+        let err = fast_client(1)
+            .post_json(&server.chat_url(), HeaderMap::new(), &serde_json::json!({}))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, LlmHttpError::Server { status: 502, .. }));
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_is_capped_by_max_backoff() {
+        let rate_limited = |retry_after: &str| MockReply::Error {
+            status: 429,
+            body: "slow down".to_string(),
+            retry_after: Some(retry_after.to_string()),
+        };
+        let server = MockLlmServer::start(vec![
+            rate_limited("86400"),
+            rate_limited("inf"),
+            rate_limited("1e30"),
+            MockReply::text("hello"),
+        ])
+        .await
+        .unwrap();
+
+        let body = tokio::time::timeout(
+            Duration::from_secs(5),
+            fast_client(3).post_json(&server.chat_url(), HeaderMap::new(), &serde_json::json!({})),
+        )
+        .await
+        .expect("Retry-After should not outlast max_backoff")
+        .unwrap();
+
+        assert!(body.contains("hello"));
+        assert_eq!(server.requests().len(), 4);
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("7"), Some(Duration::from_secs(7)));
+        assert_eq!(
+            parse_retry_after("Thu, 01 Jan 1970 00:00:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+        assert_eq!(parse_retry_after("inf"), None);
+        assert_eq!(parse_retry_after("NaN"), None);
+        assert_eq!(parse_retry_after("1e30"), None);
+        assert_eq!(parse_retry_after("-1"), None);
+    }
+}
@@ -0,0 +1,171 @@
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::agent::llm_backend::http::{bearer_headers, LlmHttpClient};
+use crate::agent::llm_backend::{AgentCapability, LlmConfig};
+use crate::msg_types::ImageContent;
+
+#[derive(Debug, Clone)]
+pub struct ImageGenRequest {
+    pub prompt: String,
+    pub n: u8,
+    /// `"<width>x<height>"`, e.g. `"1024x1024"`.
+    pub size: String,
+}
+
+impl ImageGenRequest {
+    pub fn new(prompt: impl Into<String>) -> Self {
+        ImageGenRequest {
+            prompt: prompt.into(),
+            n: 1,
+            size: "1024x1024".to_string(),
+        }
+    }
+}
+
+/// Text-to-image backends, held by agents as `Arc<dyn ImageGenClient>`.
+#[async_trait]
+pub trait ImageGenClient: Send + Sync {
+    fn llm_config(&self) -> &LlmConfig;
+
+    async fn generate(&self, request: &ImageGenRequest) -> anyhow::Result<Vec<ImageContent>>;
+}
+
+/// Client for OpenAI-compatible `/v1/images/generations` endpoints. Images are
+/// requested as `b64_json`; providers that only return URLs are downloaded.
+#[derive(Clone)]
+pub struct OpenAiImageClient {
+    pub llm_config: LlmConfig,
+    http: LlmHttpClient,
+}
+
+impl OpenAiImageClient {
+    pub fn new(llm_config: LlmConfig) -> anyhow::Result<Self> {
+        if !llm_config.supports(&AgentCapability::ImageGeneration) {
+            return Err(anyhow::anyhow!(
+                "Model {} does not support image generation",
+                llm_config.model
+            ));
+        }
+        Ok(OpenAiImageClient {
+            llm_config,
+            http: LlmHttpClient::shared(),
+        })
+    }
+
+    pub fn with_http(llm_config: LlmConfig, http: LlmHttpClient) -> Self {
+        OpenAiImageClient { llm_config, http }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ImagesResponse {
+    data: Vec<ImageData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImageData {
+    b64_json: Option<String>,
+    url: Option<String>,
+}
+
+#[async_trait]
+impl ImageGenClient for OpenAiImageClient {
+    fn llm_config(&self) -> &LlmConfig {
+        &self.llm_config
+    }
+
+    async fn generate(&self, request: &ImageGenRequest) -> anyhow::Result<Vec<ImageContent>> {
+        let headers = bearer_headers(&self.llm_config)?;
+        let body_json = json!({
+            "model": self.llm_config.model,
+            "prompt": request.prompt,
+            "n": request.n,
+            "size": request.size,
+            "response_format": "b64_json"
+        });
+
+        let response_body = self
+            .http
+            .post_json(self.llm_config.base_url, headers, &body_json)
+            .await?;
+        let response = serde_json::from_str::<ImagesResponse>(&response_body)?;
+
+        let mut images = Vec::new();
+        for item in response.data {
+            let image = match (item.b64_json, item.url) {
+                (Some(b64), _) => ImageContent::from_bytes(
+                    base64::engine::general_purpose::STANDARD.decode(b64.trim())?,
+                )?,
+                (None, Some(url)) => ImageContent::from_url(&url).await?,
+                (None, None) => {
+                    return Err(anyhow::anyhow!("Image entry has neither data nor url"))
+                }
+            };
+            images.push(image);
+        }
+        Ok(images)
+    }
+}
+
+/// Writes the images as `<prefix>_<n>.<ext>` under `dir`, creating it if needed.
+pub fn save_images(
+    images: &[ImageContent],
+    dir: impl AsRef<Path>,
+    prefix: &str,
+) -> anyhow::Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir.as_ref())?;
+    images
+        .iter()
+        .enumerate()
+        .map(|(i, image)| {
+            let path = dir
+                .as_ref()
+                .join(format!("{}_{}.{}", prefix, i, image.extension()));
+            image.save(&path)?;
+            Ok(path)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::llm_backend::mock_server::{MockLlmServer, MockReply};
+    use crate::agent::llm_backend::{DALLE_CONFIG, OPENAI_CONFIG};
+
+    const PNG_HEADER: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+    #[tokio::test]
+    async fn test_generate_and_save() {
+        let server = MockLlmServer::start(vec![MockReply::Images(vec![PNG_HEADER.to_vec()])])
+            .await
+            .unwrap();
+        let client =
+            OpenAiImageClient::new(server.config_for_path(&DALLE_CONFIG, "/v1/images/generations"))
+                .unwrap();
+
+        let images = client
+            .generate(&ImageGenRequest::new("a meme about jokes"))
+            .await
+            .unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].mime_type, "image/png");
+        assert_eq!(server.requests()[0].body["prompt"], "a meme about jokes");
+
+        let dir = std::env::temp_dir().join(format!("image-gen-{}", uuid::Uuid::new_v4()));
+        let paths = save_images(&images, &dir, "meme").unwrap();
+        assert_eq!(paths[0].file_name().unwrap(), "meme_0.png");
+        assert_eq!(std::fs::read(&paths[0]).unwrap(), PNG_HEADER);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_model_without_capability() {
+        assert!(OpenAiImageClient::new(OPENAI_CONFIG).is_err());
+    }
+}
@@ -1,14 +1,15 @@
 use std::collections::HashMap;
 
-use reqwest::{
-    header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE, USER_AGENT},
-    ClientBuilder,
-};
+use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::agent::llm_backend::{LlmConfig, TOGETHER_CONFIG};
-use crate::msg_types::{llm_msg_types::LlmMessage, RequestUsage, AgentId};
+use crate::agent::chat_agent::{CreateResult, ResultContent};
+use crate::agent::llm_backend::http::{bearer_headers, LlmHttpClient};
+use crate::agent::llm_backend::openai::openai_request_body;
+use crate::agent::llm_backend::{CreateRequest, LlmClient, LlmConfig, TOGETHER_CONFIG};
+use crate::msg_types::chat_msg_types::AssistantMessageContent;
+use crate::msg_types::{llm_msg_types::LlmMessage, AgentId, FinishReason, RequestUsage};
 use crate::tool_types::FunctionCallInput;
 
 // Define custom FinishReason to include 'eos'
@@ -56,6 +57,81 @@ pub struct UsageExt {
     pub total_tokens: u64,
 }
 
+/// Llama models on Together: tools are described in the system prompt and calls
+/// come back as `<tool_call>{...}</tool_call>` text rather than native `tool_calls`.
+#[derive(Clone)]
+pub struct LlamaClient {
+    pub llm_config: LlmConfig,
+    http: LlmHttpClient,
+}
+
+impl LlamaClient {
+    pub fn new(llm_config: LlmConfig) -> Self {
+        LlamaClient {
+            llm_config,
+            http: LlmHttpClient::shared(),
+        }
+    }
+}
+
+#[async_trait]
+impl LlmClient for LlamaClient {
+    fn llm_config(&self) -> &LlmConfig {
+        &self.llm_config
+    }
+
+    async fn create(&self, request: &CreateRequest) -> anyhow::Result<CreateResult> {
+        let headers = bearer_headers(&self.llm_config)?;
+
+        let mut request = request.clone();
+        if !request.tools.is_empty() {
+            let tool_prompt = format!(
+                "Here are the tools you're equipped with: {}\n",
+                Value::Array(std::mem::take(&mut request.tools))
+            );
+            request
+                .messages
+                .insert(0, LlmMessage::system(tool_prompt, AgentId::new(Some("hold"))));
+        }
+        let body_json = openai_request_body(&self.llm_config, &request);
+
+        let response_body = self
+            .http
+            .post_json(self.llm_config.base_url, headers, &body_json)
+            .await?;
+        let raw_output = serde_json::from_str::<CreateChatCompletionResponseExt>(&response_body)?;
+
+        let usage = raw_output
+            .usage
+            .clone()
+            .map(|u| RequestUsage {
+                prompt_tokens: u.prompt_tokens as i32,
+                completion_tokens: u.completion_tokens as i32,
+                ..Default::default()
+            })
+            .unwrap_or_default();
+        let (content, finish_reason) = match output_llmmessage(raw_output) {
+            Some(LlmMessage::AssistantMessage(msg)) => match msg.content {
+                AssistantMessageContent::FunctionCallInput(call) => (
+                    ResultContent::FunctionCallContent(vec![call]),
+                    FinishReason::FunctionCall,
+                ),
+                AssistantMessageContent::TextContent(text) => {
+                    (ResultContent::TextContent(text), FinishReason::Stop)
+                }
+            },
+            _ => return Err(anyhow::anyhow!("Could not convert to LlmMessage")),
+        };
+
+        Ok(CreateResult {
+            finish_reason,
+            content,
+            usage,
+            served_by: self.llm_config.clone(),
+        })
+    }
+}
+
 pub async fn chat_wrapper_llama_toolcall(
     llm_config: &LlmConfig,
     functions: &Value,
@@ -63,17 +139,11 @@ pub async fn chat_wrapper_llama_toolcall(
     input: &str,
     max_token: u16,
 ) -> anyhow::Result<(LlmMessage, RequestUsage)> {
-    let mut headers = HeaderMap::new();
-    let api_key = std::env::var(&llm_config.api_key_str)?;
-    let bearer_token = format!("Bearer {}", api_key);
-
     // Ensure that the model is a Llama model
     let contains_llama = llm_config.model.to_ascii_lowercase().contains("llama");
     assert_eq!(contains_llama, true);
 
-    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-    headers.insert(USER_AGENT, HeaderValue::from_static("MyClient/1.0.0"));
-    headers.insert(AUTHORIZATION, HeaderValue::from_str(&bearer_token)?);
+    let headers = bearer_headers(llm_config)?;
 
     // Combine system prompt and functions into the prompt
     let system_prompt_w_tool = format!(
@@ -95,12 +165,11 @@ pub async fn chat_wrapper_llama_toolcall(
         "temperature": 0.3
     });
 
-    let body = serde_json::to_vec(&body_json)?;
-
-    let client = ClientBuilder::new().default_headers(headers).build()?;
-    match client.post(uri).body(body.clone()).send().await {
-        Ok(chat) => {
-            let response_body = chat.text().await?;
+    match LlmHttpClient::shared()
+        .post_json(uri, headers, &body_json)
+        .await
+    {
+        Ok(response_body) => {
             let raw_output =
                 serde_json::from_str::<CreateChatCompletionResponseExt>(&response_body)?;
 
@@ -110,10 +179,12 @@ pub async fn chat_wrapper_llama_toolcall(
                     .map(|u| RequestUsage {
                         prompt_tokens: u.prompt_tokens as i32,
                         completion_tokens: u.completion_tokens as i32,
+                        ..Default::default()
                     })
                     .unwrap_or(RequestUsage {
                         prompt_tokens: 0,
                         completion_tokens: 0,
+                        ..Default::default()
                     });
                 Ok((llm_message, usage))
             } else {
@@ -121,11 +192,8 @@ pub async fn chat_wrapper_llama_toolcall(
             }
         }
         Err(e) => {
-            println!("Error getting response from Llama API: {:?}", e);
-            Err(anyhow::anyhow!(
-                "Failed to get reply from Llama API: {:?}",
-                e
-            ))
+            println!("Error getting response from Llama API: {}", e);
+            Err(anyhow::Error::new(e).context("Failed to get reply from Llama API"))
         }
     }
 }
@@ -136,16 +204,10 @@ pub async fn chat_wrapper_llama(
     input: &str,
     max_token: u16,
 ) -> anyhow::Result<(LlmMessage, RequestUsage)> {
-    let mut headers = HeaderMap::new();
-    let api_key = std::env::var(&llm_config.api_key_str)?;
-    let bearer_token = format!("Bearer {}", api_key);
-
     let contains_llama = llm_config.model.to_ascii_lowercase().contains("llama");
     assert_eq!(contains_llama, true);
 
-    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-    headers.insert(USER_AGENT, HeaderValue::from_static("MyClient/1.0.0"));
-    headers.insert(AUTHORIZATION, HeaderValue::from_str(&bearer_token)?);
+    let headers = bearer_headers(llm_config)?;
 
     let messages = serde_json::json!([
         {"role": "system", "content": system_prompt},
@@ -161,12 +223,11 @@ pub async fn chat_wrapper_llama(
         "temperature": 0.3
     });
 
-    let body = serde_json::to_vec(&body_json)?;
-
-    let client = ClientBuilder::new().default_headers(headers).build()?;
-    match client.post(uri).body(body.clone()).send().await {
-        Ok(chat) => {
-            let response_body = chat.text().await?;
+    match LlmHttpClient::shared()
+        .post_json(uri, headers, &body_json)
+        .await
+    {
+        Ok(response_body) => {
             let raw_output =
                 serde_json::from_str::<CreateChatCompletionResponseExt>(&response_body)?;
             let usage = raw_output
@@ -175,10 +236,12 @@ pub async fn chat_wrapper_llama(
                 .map(|u| RequestUsage {
                     prompt_tokens: u.prompt_tokens as i32,
                     completion_tokens: u.completion_tokens as i32,
+                    ..Default::default()
                 })
                 .unwrap_or(RequestUsage {
                     prompt_tokens: 0,
                     completion_tokens: 0,
+                    ..Default::default()
                 });
 
             let llm_message = LlmMessage::assistant_text(
@@ -189,11 +252,8 @@ pub async fn chat_wrapper_llama(
             Ok((llm_message, usage))
         }
         Err(e) => {
-            println!("Error getting response from Llama API: {:?}", e);
-            Err(anyhow::anyhow!(
-                "Failed to get reply from Llama API: {:?}",
-                e
-            ))
+            println!("Error getting response from Llama API: {}", e);
+            Err(anyhow::Error::new(e).context("Failed to get reply from Llama API"))
         }
     }
 }
@@ -227,6 +287,7 @@ pub fn output_llmmessage(res_obj: CreateChatCompletionResponseExt) -> Option<Llm
                 Some(tc) => {
                     // Construct FunctionCallInput
                     let function_call_input = FunctionCallInput {
+                        id: format!("call_{}", uuid::Uuid::new_v4().simple()),
                         function_name: tc.name.clone(),
                         arguments_obj: serde_json::Value::Object(
                             tc.arguments
@@ -315,3 +376,61 @@ pub async fn run_test() {
 
     println!("msg: {:?} \n usage: {:?} ", res.0, res.1.completion_tokens);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::llm_backend::http::LlmHttpError;
+    use crate::agent::llm_backend::mock_server::{MockLlmServer, MockReply};
+
+    #[tokio::test]
+    async fn test_llama_client_parses_xml_tool_call() {
+        let server = MockLlmServer::start(vec![MockReply::text(
+            r#"<tool_call>{"name": "get_current_weather", "arguments": {"location": "Paris", "unit": "celsius"}}</tool_call>"#,
+        )])
+        .await
+        .unwrap();
+        let client = LlamaClient::new(server.config(&TOGETHER_CONFIG));
+
+        let mut request = CreateRequest::new(
+            vec![LlmMessage::user_text(
+                "Weather in Paris?",
+                AgentId::new(None),
+            )],
+            100,
+        );
+        request.tools = vec![serde_json::json!({"name": "get_current_weather"})];
+        let result = client.create(&request).await.unwrap();
+
+        match result.content {
+            ResultContent::FunctionCallContent(calls) => {
+                let call = &calls[0];
+                assert_eq!(call.function_name, "get_current_weather");
+                assert_eq!(call.arguments_obj["location"], "Paris");
+            }
+            other => panic!("expected a function call, got {:?}", other),
+        }
+        let sent = &server.requests()[0].body;
+        assert!(sent.get("tools").is_none());
+        assert!(sent["messages"][0]["content"]
+            .as_str()
+            .unwrap()
+            .contains("get_current_weather"));
+    }
+
+    #[tokio::test]
+    async fn test_error_status_is_not_parsed_as_completion() {
+        let server = MockLlmServer::start(vec![MockReply::error(401, "invalid api key")])
+            .await
+            .unwrap();
+
+        let err = chat_wrapper_llama(&server.config(&TOGETHER_CONFIG), "system", "hi", 100)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<LlmHttpError>(),
+            Some(LlmHttpError::Client { status: 401, .. })
+        ));
+    }
+}
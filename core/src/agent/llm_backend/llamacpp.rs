@@ -0,0 +1,113 @@
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+use crate::agent::chat_agent::CreateResult;
+use crate::agent::llm_backend::http::{bearer_headers, LlmHttpClient};
+use crate::agent::llm_backend::openai::{
+    openai_request_body, parse_openai_response, read_openai_stream,
+};
+use crate::agent::llm_backend::{CreateRequest, LlmClient, LlmConfig};
+
+/// Client for a llama.cpp `server`. Its `/v1/chat/completions` speaks the OpenAI
+/// schema, including `tool_calls` (with `--jinja`) and `image_url` parts (with a
+/// multimodal projector loaded); the prompt cache is kept warm between turns.
+#[derive(Clone)]
+pub struct LlamaCppClient {
+    pub llm_config: LlmConfig,
+    http: LlmHttpClient,
+}
+
+impl LlamaCppClient {
+    pub fn new(llm_config: LlmConfig) -> Self {
+        LlamaCppClient {
+            llm_config,
+            http: LlmHttpClient::shared(),
+        }
+    }
+
+    pub fn with_http(llm_config: LlmConfig, http: LlmHttpClient) -> Self {
+        LlamaCppClient { llm_config, http }
+    }
+
+    fn request_body(&self, request: &CreateRequest) -> Value {
+        let mut body_json = openai_request_body(&self.llm_config, request);
+        body_json["cache_prompt"] = json!(true);
+        body_json
+    }
+}
+
+#[async_trait]
+impl LlmClient for LlamaCppClient {
+    fn llm_config(&self) -> &LlmConfig {
+        &self.llm_config
+    }
+
+    async fn create(&self, request: &CreateRequest) -> anyhow::Result<CreateResult> {
+        let headers = bearer_headers(&self.llm_config)?;
+        let body_json = self.request_body(request);
+
+        let response_body = self
+            .http
+            .post_json(self.llm_config.base_url, headers, &body_json)
+            .await?;
+
+        parse_openai_response(&response_body, &self.llm_config)
+    }
+
+    async fn create_stream(
+        &self,
+        request: &CreateRequest,
+        tx: mpsc::Sender<String>,
+    ) -> anyhow::Result<CreateResult> {
+        let headers = bearer_headers(&self.llm_config)?;
+        let mut body_json = self.request_body(request);
+        body_json["stream"] = json!(true);
+
+        let response = self
+            .http
+            .post_with_retry(self.llm_config.base_url, headers, &body_json)
+            .await?;
+
+        read_openai_stream(response, &self.llm_config, tx).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::chat_agent::ResultContent;
+    use crate::agent::llm_backend::mock_server::{MockLlmServer, MockReply};
+    use crate::agent::llm_backend::{client_for, LLAMACPP_CONFIG};
+    use crate::msg_types::llm_msg_types::LlmMessage;
+    use crate::msg_types::AgentId;
+
+    #[tokio::test]
+    async fn test_llamacpp_stream() {
+        let server = MockLlmServer::start(vec![MockReply::Stream(vec![
+            "local ".to_string(),
+            "model".to_string(),
+        ])])
+        .await
+        .unwrap();
+        // Built the way agents build it, so the llama.cpp backend must be chosen.
+        let client = client_for(server.config(&LLAMACPP_CONFIG));
+        let (tx, mut rx) = mpsc::channel(8);
+
+        let request = CreateRequest::new(
+            vec![LlmMessage::user_text("who are you?", AgentId::new(None))],
+            100,
+        );
+        let result = client.create_stream(&request, tx).await.unwrap();
+
+        assert_eq!(rx.recv().await.as_deref(), Some("local "));
+        assert_eq!(rx.recv().await.as_deref(), Some("model"));
+        assert!(matches!(
+            result.content,
+            ResultContent::TextContent(ref text) if text.text == "local model"
+        ));
+        let sent = &server.requests()[0].body;
+        assert_eq!(sent["stream"], true);
+        assert_eq!(sent["cache_prompt"], true);
+    }
+}
@@ -0,0 +1,610 @@
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use base64::Engine;
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+use crate::agent::llm_backend::LlmConfig;
+
+/// One scripted answer. Replies are served in order, one per request, in the
+/// wire format of the route hit; once the script runs out the server answers 500.
+#[derive(Debug, Clone)]
+pub enum MockReply {
+    Text(String),
+    /// `(function name, arguments)` pairs returned as native `tool_calls`. On the
+    /// OpenAI route a string is sent as the raw `arguments` text, which need not
+    /// be valid JSON.
+    ToolCalls(Vec<(String, Value)>),
+    /// Content deltas sent as server-sent events, followed by `[DONE]`.
+    Stream(Vec<String>),
+    /// Raw `chat.completion.chunk` objects sent as server-sent events on the
+    /// OpenAI route, followed by `[DONE]`, for scripting tool call deltas.
+    StreamEvents(Vec<Value>),
+    /// Encoded images, returned as `b64_json` by `/v1/images/generations`.
+    Images(Vec<Vec<u8>>),
+    /// Encoded audio, returned as the raw body by `/v1/audio/speech`.
+    Audio(Vec<u8>),
+    /// One vector per input, returned by `/v1/embeddings`.
+    Embeddings(Vec<Vec<f32>>),
+    Error {
+        status: u16,
+        body: String,
+        /// Sent verbatim as the `Retry-After` header.
+        retry_after: Option<String>,
+    },
+}
+
+impl MockReply {
+    pub fn text(text: impl Into<String>) -> Self {
+        MockReply::Text(text.into())
+    }
+
+    pub fn tool_call(name: impl Into<String>, arguments: Value) -> Self {
+        MockReply::ToolCalls(vec![(name.into(), arguments)])
+    }
+
+    pub fn error(status: u16, body: impl Into<String>) -> Self {
+        MockReply::Error {
+            status,
+            body: body.into(),
+            retry_after: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    /// The parsed JSON body; bodies that aren't JSON, such as multipart uploads,
+    /// are kept as a lossy UTF-8 string.
+    pub body: Value,
+}
+
+/// Local stand-in for an OpenAI-compatible `/v1/chat/completions` endpoint (plus
+/// Ollama's `/api/chat`, Anthropic's `/v1/messages` and OpenAI's image, audio and
+/// embedding endpoints), so backends, agents and group chats can be exercised
+/// offline.
+pub struct MockLlmServer {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+    handle: JoinHandle<()>,
+}
+
+struct MockState {
+    script: Mutex<VecDeque<MockReply>>,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+impl MockLlmServer {
+    pub async fn start(script: Vec<MockReply>) -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let state = Arc::new(MockState {
+            script: Mutex::new(script.into()),
+            requests: requests.clone(),
+        });
+
+        let handle = tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(socket, state).await {
+                        println!("mock LLM server connection error: {}", e);
+                    }
+                });
+            }
+        });
+
+        Ok(MockLlmServer {
+            addr,
+            requests,
+            handle,
+        })
+    }
+
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn chat_url(&self) -> String {
+        format!("{}/v1/chat/completions", self.base_url())
+    }
+
+    /// `base` pointed at this server with no API key. `LlmConfig` only holds
+    /// `&'static str`, so the URL is leaked; fine for the lifetime of a test.
+    pub fn config(&self, base: &LlmConfig) -> LlmConfig {
+        self.config_for_path(base, "/v1/chat/completions")
+    }
+
+    pub fn config_for_path(&self, base: &LlmConfig, path: &str) -> LlmConfig {
+        let url: &'static str = Box::leak(format!("{}{}", self.base_url(), path).into_boxed_str());
+        LlmConfig {
+            base_url: url,
+            api_key_str: "",
+            ..base.clone()
+        }
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockLlmServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn handle_connection(mut socket: TcpStream, state: Arc<MockState>) -> std::io::Result<()> {
+    let (method, path, body) = read_request(&mut socket).await?;
+    let body_json = match serde_json::from_slice::<Value>(&body) {
+        Ok(body_json) => body_json,
+        Err(_) if body.is_empty() => Value::Null,
+        Err(_) => Value::String(String::from_utf8_lossy(&body).to_string()),
+    };
+    let model = body_json["model"]
+        .as_str()
+        .unwrap_or("mock-model")
+        .to_string();
+
+    state.requests.lock().unwrap().push(RecordedRequest {
+        method,
+        path: path.clone(),
+        body: body_json,
+    });
+
+    if path == "/api/chat" {
+        let reply = state.script.lock().unwrap().pop_front();
+        return write_ollama_reply(&mut socket, &model, reply).await;
+    }
+    if path == "/v1/messages" {
+        let reply = state.script.lock().unwrap().pop_front();
+        return write_anthropic_reply(&mut socket, &model, reply).await;
+    }
+    if path == "/v1/images/generations" {
+        let reply = state.script.lock().unwrap().pop_front();
+        return write_images_reply(&mut socket, reply).await;
+    }
+    if path == "/v1/embeddings" {
+        let reply = state.script.lock().unwrap().pop_front();
+        return write_embeddings_reply(&mut socket, &model, reply).await;
+    }
+    if path == "/v1/audio/transcriptions" || path == "/v1/audio/speech" {
+        let reply = state.script.lock().unwrap().pop_front();
+        return write_audio_reply(&mut socket, reply).await;
+    }
+    if path != "/v1/chat/completions" {
+        return write_json(&mut socket, 404, &json!({"error": "not found"}), None).await;
+    }
+
+    let reply = state.script.lock().unwrap().pop_front();
+    match reply {
+        Some(MockReply::Text(text)) => {
+            let message = json!({"role": "assistant", "content": text});
+            write_json(&mut socket, 200, &completion(&model, message, "stop"), None).await
+        }
+        Some(MockReply::ToolCalls(calls)) => {
+            let tool_calls: Vec<Value> = calls
+                .iter()
+                .enumerate()
+                .map(|(i, (name, arguments))| {
+                    let arguments = match arguments {
+                        Value::String(raw) => raw.clone(),
+                        arguments => arguments.to_string(),
+                    };
+                    json!({
+                        "id": format!("call_{}", i),
+                        "type": "function",
+                        "function": {"name": name, "arguments": arguments}
+                    })
+                })
+                .collect();
+            let message = json!({"role": "assistant", "content": null, "tool_calls": tool_calls});
+            write_json(
+                &mut socket,
+                200,
+                &completion(&model, message, "tool_calls"),
+                None,
+            )
+            .await
+        }
+        Some(MockReply::Stream(chunks)) => write_stream(&mut socket, &model, &chunks).await,
+        Some(MockReply::StreamEvents(events)) => write_stream_events(&mut socket, &events).await,
+        Some(MockReply::Error {
+            status,
+            body,
+            retry_after,
+        }) => {
+            let error = json!({"error": {"message": body}});
+            write_json(&mut socket, status, &error, retry_after.as_deref()).await
+        }
+        Some(MockReply::Images(_) | MockReply::Audio(_) | MockReply::Embeddings(_)) | None => {
+            let error = json!({"error": {"message": "mock script exhausted"}});
+            write_json(&mut socket, 500, &error, None).await
+        }
+    }
+}
+
+async fn write_embeddings_reply(
+    socket: &mut TcpStream,
+    model: &str,
+    reply: Option<MockReply>,
+) -> std::io::Result<()> {
+    match reply {
+        Some(MockReply::Embeddings(vectors)) => {
+            // Listed last-to-first so clients have to order by `index`.
+            let data: Vec<Value> = vectors
+                .iter()
+                .enumerate()
+                .rev()
+                .map(|(i, embedding)| {
+                    json!({"object": "embedding", "index": i, "embedding": embedding})
+                })
+                .collect();
+            let body = json!({
+                "object": "list",
+                "model": model,
+                "data": data,
+                "usage": {"prompt_tokens": 10, "total_tokens": 10}
+            });
+            write_json(socket, 200, &body, None).await
+        }
+        Some(MockReply::Error {
+            status,
+            body,
+            retry_after,
+        }) => {
+            let error = json!({"error": {"message": body}});
+            write_json(socket, status, &error, retry_after.as_deref()).await
+        }
+        _ => {
+            let error = json!({"error": {"message": "no embeddings scripted"}});
+            write_json(socket, 500, &error, None).await
+        }
+    }
+}
+
+async fn write_audio_reply(
+    socket: &mut TcpStream,
+    reply: Option<MockReply>,
+) -> std::io::Result<()> {
+    match reply {
+        Some(MockReply::Text(text)) => write_json(socket, 200, &json!({"text": text}), None).await,
+        Some(MockReply::Audio(audio)) => {
+            let head = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: audio/mpeg\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                audio.len()
+            );
+            socket.write_all(head.as_bytes()).await?;
+            socket.write_all(&audio).await?;
+            socket.shutdown().await
+        }
+        Some(MockReply::Error {
+            status,
+            body,
+            retry_after,
+        }) => {
+            let error = json!({"error": {"message": body}});
+            write_json(socket, status, &error, retry_after.as_deref()).await
+        }
+        _ => {
+            let error = json!({"error": {"message": "no audio reply scripted"}});
+            write_json(socket, 500, &error, None).await
+        }
+    }
+}
+
+async fn write_images_reply(
+    socket: &mut TcpStream,
+    reply: Option<MockReply>,
+) -> std::io::Result<()> {
+    match reply {
+        Some(MockReply::Images(images)) => {
+            let data: Vec<Value> = images
+                .iter()
+                .map(|image| {
+                    json!({"b64_json": base64::engine::general_purpose::STANDARD.encode(image)})
+                })
+                .collect();
+            write_json(socket, 200, &json!({"created": 0, "data": data}), None).await
+        }
+        Some(MockReply::Error {
+            status,
+            body,
+            retry_after,
+        }) => {
+            let error = json!({"error": {"message": body}});
+            write_json(socket, status, &error, retry_after.as_deref()).await
+        }
+        _ => {
+            let error = json!({"error": {"message": "no image reply scripted"}});
+            write_json(socket, 500, &error, None).await
+        }
+    }
+}
+
+async fn write_ollama_reply(
+    socket: &mut TcpStream,
+    model: &str,
+    reply: Option<MockReply>,
+) -> std::io::Result<()> {
+    match reply {
+        Some(MockReply::Text(text)) => {
+            let message = json!({"role": "assistant", "content": text});
+            write_json(socket, 200, &ollama_chunk(model, message, Some(5)), None).await
+        }
+        Some(MockReply::ToolCalls(calls)) => {
+            let tool_calls: Vec<Value> = calls
+                .iter()
+                .map(
+                    |(name, arguments)| json!({"function": {"name": name, "arguments": arguments}}),
+                )
+                .collect();
+            let message = json!({"role": "assistant", "content": "", "tool_calls": tool_calls});
+            write_json(socket, 200, &ollama_chunk(model, message, Some(5)), None).await
+        }
+        Some(MockReply::Stream(chunks)) => {
+            socket
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\ncontent-type: application/x-ndjson\r\nconnection: close\r\n\r\n",
+                )
+                .await?;
+            for chunk in &chunks {
+                let message = json!({"role": "assistant", "content": chunk});
+                socket
+                    .write_all(format!("{}\n", ollama_chunk(model, message, None)).as_bytes())
+                    .await?;
+            }
+            let last = ollama_chunk(
+                model,
+                json!({"role": "assistant", "content": ""}),
+                Some(chunks.len()),
+            );
+            socket.write_all(format!("{}\n", last).as_bytes()).await?;
+            socket.shutdown().await
+        }
+        Some(MockReply::Error {
+            status,
+            body,
+            retry_after,
+        }) => {
+            write_json(
+                socket,
+                status,
+                &json!({"error": body}),
+                retry_after.as_deref(),
+            )
+            .await
+        }
+        Some(
+            MockReply::StreamEvents(_)
+            | MockReply::Images(_)
+            | MockReply::Audio(_)
+            | MockReply::Embeddings(_),
+        )
+        | None => {
+            write_json(
+                socket,
+                500,
+                &json!({"error": "mock script exhausted"}),
+                None,
+            )
+            .await
+        }
+    }
+}
+
+async fn write_anthropic_reply(
+    socket: &mut TcpStream,
+    model: &str,
+    reply: Option<MockReply>,
+) -> std::io::Result<()> {
+    let (content, stop_reason) = match reply {
+        Some(MockReply::Text(text)) => (vec![json!({"type": "text", "text": text})], "end_turn"),
+        // Streaming isn't scripted for this route; the chunks come back as one block.
+        Some(MockReply::Stream(chunks)) => (
+            vec![json!({"type": "text", "text": chunks.concat()})],
+            "end_turn",
+        ),
+        Some(MockReply::ToolCalls(calls)) => (
+            calls
+                .iter()
+                .enumerate()
+                .map(|(i, (name, input))| {
+                    json!({"type": "tool_use", "id": format!("toolu_{}", i), "name": name, "input": input})
+                })
+                .collect(),
+            "tool_use",
+        ),
+        Some(MockReply::Error {
+            status,
+            body,
+            retry_after,
+        }) => {
+            let error = json!({"type": "error", "error": {"type": "api_error", "message": body}});
+            return write_json(socket, status, &error, retry_after.as_deref()).await;
+        }
+        Some(
+            MockReply::StreamEvents(_)
+            | MockReply::Images(_)
+            | MockReply::Audio(_)
+            | MockReply::Embeddings(_),
+        )
+        | None => {
+            let error = json!({"type": "error", "error": {"type": "api_error", "message": "mock script exhausted"}});
+            return write_json(socket, 500, &error, None).await;
+        }
+    };
+    let message = json!({
+        "id": "msg_mock",
+        "type": "message",
+        "role": "assistant",
+        "model": model,
+        "content": content,
+        "stop_reason": stop_reason,
+        "usage": {
+            "input_tokens": 10,
+            "output_tokens": 5,
+            "cache_creation_input_tokens": 0,
+            "cache_read_input_tokens": 4
+        }
+    });
+    write_json(socket, 200, &message, None).await
+}
+
+/// `/api/chat` record; `eval_count` is only set on the final (`done`) one.
+fn ollama_chunk(model: &str, message: Value, eval_count: Option<usize>) -> Value {
+    let mut chunk = json!({
+        "model": model,
+        "created_at": "1970-01-01T00:00:00Z",
+        "message": message,
+        "done": eval_count.is_some()
+    });
+    if let Some(eval_count) = eval_count {
+        chunk["done_reason"] = json!("stop");
+        chunk["prompt_eval_count"] = json!(10);
+        chunk["eval_count"] = json!(eval_count);
+    }
+    chunk
+}
+
+fn completion(model: &str, message: Value, finish_reason: &str) -> Value {
+    json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion",
+        "created": 0,
+        "model": model,
+        "choices": [{"index": 0, "message": message, "finish_reason": finish_reason}],
+        "usage": {"prompt_tokens": 10, "completion_tokens": 5, "total_tokens": 15}
+    })
+}
+
+async fn read_request(socket: &mut TcpStream) -> std::io::Result<(String, String, Vec<u8>)> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+    let content_length = head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+
+    let mut body = buf[header_end..].to_vec();
+    while body.len() < content_length {
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    Ok((method, path, body))
+}
+
+async fn write_json(
+    socket: &mut TcpStream,
+    status: u16,
+    body: &Value,
+    retry_after: Option<&str>,
+) -> std::io::Result<()> {
+    let body = body.to_string();
+    let retry_after = retry_after
+        .map(|value| format!("retry-after: {}\r\n", value))
+        .unwrap_or_default();
+    let response = format!(
+        "HTTP/1.1 {} {}\r\ncontent-type: application/json\r\n{}content-length: {}\r\nconnection: close\r\n\r\n{}",
+        status,
+        reason_phrase(status),
+        retry_after,
+        body.len(),
+        body
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
+
+async fn write_stream(
+    socket: &mut TcpStream,
+    model: &str,
+    chunks: &[String],
+) -> std::io::Result<()> {
+    socket
+        .write_all(
+            b"HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ncache-control: no-cache\r\nconnection: close\r\n\r\n",
+        )
+        .await?;
+    for chunk in chunks {
+        let event = json!({
+            "id": "chatcmpl-mock",
+            "object": "chat.completion.chunk",
+            "created": 0,
+            "model": model,
+            "choices": [{"index": 0, "delta": {"content": chunk}, "finish_reason": null}]
+        });
+        socket
+            .write_all(format!("data: {}\n\n", event).as_bytes())
+            .await?;
+    }
+    let last = json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion.chunk",
+        "created": 0,
+        "model": model,
+        "choices": [{"index": 0, "delta": {}, "finish_reason": "stop"}],
+        "usage": {"prompt_tokens": 10, "completion_tokens": chunks.len(), "total_tokens": 10 + chunks.len()}
+    });
+    socket
+        .write_all(format!("data: {}\n\ndata: [DONE]\n\n", last).as_bytes())
+        .await?;
+    socket.shutdown().await
+}
+
+async fn write_stream_events(socket: &mut TcpStream, events: &[Value]) -> std::io::Result<()> {
+    socket
+        .write_all(
+            b"HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ncache-control: no-cache\r\nconnection: close\r\n\r\n",
+        )
+        .await?;
+    for event in events {
+        socket
+            .write_all(format!("data: {}\n\n", event).as_bytes())
+            .await?;
+    }
+    socket.write_all(b"data: [DONE]\n\n").await?;
+    socket.shutdown().await
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
@@ -1,6 +1,26 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::agent::chat_agent::{CreateResult, ResultContent};
+use crate::msg_types::{llm_msg_types::LlmMessage, MultiModalContent, RequestUsage};
 
+pub mod anthropic;
+pub mod audio;
+pub mod cassette;
+pub mod embeddings;
+pub mod fallback;
+pub mod http;
+pub mod image_gen;
 pub mod llama;
+pub mod llamacpp;
+#[cfg(any(test, feature = "test-support"))]
+pub mod mock_server;
+pub mod ollama;
 pub mod openai;
 pub mod vision_llama;
 
@@ -10,61 +30,344 @@ pub enum AgentCapability {
     Vision,
     Audio,
     ImageGeneration,
+    ToolCalling,
+    Embedding,
+}
+
+/// The API a config speaks, which decides the client `client_for` builds.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum LlmBackend {
+    /// OpenAI's chat completions API and the many servers compatible with it.
+    OpenAi,
+    Anthropic,
+    Ollama,
+    /// OpenAI-compatible, plus llama.cpp's prompt cache.
+    LlamaCpp,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Clone, PartialEq)]
 pub struct LlmConfig {
     pub model: &'static str,
     pub base_url: &'static str,
+    pub backend: LlmBackend,
     pub context_size: usize,
     pub api_key_str: &'static str,
-    pub capabilities: AgentCapability,
+    pub capabilities: &'static [AgentCapability],
+    pub pricing: ModelPricing,
 }
 
-const TOGETHER_CONFIG: LlmConfig = LlmConfig {
+/// USD price per million tokens, used to turn `RequestUsage` into a cost.
+/// Providers without prompt caching use the prompt rate for both cache rates.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub struct ModelPricing {
+    pub prompt_per_mtok: f64,
+    pub completion_per_mtok: f64,
+    pub cache_read_per_mtok: f64,
+    pub cache_write_per_mtok: f64,
+}
+
+impl ModelPricing {
+    /// Self-hosted models: usage is still counted against token budgets, at no cost.
+    pub const FREE: ModelPricing = ModelPricing {
+        prompt_per_mtok: 0.0,
+        completion_per_mtok: 0.0,
+        cache_read_per_mtok: 0.0,
+        cache_write_per_mtok: 0.0,
+    };
+}
+
+impl LlmConfig {
+    pub fn supports(&self, capability: &AgentCapability) -> bool {
+        self.capabilities.contains(capability)
+    }
+
+    /// `prompt_tokens` includes cached tokens, which are billed at their own rates.
+    pub fn cost(&self, usage: &RequestUsage) -> f64 {
+        let uncached =
+            (usage.prompt_tokens - usage.cache_read_tokens - usage.cache_creation_tokens).max(0);
+        (uncached as f64 * self.pricing.prompt_per_mtok
+            + usage.cache_read_tokens as f64 * self.pricing.cache_read_per_mtok
+            + usage.cache_creation_tokens as f64 * self.pricing.cache_write_per_mtok
+            + usage.completion_tokens as f64 * self.pricing.completion_per_mtok)
+            / 1_000_000.0
+    }
+}
+
+pub const TOGETHER_CONFIG: LlmConfig = LlmConfig {
     model: "meta-llama/Meta-Llama-3.1-70B-Instruct-Turbo",
     context_size: 8192,
     base_url: "https://api.together.xyz/v1/chat/completions",
+    backend: LlmBackend::OpenAi,
     api_key_str: "TOGETHER_API_KEY",
-    capabilities: AgentCapability::Text,
+    capabilities: &[AgentCapability::Text, AgentCapability::ToolCalling],
+    pricing: ModelPricing {
+        prompt_per_mtok: 0.88,
+        completion_per_mtok: 0.88,
+        cache_read_per_mtok: 0.88,
+        cache_write_per_mtok: 0.88,
+    },
 };
 
-const TOGETHER_VISION_CONFIG: LlmConfig = LlmConfig {
+pub const TOGETHER_VISION_CONFIG: LlmConfig = LlmConfig {
     model: "meta-llama/Llama-3.2-90B-Vision-Instruct-Turbo",
     context_size: 16000,
     base_url: "https://api.together.xyz/v1/chat/completions",
+    backend: LlmBackend::OpenAi,
     api_key_str: "TOGETHER_API_KEY",
-    capabilities: AgentCapability::Vision,
+    capabilities: &[AgentCapability::Text, AgentCapability::Vision],
+    pricing: ModelPricing {
+        prompt_per_mtok: 1.2,
+        completion_per_mtok: 1.2,
+        cache_read_per_mtok: 1.2,
+        cache_write_per_mtok: 1.2,
+    },
 };
 
-const CODELLAMA_CONFIG: LlmConfig = LlmConfig {
+pub const CODELLAMA_CONFIG: LlmConfig = LlmConfig {
     model: "codellama/CodeLlama-34b-Instruct-hf",
     context_size: 8192,
     base_url: "https://api.together.xyz/v1/chat/completions",
+    backend: LlmBackend::OpenAi,
     api_key_str: "TOGETHER_API_KEY",
-    capabilities: AgentCapability::Text,
+    capabilities: &[AgentCapability::Text],
+    pricing: ModelPricing {
+        prompt_per_mtok: 0.78,
+        completion_per_mtok: 0.78,
+        cache_read_per_mtok: 0.78,
+        cache_write_per_mtok: 0.78,
+    },
 };
 
-const QWEN_CONFIG: LlmConfig = LlmConfig {
+pub const QWEN_CONFIG: LlmConfig = LlmConfig {
     model: "Qwen/Qwen2-72B-Instruct",
     context_size: 32000,
     base_url: "https://api.deepinfra.com/v1/openai/chat/completions",
+    backend: LlmBackend::OpenAi,
     api_key_str: "DEEPINFRA_API_KEY",
-    capabilities: AgentCapability::Text,
+    capabilities: &[AgentCapability::Text, AgentCapability::ToolCalling],
+    pricing: ModelPricing {
+        prompt_per_mtok: 0.35,
+        completion_per_mtok: 0.4,
+        cache_read_per_mtok: 0.35,
+        cache_write_per_mtok: 0.35,
+    },
 };
 
-const DEEPSEEK_CONFIG: LlmConfig = LlmConfig {
+pub const DEEPSEEK_CONFIG: LlmConfig = LlmConfig {
     model: "deepseek-coder",
     context_size: 16000,
     base_url: "https://api.deepseek.com/chat/completions",
+    backend: LlmBackend::OpenAi,
     api_key_str: "SEEK_API_KEY",
-    capabilities: AgentCapability::Text,
+    capabilities: &[AgentCapability::Text],
+    pricing: ModelPricing {
+        prompt_per_mtok: 0.14,
+        completion_per_mtok: 0.28,
+        cache_read_per_mtok: 0.14,
+        cache_write_per_mtok: 0.14,
+    },
 };
 
-const OPENAI_CONFIG: LlmConfig = LlmConfig {
+pub const OPENAI_CONFIG: LlmConfig = LlmConfig {
     model: "gpt-3.5-turbo",
     context_size: 16000,
     base_url: "https://api.openai.com/v1/chat/completions",
+    backend: LlmBackend::OpenAi,
+    api_key_str: "OPENAI_API_KEY",
+    capabilities: &[AgentCapability::Text, AgentCapability::ToolCalling],
+    pricing: ModelPricing {
+        prompt_per_mtok: 0.5,
+        completion_per_mtok: 1.5,
+        cache_read_per_mtok: 0.5,
+        cache_write_per_mtok: 0.5,
+    },
+};
+
+pub const ANTHROPIC_CONFIG: LlmConfig = LlmConfig {
+    model: "claude-3-5-sonnet-latest",
+    context_size: 200000,
+    base_url: "https://api.anthropic.com/v1/messages",
+    backend: LlmBackend::Anthropic,
+    api_key_str: "ANTHROPIC_API_KEY",
+    capabilities: &[
+        AgentCapability::Text,
+        AgentCapability::Vision,
+        AgentCapability::ToolCalling,
+    ],
+    pricing: ModelPricing {
+        prompt_per_mtok: 3.0,
+        completion_per_mtok: 15.0,
+        cache_read_per_mtok: 0.3,
+        cache_write_per_mtok: 3.75,
+    },
+};
+
+pub const OLLAMA_CONFIG: LlmConfig = LlmConfig {
+    model: "llama3.1",
+    context_size: 8192,
+    base_url: "http://localhost:11434/api/chat",
+    backend: LlmBackend::Ollama,
+    api_key_str: "",
+    capabilities: &[AgentCapability::Text, AgentCapability::ToolCalling],
+    pricing: ModelPricing::FREE,
+};
+
+pub const OLLAMA_VISION_CONFIG: LlmConfig = LlmConfig {
+    model: "llama3.2-vision",
+    context_size: 8192,
+    base_url: "http://localhost:11434/api/chat",
+    backend: LlmBackend::Ollama,
+    api_key_str: "",
+    capabilities: &[AgentCapability::Text, AgentCapability::Vision],
+    pricing: ModelPricing::FREE,
+};
+
+// llama.cpp serves whichever model it was started with and ignores `model`;
+// tool calling needs the server to run with `--jinja`.
+pub const LLAMACPP_CONFIG: LlmConfig = LlmConfig {
+    model: "local",
+    context_size: 8192,
+    base_url: "http://localhost:8080/v1/chat/completions",
+    backend: LlmBackend::LlamaCpp,
+    api_key_str: "",
+    capabilities: &[AgentCapability::Text, AgentCapability::ToolCalling],
+    pricing: ModelPricing::FREE,
+};
+
+// Billed per image rather than per token, so token pricing does not apply.
+pub const DALLE_CONFIG: LlmConfig = LlmConfig {
+    model: "dall-e-3",
+    context_size: 4000,
+    base_url: "https://api.openai.com/v1/images/generations",
+    backend: LlmBackend::OpenAi,
     api_key_str: "OPENAI_API_KEY",
-    capabilities: AgentCapability::Text,
+    capabilities: &[AgentCapability::ImageGeneration],
+    pricing: ModelPricing::FREE,
 };
+
+// Speech models are billed per minute of audio or per character, not per token.
+pub const WHISPER_CONFIG: LlmConfig = LlmConfig {
+    model: "whisper-1",
+    context_size: 0,
+    base_url: "https://api.openai.com/v1/audio/transcriptions",
+    backend: LlmBackend::OpenAi,
+    api_key_str: "OPENAI_API_KEY",
+    capabilities: &[AgentCapability::Audio],
+    pricing: ModelPricing::FREE,
+};
+
+pub const TTS_CONFIG: LlmConfig = LlmConfig {
+    model: "tts-1",
+    context_size: 4096,
+    base_url: "https://api.openai.com/v1/audio/speech",
+    backend: LlmBackend::OpenAi,
+    api_key_str: "OPENAI_API_KEY",
+    capabilities: &[AgentCapability::Audio],
+    pricing: ModelPricing::FREE,
+};
+
+pub const OPENAI_EMBEDDING_CONFIG: LlmConfig = LlmConfig {
+    model: "text-embedding-3-small",
+    context_size: 8191,
+    base_url: "https://api.openai.com/v1/embeddings",
+    backend: LlmBackend::OpenAi,
+    api_key_str: "OPENAI_API_KEY",
+    capabilities: &[AgentCapability::Embedding],
+    pricing: ModelPricing {
+        prompt_per_mtok: 0.02,
+        completion_per_mtok: 0.0,
+        cache_read_per_mtok: 0.02,
+        cache_write_per_mtok: 0.02,
+    },
+};
+
+/// Tool call arguments as the object Anthropic and Ollama require in history.
+/// Arguments the model sent malformed are kept as a string; they go back under
+/// `_raw` so the model can still see what it wrote.
+pub(crate) fn arguments_object(arguments: &Value) -> Value {
+    match arguments {
+        Value::Object(_) => arguments.clone(),
+        other => serde_json::json!({ "_raw": other }),
+    }
+}
+
+/// Everything a backend needs for one chat completion call.
+#[derive(Debug, Clone)]
+pub struct CreateRequest {
+    pub messages: Vec<LlmMessage>,
+    pub tools: Vec<Value>,
+    pub json_output: bool,
+    pub max_tokens: u16,
+    pub extra_create_args: HashMap<String, Value>,
+}
+
+impl CreateRequest {
+    pub fn new(messages: Vec<LlmMessage>, max_tokens: u16) -> Self {
+        CreateRequest {
+            messages,
+            tools: Vec::new(),
+            json_output: false,
+            max_tokens,
+            extra_create_args: HashMap::new(),
+        }
+    }
+
+    pub fn has_images(&self) -> bool {
+        self.messages.iter().any(|msg| {
+            matches!(
+                msg,
+                LlmMessage::UserMessage(user)
+                    if user.content.iter().any(|part| matches!(part, MultiModalContent::Image(_)))
+            )
+        })
+    }
+
+    /// Capabilities a model must have to serve this request, beyond plain text.
+    pub fn required_capabilities(&self) -> Vec<AgentCapability> {
+        let mut required = Vec::new();
+        if self.has_images() {
+            required.push(AgentCapability::Vision);
+        }
+        if !self.tools.is_empty() {
+            required.push(AgentCapability::ToolCalling);
+        }
+        required
+    }
+}
+
+/// Common interface of the chat backends, so agents and composite clients can
+/// hold any of them behind `Arc<dyn LlmClient>`.
+#[async_trait]
+pub trait LlmClient: Send + Sync {
+    fn llm_config(&self) -> &LlmConfig;
+
+    async fn create(&self, request: &CreateRequest) -> anyhow::Result<CreateResult>;
+
+    /// Sends text deltas to `tx` as they arrive and returns the assembled result.
+    /// Backends without native streaming send the whole reply as one delta.
+    async fn create_stream(
+        &self,
+        request: &CreateRequest,
+        tx: mpsc::Sender<String>,
+    ) -> anyhow::Result<CreateResult> {
+        let result = self.create(request).await?;
+        if let ResultContent::TextContent(text) = &result.content {
+            let _ = tx.send(text.text.clone()).await;
+        }
+        Ok(result)
+    }
+
+    fn supports(&self, capability: &AgentCapability) -> bool {
+        self.llm_config().supports(capability)
+    }
+}
+
+/// Builds the chat client for `llm_config.backend`.
+pub fn client_for(llm_config: LlmConfig) -> Arc<dyn LlmClient> {
+    match llm_config.backend {
+        LlmBackend::OpenAi => Arc::new(openai::OpenAiClient::new(llm_config)),
+        LlmBackend::Anthropic => Arc::new(anthropic::AnthropicClient::new(llm_config)),
+        LlmBackend::Ollama => Arc::new(ollama::OllamaClient::new(llm_config)),
+        LlmBackend::LlamaCpp => Arc::new(llamacpp::LlamaCppClient::new(llm_config)),
+    }
+}
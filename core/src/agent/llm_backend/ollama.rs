@@ -0,0 +1,394 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+use crate::agent::chat_agent::{CreateResult, ResultContent};
+use crate::agent::llm_backend::http::{bearer_headers, LineStream, LlmHttpClient};
+use crate::agent::llm_backend::{arguments_object, CreateRequest, LlmClient, LlmConfig};
+use crate::msg_types::chat_msg_types::AssistantMessageContent;
+use crate::msg_types::{llm_msg_types::LlmMessage, FinishReason, MultiModalContent, RequestUsage};
+use crate::tool_types::FunctionCallInput;
+
+/// Native client for Ollama's `/api/chat`. Images travel as bare base64 in the
+/// message's `images` field, tool call arguments are JSON objects rather than
+/// strings, and streaming replies are newline-delimited JSON.
+#[derive(Clone)]
+pub struct OllamaClient {
+    pub llm_config: LlmConfig,
+    http: LlmHttpClient,
+}
+
+impl OllamaClient {
+    pub fn new(llm_config: LlmConfig) -> Self {
+        OllamaClient {
+            llm_config,
+            http: LlmHttpClient::shared(),
+        }
+    }
+
+    pub fn with_http(llm_config: LlmConfig, http: LlmHttpClient) -> Self {
+        OllamaClient { llm_config, http }
+    }
+}
+
+#[async_trait]
+impl LlmClient for OllamaClient {
+    fn llm_config(&self) -> &LlmConfig {
+        &self.llm_config
+    }
+
+    async fn create(&self, request: &CreateRequest) -> anyhow::Result<CreateResult> {
+        let headers = bearer_headers(&self.llm_config)?;
+        let body_json = ollama_request_body(&self.llm_config, request, false);
+
+        let response_body = self
+            .http
+            .post_json(self.llm_config.base_url, headers, &body_json)
+            .await?;
+        let response = serde_json::from_str::<OllamaResponse>(&response_body)?;
+        if let Some(error) = response.error {
+            return Err(anyhow::anyhow!("Ollama error: {}", error));
+        }
+
+        let usage = usage(&response);
+        let message = response.message.unwrap_or_default();
+        ollama_result(
+            message.content,
+            message.tool_calls,
+            response.done_reason,
+            usage,
+            &self.llm_config,
+        )
+    }
+
+    async fn create_stream(
+        &self,
+        request: &CreateRequest,
+        tx: mpsc::Sender<String>,
+    ) -> anyhow::Result<CreateResult> {
+        let headers = bearer_headers(&self.llm_config)?;
+        let body_json = ollama_request_body(&self.llm_config, request, true);
+
+        let response = self
+            .http
+            .post_with_retry(self.llm_config.base_url, headers, &body_json)
+            .await?;
+        let mut lines = LineStream::new(response);
+
+        let mut content = String::new();
+        let mut tool_calls = Vec::new();
+        let mut done_reason = None;
+        let mut usage_total = RequestUsage::default();
+        while let Some(line) = lines.next_line().await? {
+            if line.is_empty() {
+                continue;
+            }
+            let chunk = serde_json::from_str::<OllamaResponse>(&line)?;
+            if let Some(error) = chunk.error {
+                return Err(anyhow::anyhow!("Ollama error: {}", error));
+            }
+            if let Some(message) = chunk.message.clone() {
+                if !message.content.is_empty() {
+                    content.push_str(&message.content);
+                    let _ = tx.send(message.content).await;
+                }
+                tool_calls.extend(message.tool_calls);
+            }
+            if chunk.done {
+                done_reason = chunk.done_reason.clone();
+                usage_total = usage(&chunk);
+                break;
+            }
+        }
+
+        ollama_result(
+            content,
+            tool_calls,
+            done_reason,
+            usage_total,
+            &self.llm_config,
+        )
+    }
+}
+
+pub fn ollama_request_body(llm_config: &LlmConfig, request: &CreateRequest, stream: bool) -> Value {
+    let mut body_json = json!({
+        "model": llm_config.model,
+        "messages": ollama_messages(&request.messages),
+        "stream": stream,
+        "options": {
+            "num_predict": request.max_tokens,
+            "temperature": 0.3
+        }
+    });
+    if !request.tools.is_empty() {
+        body_json["tools"] = request
+            .tools
+            .iter()
+            .map(|tool| json!({"type": "function", "function": tool}))
+            .collect();
+    }
+    if request.json_output {
+        body_json["format"] = json!("json");
+    }
+    for (key, value) in &request.extra_create_args {
+        body_json[key] = value.clone();
+    }
+    body_json
+}
+
+pub fn ollama_messages(messages: &[LlmMessage]) -> Vec<Value> {
+    let mut out = Vec::new();
+    for message in messages {
+        match message {
+            LlmMessage::SystemMessage(msg) => {
+                out.push(json!({"role": "system", "content": msg.content.text}));
+            }
+            // Ollama takes one text per message, with the images alongside it.
+            LlmMessage::UserMessage(msg) => {
+                let mut text = Vec::new();
+                let mut images = Vec::new();
+                for part in &msg.content {
+                    match part {
+                        MultiModalContent::Text(t) => text.push(t.text.as_str()),
+                        MultiModalContent::Image(image) => images.push(image.to_base64()),
+                    }
+                }
+                let mut user = json!({"role": "user", "content": text.join("\n")});
+                if !images.is_empty() {
+                    user["images"] = json!(images);
+                }
+                out.push(user);
+            }
+            LlmMessage::AssistantMessage(msg) => match &msg.content {
+                AssistantMessageContent::TextContent(text) => {
+                    out.push(json!({"role": "assistant", "content": text.text}));
+                }
+                AssistantMessageContent::FunctionCallInput(call) => {
+                    out.push(json!({
+                        "role": "assistant",
+                        "content": "",
+                        "tool_calls": [{
+                            "function": {
+                                "name": call.function_name,
+                                "arguments": arguments_object(&call.arguments_obj)
+                            }
+                        }]
+                    }));
+                }
+            },
+            // Ollama pairs tool results with calls by position, not by id.
+            LlmMessage::FunctionExecutionResultMessage(msg) => {
+                for result in &msg.content {
+                    out.push(json!({"role": "tool", "content": result.content}));
+                }
+            }
+        }
+    }
+    out
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OllamaResponse {
+    pub message: Option<OllamaMessage>,
+    #[serde(default)]
+    pub done: bool,
+    pub done_reason: Option<String>,
+    #[serde(default)]
+    pub prompt_eval_count: i32,
+    #[serde(default)]
+    pub eval_count: i32,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct OllamaMessage {
+    #[serde(default)]
+    pub content: String,
+    #[serde(default)]
+    pub tool_calls: Vec<OllamaToolCall>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OllamaToolCall {
+    pub function: OllamaFunctionCall,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OllamaFunctionCall {
+    pub name: String,
+    pub arguments: Value,
+}
+
+fn usage(response: &OllamaResponse) -> RequestUsage {
+    RequestUsage {
+        prompt_tokens: response.prompt_eval_count,
+        completion_tokens: response.eval_count,
+        ..Default::default()
+    }
+}
+
+fn ollama_result(
+    content: String,
+    tool_calls: Vec<OllamaToolCall>,
+    done_reason: Option<String>,
+    usage: RequestUsage,
+    llm_config: &LlmConfig,
+) -> anyhow::Result<CreateResult> {
+    let calls = tool_calls
+        .into_iter()
+        .map(|tool_call| {
+            // Older Ollama releases send the arguments as a JSON string. Malformed
+            // ones stay a string, so `Tool::run` reports them to the model.
+            let arguments_obj = match tool_call.function.arguments {
+                Value::String(raw) => {
+                    serde_json::from_str::<Value>(&raw).unwrap_or(Value::String(raw))
+                }
+                other => other,
+            };
+            FunctionCallInput {
+                id: format!("call_{}", uuid::Uuid::new_v4().simple()),
+                function_name: tool_call.function.name,
+                arguments_obj,
+                return_type: "".to_string(),
+            }
+        })
+        .collect::<Vec<FunctionCallInput>>();
+    let (content, finish_reason) = if calls.is_empty() {
+        (
+            ResultContent::TextContent(content.into()),
+            FinishReason::from_api(done_reason.as_deref().unwrap_or("stop")),
+        )
+    } else {
+        (
+            ResultContent::FunctionCallContent(calls),
+            FinishReason::FunctionCall,
+        )
+    };
+
+    Ok(CreateResult {
+        finish_reason,
+        content,
+        usage,
+        served_by: llm_config.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::llm_backend::mock_server::{MockLlmServer, MockReply};
+    use crate::agent::llm_backend::{OLLAMA_CONFIG, OLLAMA_VISION_CONFIG};
+    use crate::msg_types::{AgentId, ImageContent};
+
+    #[tokio::test]
+    async fn test_ollama_image_input_and_text_reply() {
+        let image =
+            ImageContent::from_bytes(vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]).unwrap();
+        let server = MockLlmServer::start(vec![MockReply::text("a tiny image")])
+            .await
+            .unwrap();
+        let client = OllamaClient::new(server.config_for_path(&OLLAMA_VISION_CONFIG, "/api/chat"));
+
+        let request = CreateRequest::new(
+            vec![LlmMessage::user_image(image.clone(), AgentId::new(None))],
+            100,
+        );
+        let result = client.create(&request).await.unwrap();
+
+        assert!(matches!(
+            result.content,
+            ResultContent::TextContent(ref text) if text.text == "a tiny image"
+        ));
+        assert_eq!(result.usage.completion_tokens, 5);
+        let sent = &server.requests()[0].body;
+        assert_eq!(sent["stream"], false);
+        assert_eq!(sent["messages"][0]["images"][0], "iVBORw0KGgo=");
+    }
+
+    #[tokio::test]
+    async fn test_ollama_tool_call() {
+        let server = MockLlmServer::start(vec![MockReply::tool_call(
+            "get_current_weather",
+            json!({"location": "Paris"}),
+        )])
+        .await
+        .unwrap();
+        let client = OllamaClient::new(server.config_for_path(&OLLAMA_CONFIG, "/api/chat"));
+
+        let mut request = CreateRequest::new(
+            vec![LlmMessage::user_text(
+                "Weather in Paris?",
+                AgentId::new(None),
+            )],
+            100,
+        );
+        request.tools = vec![json!({"name": "get_current_weather", "parameters": {}})];
+        let result = client.create(&request).await.unwrap();
+
+        match result.content {
+            ResultContent::FunctionCallContent(calls) => {
+                let call = &calls[0];
+                assert_eq!(call.function_name, "get_current_weather");
+                assert_eq!(call.arguments_obj["location"], "Paris");
+                assert!(call.id.starts_with("call_"));
+            }
+            other => panic!("expected a function call, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ollama_malformed_arguments_are_kept() {
+        let server = MockLlmServer::start(vec![MockReply::tool_call(
+            "get_current_weather",
+            json!(r#"{"location": "#),
+        )])
+        .await
+        .unwrap();
+        let client = OllamaClient::new(server.config_for_path(&OLLAMA_CONFIG, "/api/chat"));
+
+        let request = CreateRequest::new(
+            vec![LlmMessage::user_text(
+                "Weather in Paris?",
+                AgentId::new(None),
+            )],
+            100,
+        );
+        let result = client.create(&request).await.unwrap();
+
+        match result.content {
+            ResultContent::FunctionCallContent(calls) => {
+                assert_eq!(calls[0].arguments_obj, json!(r#"{"location": "#));
+            }
+            other => panic!("expected a function call, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ollama_stream() {
+        let server = MockLlmServer::start(vec![MockReply::Stream(vec![
+            "Hel".to_string(),
+            "lo".to_string(),
+        ])])
+        .await
+        .unwrap();
+        let client = OllamaClient::new(server.config_for_path(&OLLAMA_CONFIG, "/api/chat"));
+        let (tx, mut rx) = mpsc::channel(8);
+
+        let request =
+            CreateRequest::new(vec![LlmMessage::user_text("hi", AgentId::new(None))], 100);
+        let result = client.create_stream(&request, tx).await.unwrap();
+
+        let mut deltas = Vec::new();
+        while let Some(delta) = rx.recv().await {
+            deltas.push(delta);
+        }
+        assert_eq!(deltas, vec!["Hel", "lo"]);
+        assert!(matches!(
+            result.content,
+            ResultContent::TextContent(ref text) if text.text == "Hello"
+        ));
+        assert_eq!(result.usage.completion_tokens, 2);
+    }
+}
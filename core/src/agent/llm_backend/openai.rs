@@ -1,29 +1,403 @@
 use async_openai::types::{
     ChatCompletionToolType, CreateChatCompletionResponse, FinishReason, Role,
 };
-use reqwest::{
-    header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE, USER_AGENT},
-    ClientBuilder,
-};
-use serde_json::Value;
+use async_trait::async_trait;
+use reqwest::Response;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use tokio::sync::mpsc;
 
-use crate::agent::llm_backend::{LlmConfig, OPENAI_CONFIG};
-use crate::msg_types::{llm_msg_types::LlmMessage, RequestUsage, AgentId};
+use crate::agent::chat_agent::{CreateResult, ResultContent};
+use crate::agent::llm_backend::http::{bearer_headers, LineStream, LlmHttpClient};
+use crate::agent::llm_backend::{CreateRequest, LlmClient, LlmConfig, OPENAI_CONFIG};
+use crate::msg_types::chat_msg_types::AssistantMessageContent;
+use crate::msg_types::{
+    llm_msg_types::LlmMessage, AgentId, FinishReason as ResultFinishReason, MultiModalContent,
+    RequestUsage,
+};
 use crate::tool_types::FunctionCallInput;
 
+/// Client for any endpoint speaking the OpenAI chat completions schema
+/// (OpenAI, Together, DeepInfra, DeepSeek).
+#[derive(Clone)]
+pub struct OpenAiClient {
+    pub llm_config: LlmConfig,
+    http: LlmHttpClient,
+}
+
+impl OpenAiClient {
+    pub fn new(llm_config: LlmConfig) -> Self {
+        OpenAiClient {
+            llm_config,
+            http: LlmHttpClient::shared(),
+        }
+    }
+
+    pub fn with_http(llm_config: LlmConfig, http: LlmHttpClient) -> Self {
+        OpenAiClient { llm_config, http }
+    }
+}
+
+#[async_trait]
+impl LlmClient for OpenAiClient {
+    fn llm_config(&self) -> &LlmConfig {
+        &self.llm_config
+    }
+
+    async fn create(&self, request: &CreateRequest) -> anyhow::Result<CreateResult> {
+        let headers = bearer_headers(&self.llm_config)?;
+        let body_json = openai_request_body(&self.llm_config, request);
+
+        let response_body = self
+            .http
+            .post_json(self.llm_config.base_url, headers, &body_json)
+            .await?;
+
+        parse_openai_response(&response_body, &self.llm_config)
+    }
+
+    async fn create_stream(
+        &self,
+        request: &CreateRequest,
+        tx: mpsc::Sender<String>,
+    ) -> anyhow::Result<CreateResult> {
+        let headers = bearer_headers(&self.llm_config)?;
+        let mut body_json = openai_request_body(&self.llm_config, request);
+        body_json["stream"] = json!(true);
+        body_json["stream_options"] = json!({"include_usage": true});
+
+        let response = self
+            .http
+            .post_with_retry(self.llm_config.base_url, headers, &body_json)
+            .await?;
+
+        read_openai_stream(response, &self.llm_config, tx).await
+    }
+}
+
+pub fn openai_request_body(llm_config: &LlmConfig, request: &CreateRequest) -> Value {
+    let mut body_json = json!({
+        "model": llm_config.model,
+        "messages": openai_messages(&request.messages),
+        "max_tokens": request.max_tokens,
+        "temperature": 0.3
+    });
+    if !request.tools.is_empty() {
+        body_json["tools"] = request
+            .tools
+            .iter()
+            .map(|tool| json!({"type": "function", "function": tool}))
+            .collect();
+    }
+    if request.json_output {
+        body_json["response_format"] = json!({"type": "json_object"});
+    }
+    for (key, value) in &request.extra_create_args {
+        body_json[key] = value.clone();
+    }
+    body_json
+}
+
+/// Converts the conversation into OpenAI `messages`; each function result becomes
+/// its own `tool` message so it pairs with the assistant's `tool_calls` by id.
+pub fn openai_messages(messages: &[LlmMessage]) -> Vec<Value> {
+    let mut out = Vec::new();
+    for message in messages {
+        match message {
+            LlmMessage::SystemMessage(msg) => {
+                out.push(json!({"role": "system", "content": msg.content.text}));
+            }
+            LlmMessage::UserMessage(msg) => match msg.content.as_slice() {
+                [MultiModalContent::Text(text)] => {
+                    out.push(json!({"role": "user", "content": text.text}));
+                }
+                parts => {
+                    let content: Vec<Value> = parts
+                        .iter()
+                        .map(|part| match part {
+                            MultiModalContent::Text(text) => {
+                                json!({"type": "text", "text": text.text})
+                            }
+                            MultiModalContent::Image(image) => {
+                                json!({"type": "image_url", "image_url": {"url": image.to_data_uri()}})
+                            }
+                        })
+                        .collect();
+                    out.push(json!({"role": "user", "content": content}));
+                }
+            },
+            LlmMessage::AssistantMessage(msg) => match &msg.content {
+                AssistantMessageContent::TextContent(text) => {
+                    out.push(json!({"role": "assistant", "content": text.text}));
+                }
+                AssistantMessageContent::FunctionCallInput(call) => {
+                    out.push(json!({
+                        "role": "assistant",
+                        "content": null,
+                        "tool_calls": [{
+                            "id": call.id,
+                            "type": "function",
+                            "function": {
+                                "name": call.function_name,
+                                "arguments": call.arguments_obj.to_string()
+                            }
+                        }]
+                    }));
+                }
+            },
+            LlmMessage::FunctionExecutionResultMessage(msg) => {
+                for result in &msg.content {
+                    out.push(json!({
+                        "role": "tool",
+                        "tool_call_id": result.call_id,
+                        "content": result.content
+                    }));
+                }
+            }
+        }
+    }
+    out
+}
+
+// Lenient mirror of the chat completion response: compatible servers often omit
+// `id`/`created` or report non-standard finish reasons such as `eos`.
+#[derive(Debug, Deserialize)]
+pub struct OpenAiResponse {
+    pub choices: Vec<OpenAiChoice>,
+    pub usage: Option<OpenAiUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenAiChoice {
+    pub message: OpenAiResponseMessage,
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenAiResponseMessage {
+    pub content: Option<String>,
+    #[serde(default)]
+    pub tool_calls: Vec<OpenAiToolCall>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenAiToolCall {
+    #[serde(default)]
+    pub id: String,
+    pub function: OpenAiFunctionCall,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenAiFunctionCall {
+    pub name: String,
+    pub arguments: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenAiUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub prompt_tokens_details: Option<OpenAiPromptTokensDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenAiPromptTokensDetails {
+    #[serde(default)]
+    pub cached_tokens: u64,
+}
+
+pub fn parse_openai_response(
+    response_body: &str,
+    llm_config: &LlmConfig,
+) -> anyhow::Result<CreateResult> {
+    let raw_output = serde_json::from_str::<OpenAiResponse>(response_body)?;
+    openai_result(raw_output, llm_config)
+}
+
+fn openai_result(
+    raw_output: OpenAiResponse,
+    llm_config: &LlmConfig,
+) -> anyhow::Result<CreateResult> {
+    let choice = raw_output
+        .choices
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Response contained no choices"))?;
+
+    let usage = raw_output
+        .usage
+        .map(|u| RequestUsage {
+            prompt_tokens: u.prompt_tokens as i32,
+            completion_tokens: u.completion_tokens as i32,
+            cache_read_tokens: u
+                .prompt_tokens_details
+                .map(|d| d.cached_tokens as i32)
+                .unwrap_or(0),
+            ..Default::default()
+        })
+        .unwrap_or_default();
+
+    let function_calls = choice
+        .message
+        .tool_calls
+        .into_iter()
+        .map(|tool_call| {
+            // Malformed arguments stay as the raw string, so the call still reaches
+            // `Tool::run` and the model gets an error result back.
+            let arguments_obj = serde_json::from_str::<Value>(&tool_call.function.arguments)
+                .unwrap_or(Value::String(tool_call.function.arguments));
+            let id = if tool_call.id.is_empty() {
+                format!("call_{}", uuid::Uuid::new_v4().simple())
+            } else {
+                tool_call.id
+            };
+            FunctionCallInput {
+                id,
+                function_name: tool_call.function.name,
+                arguments_obj,
+                return_type: "".to_string(),
+            }
+        })
+        .collect::<Vec<FunctionCallInput>>();
+
+    let (content, finish_reason) = if function_calls.is_empty() {
+        (
+            ResultContent::TextContent(choice.message.content.unwrap_or_default().into()),
+            ResultFinishReason::from_api(choice.finish_reason.as_deref().unwrap_or("stop")),
+        )
+    } else {
+        (
+            ResultContent::FunctionCallContent(function_calls),
+            ResultFinishReason::FunctionCall,
+        )
+    };
+
+    Ok(CreateResult {
+        finish_reason,
+        content,
+        usage,
+        served_by: llm_config.clone(),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiStreamChunk {
+    #[serde(default)]
+    choices: Vec<OpenAiStreamChoice>,
+    usage: Option<OpenAiUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiStreamChoice {
+    #[serde(default)]
+    delta: OpenAiDelta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct OpenAiDelta {
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<OpenAiToolCallDelta>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiToolCallDelta {
+    #[serde(default)]
+    index: usize,
+    id: Option<String>,
+    function: Option<OpenAiFunctionDelta>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiFunctionDelta {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+/// Reads a `stream: true` chat completion (server-sent events), forwarding content
+/// deltas to `tx` and stitching tool call fragments back together by index.
+pub async fn read_openai_stream(
+    response: Response,
+    llm_config: &LlmConfig,
+    tx: mpsc::Sender<String>,
+) -> anyhow::Result<CreateResult> {
+    let mut lines = LineStream::new(response);
+    let mut content = String::new();
+    let mut tool_calls: BTreeMap<usize, OpenAiToolCall> = BTreeMap::new();
+    let mut finish_reason = None;
+    let mut usage = None;
+
+    while let Some(line) = lines.next_line().await? {
+        let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+            continue;
+        };
+        if data == "[DONE]" {
+            break;
+        }
+        let chunk = serde_json::from_str::<OpenAiStreamChunk>(data)?;
+        if chunk.usage.is_some() {
+            usage = chunk.usage;
+        }
+        for choice in chunk.choices {
+            if let Some(delta) = choice.delta.content.filter(|d| !d.is_empty()) {
+                content.push_str(&delta);
+                let _ = tx.send(delta).await;
+            }
+            for fragment in choice.delta.tool_calls {
+                let call = tool_calls
+                    .entry(fragment.index)
+                    .or_insert_with(|| OpenAiToolCall {
+                        id: String::new(),
+                        function: OpenAiFunctionCall {
+                            name: String::new(),
+                            arguments: String::new(),
+                        },
+                    });
+                if let Some(id) = fragment.id {
+                    call.id = id;
+                }
+                if let Some(function) = fragment.function {
+                    call.function
+                        .name
+                        .push_str(&function.name.unwrap_or_default());
+                    call.function
+                        .arguments
+                        .push_str(&function.arguments.unwrap_or_default());
+                }
+            }
+            if choice.finish_reason.is_some() {
+                finish_reason = choice.finish_reason;
+            }
+        }
+    }
+
+    // Fragments that never carried a function name cannot be dispatched.
+    let tool_calls = tool_calls
+        .into_values()
+        .filter(|call| !call.function.name.is_empty())
+        .collect();
+    let raw_output = OpenAiResponse {
+        choices: vec![OpenAiChoice {
+            message: OpenAiResponseMessage {
+                content: Some(content),
+                tool_calls,
+            },
+            finish_reason,
+        }],
+        usage,
+    };
+    openai_result(raw_output, llm_config)
+}
+
 pub async fn chat_wrapper_openai(
     llm_config: &LlmConfig,
     system_prompt: &str,
     input: &str,
     max_token: u16,
 ) -> anyhow::Result<(LlmMessage, RequestUsage)> {
-    let mut headers = HeaderMap::new();
-    let api_key = std::env::var(llm_config.api_key_str)?;
-    let bearer_token = format!("Bearer {}", api_key);
-
-    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-    headers.insert(USER_AGENT, HeaderValue::from_static("MyClient/1.0.0"));
-    headers.insert(AUTHORIZATION, HeaderValue::from_str(&bearer_token)?);
+    let headers = bearer_headers(llm_config)?;
 
     let messages = serde_json::json!([
         {"role": "system", "content": system_prompt},
@@ -39,13 +413,11 @@ pub async fn chat_wrapper_openai(
         "temperature": 0.3
     });
 
-    let body = serde_json::to_vec(&body_json)?;
-
-    let client = ClientBuilder::new().default_headers(headers).build()?;
-
-    match client.post(uri).body(body.clone()).send().await {
-        Ok(chat) => {
-            let response_body = chat.text().await?;
+    match LlmHttpClient::shared()
+        .post_json(uri, headers, &body_json)
+        .await
+    {
+        Ok(response_body) => {
             println!("coding response_body: {:?}", response_body.clone());
 
             let raw_output: CreateChatCompletionResponse =
@@ -61,10 +433,12 @@ pub async fn chat_wrapper_openai(
                     .map(|u| RequestUsage {
                         prompt_tokens: u.prompt_tokens as i32,
                         completion_tokens: u.completion_tokens as i32,
+                        ..Default::default()
                     })
                     .unwrap_or(RequestUsage {
                         prompt_tokens: 0,
                         completion_tokens: 0,
+                        ..Default::default()
                     });
 
                 let llm_message = LlmMessage::assistant_text(
@@ -76,7 +450,7 @@ pub async fn chat_wrapper_openai(
                 Err(anyhow::anyhow!("Could not convert to LlmMessage"))
             }
         }
-        Err(_e) => Err(anyhow::anyhow!("Failed to get reply from OpenAI: {:?}", _e)),
+        Err(e) => Err(anyhow::Error::new(e).context("Failed to get reply from OpenAI")),
     }
 }
 pub async fn chat_inner_async_wrapper(
@@ -86,13 +460,7 @@ pub async fn chat_inner_async_wrapper(
     input: &str,
     max_token: u16,
 ) -> anyhow::Result<(LlmMessage, RequestUsage)> {
-    let mut headers = HeaderMap::new();
-    let api_key = std::env::var(llm_config.api_key_str)?;
-    let bearer_token = format!("Bearer {}", api_key);
-
-    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-    headers.insert(USER_AGENT, HeaderValue::from_static("MyClient/1.0.0"));
-    headers.insert(AUTHORIZATION, HeaderValue::from_str(&bearer_token)?);
+    let headers = bearer_headers(llm_config)?;
 
     let messages = serde_json::json!([
         {"role": "system", "content": system_prompt},
@@ -110,13 +478,11 @@ pub async fn chat_inner_async_wrapper(
         "temperature": 0.3
     });
 
-    let body = serde_json::to_vec(&body_json)?;
-
-    let client = ClientBuilder::new().default_headers(headers).build()?;
-
-    match client.post(uri).body(body.clone()).send().await {
-        Ok(chat) => {
-            let response_body = chat.text().await?;
+    match LlmHttpClient::shared()
+        .post_json(uri, headers, &body_json)
+        .await
+    {
+        Ok(response_body) => {
             println!("coding response_body: {:?}", response_body.clone());
 
             let raw_output: CreateChatCompletionResponse =
@@ -141,6 +507,7 @@ pub async fn chat_inner_async_wrapper(
                                         serde_json::from_str::<Value>(&tool_call.function.arguments)
                                             .ok()
                                             .map(|arguments_obj| FunctionCallInput {
+                                                id: tool_call.id.clone(),
                                                 function_name: tool_call.function.name.clone(),
                                                 arguments_obj,
                                                 return_type: "".to_string(),
@@ -159,10 +526,12 @@ pub async fn chat_inner_async_wrapper(
                                             .map(|u| RequestUsage {
                                                 prompt_tokens: u.prompt_tokens as i32,
                                                 completion_tokens: u.completion_tokens as i32,
+                                                ..Default::default()
                                             })
                                             .unwrap_or(RequestUsage {
                                                 prompt_tokens: 0,
                                                 completion_tokens: 0,
+                                                ..Default::default()
                                             }),
                                     ));
                                 }
@@ -185,10 +554,12 @@ pub async fn chat_inner_async_wrapper(
                                     .map(|u| RequestUsage {
                                         prompt_tokens: u.prompt_tokens as i32,
                                         completion_tokens: u.completion_tokens as i32,
+                                        ..Default::default()
                                     })
                                     .unwrap_or(RequestUsage {
                                         prompt_tokens: 0,
                                         completion_tokens: 0,
+                                        ..Default::default()
                                     }),
                             ));
                         } else {
@@ -206,10 +577,12 @@ pub async fn chat_inner_async_wrapper(
                             .map(|u| RequestUsage {
                                 prompt_tokens: u.prompt_tokens as i32,
                                 completion_tokens: u.completion_tokens as i32,
+                                ..Default::default()
                             })
                             .unwrap_or(RequestUsage {
                                 prompt_tokens: 0,
                                 completion_tokens: 0,
+                                ..Default::default()
                             }),
                     ));
                 }
@@ -217,7 +590,7 @@ pub async fn chat_inner_async_wrapper(
 
             Err(anyhow::anyhow!("Could not convert to LlmMessage"))
         }
-        Err(_e) => Err(anyhow::anyhow!("Failed to get reply from OpenAI: {:?}", _e)),
+        Err(e) => Err(anyhow::Error::new(e).context("Failed to get reply from OpenAI")),
     }
 }
 
@@ -259,3 +632,174 @@ pub async fn run_test() {
 
     println!("msg: {:?} \n usage: {:?} ", res.0, res.1.completion_tokens);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::llm_backend::mock_server::{MockLlmServer, MockReply};
+
+    #[tokio::test]
+    async fn test_openai_client_text_reply() {
+        let server = MockLlmServer::start(vec![MockReply::text("Why did the chicken...")])
+            .await
+            .unwrap();
+        let client = OpenAiClient::new(server.config(&OPENAI_CONFIG));
+
+        let request = CreateRequest::new(
+            vec![
+                LlmMessage::system("you're a comedian", AgentId::new(None)),
+                LlmMessage::user_text("tell me a joke", AgentId::new(None)),
+            ],
+            100,
+        );
+        let result = client.create(&request).await.unwrap();
+
+        assert!(matches!(
+            result.content,
+            ResultContent::TextContent(ref text) if text.text == "Why did the chicken..."
+        ));
+        assert_eq!(result.usage.prompt_tokens, 10);
+        let sent = &server.requests()[0].body;
+        assert_eq!(sent["model"], OPENAI_CONFIG.model);
+        assert_eq!(sent["messages"][1]["content"], "tell me a joke");
+    }
+
+    #[tokio::test]
+    async fn test_openai_client_tool_call_round_trip() {
+        let server = MockLlmServer::start(vec![
+            MockReply::tool_call(
+                "get_current_weather",
+                json!({"location": "New York", "unit": "celsius"}),
+            ),
+            MockReply::text("It is sunny in New York."),
+        ])
+        .await
+        .unwrap();
+        let client = OpenAiClient::new(server.config(&OPENAI_CONFIG));
+        let source = AgentId::new(None);
+
+        let mut request = CreateRequest::new(
+            vec![LlmMessage::user_text(
+                "Weather in New York?",
+                source.clone(),
+            )],
+            100,
+        );
+        request.tools = vec![json!({"name": "get_current_weather", "parameters": {}})];
+        let first = client.create(&request).await.unwrap();
+        let call = match first.content {
+            ResultContent::FunctionCallContent(mut calls) => calls.remove(0),
+            other => panic!("expected a function call, got {:?}", other),
+        };
+        assert_eq!(call.arguments_obj["location"], "New York");
+
+        request.messages.push(LlmMessage::assistant_function_run(
+            call.clone(),
+            source.clone(),
+        ));
+        request.messages.push(LlmMessage::function_result(
+            "sunny",
+            call.id.clone(),
+            source,
+        ));
+        let second = client.create(&request).await.unwrap();
+        assert!(matches!(second.content, ResultContent::TextContent(_)));
+
+        let sent = &server.requests()[1].body;
+        assert_eq!(sent["tools"][0]["type"], "function");
+        assert_eq!(sent["messages"][1]["tool_calls"][0]["id"], call.id);
+        assert_eq!(sent["messages"][2]["role"], "tool");
+        assert_eq!(sent["messages"][2]["tool_call_id"], call.id);
+    }
+
+    #[tokio::test]
+    async fn test_openai_stream_sparse_tool_call_indices() {
+        let delta = |index: usize, id: Option<&str>, name: Option<&str>, arguments: &str| {
+            json!({
+                "choices": [{
+                    "index": 0,
+                    "delta": {"tool_calls": [{
+                        "index": index,
+                        "id": id,
+                        "function": {"name": name, "arguments": arguments}
+                    }]},
+                    "finish_reason": null
+                }]
+            })
+        };
+        let server = MockLlmServer::start(vec![MockReply::StreamEvents(vec![
+            delta(
+                3,
+                Some("call_a"),
+                Some("get_current_weather"),
+                "{\"location\":",
+            ),
+            // An index far past the others must not allocate placeholder calls.
+            delta(usize::MAX, None, None, "{}"),
+            delta(3, None, None, "\"Paris\"}"),
+        ])])
+        .await
+        .unwrap();
+        let client = OpenAiClient::new(server.config(&OPENAI_CONFIG));
+        let (tx, _rx) = mpsc::channel(8);
+
+        let request = CreateRequest::new(
+            vec![LlmMessage::user_text(
+                "Weather in Paris?",
+                AgentId::new(None),
+            )],
+            100,
+        );
+        let result = client.create_stream(&request, tx).await.unwrap();
+
+        let calls = match result.content {
+            ResultContent::FunctionCallContent(calls) => calls,
+            other => panic!("expected a function call, got {:?}", other),
+        };
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].id, "call_a");
+        assert_eq!(calls[0].function_name, "get_current_weather");
+        assert_eq!(calls[0].arguments_obj["location"], "Paris");
+    }
+
+    #[test]
+    fn test_openai_messages_mixed_text_and_image() {
+        let image =
+            crate::msg_types::ImageContent::from_bytes(vec![0xFF, 0xD8, 0xFF, 0xE0]).unwrap();
+        let messages = vec![LlmMessage::user_multimodal(
+            vec![
+                MultiModalContent::Text("What is funny about this?".into()),
+                image.into(),
+            ],
+            AgentId::new(None),
+        )];
+
+        let out = openai_messages(&messages);
+
+        assert_eq!(out[0]["content"][0]["text"], "What is funny about this?");
+        assert_eq!(out[0]["content"][1]["type"], "image_url");
+        assert!(out[0]["content"][1]["image_url"]["url"]
+            .as_str()
+            .unwrap()
+            .starts_with("data:image/jpeg;base64,"));
+    }
+
+    #[tokio::test]
+    async fn test_chat_wrapper_openai_offline() {
+        let server = MockLlmServer::start(vec![MockReply::text("a 1960s joke")])
+            .await
+            .unwrap();
+
+        let (msg, usage) = chat_wrapper_openai(
+            &server.config(&OPENAI_CONFIG),
+            "you're tool use assistant",
+            "tell me a popular joke in the 1960s",
+            300,
+        )
+        .await
+        .unwrap();
+
+        assert!(format!("{:?}", msg).contains("a 1960s joke"));
+        assert_eq!(usage.completion_tokens, 5);
+    }
+}
@@ -1,13 +1,11 @@
-use std::{collections::HashMap, fs, path::Path};
+use std::collections::HashMap;
 
+use crate::agent::llm_backend::http::{bearer_headers, LlmHttpClient};
 use crate::agent::llm_backend::{LlmConfig, TOGETHER_VISION_CONFIG};
-use crate::msg_types::{llm_msg_types::LlmMessage, RequestUsage, AgentId};
-use base64::engine::{general_purpose, Engine as _};
-use dotenv::dotenv;
-use reqwest::{
-    header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE, USER_AGENT},
-    ClientBuilder,
+use crate::msg_types::{
+    llm_msg_types::LlmMessage, AgentId, ImageContent, MultiModalContent, RequestUsage,
 };
+use dotenv::dotenv;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
@@ -66,6 +64,27 @@ pub enum MessageContentItem {
     Text { text: String },
 }
 
+impl From<&ImageContent> for MessageContentItem {
+    fn from(image: &ImageContent) -> Self {
+        MessageContentItem::ImageUrl {
+            image_url: ImageUrl {
+                url: image.to_data_uri(),
+            },
+        }
+    }
+}
+
+impl From<&MultiModalContent> for MessageContentItem {
+    fn from(content: &MultiModalContent) -> Self {
+        match content {
+            MultiModalContent::Text(text) => MessageContentItem::Text {
+                text: text.text.clone(),
+            },
+            MultiModalContent::Image(image) => image.into(),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Message {
     pub role: String,
@@ -80,16 +99,10 @@ pub async fn chat_wrapper_llama_vision(
 ) -> anyhow::Result<(LlmMessage, RequestUsage)> {
     dotenv().ok();
 
-    let mut headers = HeaderMap::new();
-    let api_key = std::env::var(&llm_config.api_key_str)?;
-    let bearer_token = format!("Bearer {}", api_key);
-
     let contains_llama = llm_config.model.to_ascii_lowercase().contains("llama");
     assert!(contains_llama, "Model is not a Llama model");
 
-    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-    headers.insert(USER_AGENT, HeaderValue::from_static("MyClient/1.0.0"));
-    headers.insert(AUTHORIZATION, HeaderValue::from_str(&bearer_token)?);
+    let headers = bearer_headers(llm_config)?;
 
     let messages = vec![
         Message {
@@ -111,33 +124,29 @@ pub async fn chat_wrapper_llama_vision(
         "temperature": 0.3
     });
 
-    let client = ClientBuilder::new().default_headers(headers).build()?;
     let uri = llm_config.base_url;
 
-    let response = client.post(uri).json(&body_json).send().await?;
-    let status = response.status();
-    let response_body = response.text().await?;
-
-    if status.is_success() {
-        let api_response = serde_json::from_str::<CreateChatCompletionResponseExt>(&response_body)?;
-        if let Some(llm_message) = output_llmmessage(api_response.clone()) {
-            let usage = api_response
-                .usage
-                .map(|u| RequestUsage {
-                    prompt_tokens: u.prompt_tokens.unwrap_or(0) as i32,
-                    completion_tokens: u.completion_tokens.unwrap_or(0) as i32,
-                })
-                .unwrap_or(RequestUsage {
-                    prompt_tokens: 0,
-                    completion_tokens: 0,
-                });
-            Ok((llm_message, usage))
-        } else {
-            Err(anyhow::anyhow!("Could not convert to LlmMessage"))
-        }
+    let response_body = LlmHttpClient::shared()
+        .post_json(uri, headers, &body_json)
+        .await?;
+
+    let api_response = serde_json::from_str::<CreateChatCompletionResponseExt>(&response_body)?;
+    if let Some(llm_message) = output_llmmessage(api_response.clone()) {
+        let usage = api_response
+            .usage
+            .map(|u| RequestUsage {
+                prompt_tokens: u.prompt_tokens.unwrap_or(0) as i32,
+                completion_tokens: u.completion_tokens.unwrap_or(0) as i32,
+                ..Default::default()
+            })
+            .unwrap_or(RequestUsage {
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                ..Default::default()
+            });
+        Ok((llm_message, usage))
     } else {
-        let error_response: Value = serde_json::from_str(&response_body)?;
-        Err(anyhow::anyhow!("API returned an error: {}", error_response))
+        Err(anyhow::anyhow!("Could not convert to LlmMessage"))
     }
 }
 
@@ -192,46 +201,25 @@ fn extract_tool_call(input: &str) -> Option<LlamaToolCall> {
     None
 }
 
-pub async fn load_image_and_encode(path: &str) -> String {
-    let image_bytes = fs::read(path).expect("Error reading image file");
-
-    let mime_type = match Path::new(path)
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .unwrap_or("")
-        .to_ascii_lowercase()
-        .as_str()
-    {
-        "jpg" | "jpeg" => "image/jpeg",
-        "png" => "image/png",
-        "gif" => "image/gif",
-        other => panic!("Unsupported image extension: {}", other),
-    };
-
-    let encoded_image = general_purpose::STANDARD.encode(&image_bytes);
-    let data_uri = format!("data:{};base64,{}", mime_type, encoded_image);
+pub async fn load_image_and_encode(path: &str) -> anyhow::Result<String> {
+    let image = ImageContent::from_file(path)?;
 
     println!("Image file read and encoded");
-    data_uri
+    Ok(image.to_data_uri())
 }
 
 pub async fn run_test() -> anyhow::Result<()> {
     let functions = serde_json::json!([]); // Not used in this example
 
-    let path = "/home/jaykchen/projects/autogen_dev/assets/cohort_age.png";
-
-    let data_uri = load_image_and_encode(path).await;
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../assets/cohort_age.png");
 
     let system_prompt = r#"You're a tool-using AI"#;
 
-    let message_contents = vec![
-        MessageContentItem::Text {
-            text: "What is funny about this?".to_string(),
-        },
-        MessageContentItem::ImageUrl {
-            image_url: ImageUrl { url: data_uri },
-        },
+    let parts = [
+        MultiModalContent::Text("What is funny about this?".into()),
+        ImageContent::from_file(path)?.into(),
     ];
+    let message_contents = parts.iter().map(MessageContentItem::from).collect();
 
     let (llm_message, usage) = chat_wrapper_llama_vision(
         &TOGETHER_VISION_CONFIG,
@@ -287,3 +275,37 @@ pub async fn run_test_text() {
 
     println!("msg: {:?} \n usage: {:?} ", res.0, res.1.completion_tokens);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::llm_backend::mock_server::{MockLlmServer, MockReply};
+
+    #[tokio::test]
+    async fn test_vision_request_offline() {
+        let server = MockLlmServer::start(vec![MockReply::text("a cat wearing a hat")])
+            .await
+            .unwrap();
+        let llm_config = server.config(&TOGETHER_VISION_CONFIG);
+
+        let message_contents = vec![
+            MessageContentItem::Text {
+                text: "What is funny about this?".to_string(),
+            },
+            MessageContentItem::ImageUrl {
+                image_url: ImageUrl {
+                    url: "data:image/png;base64,iVBORw0KGgo=".to_string(),
+                },
+            },
+        ];
+        let (llm_message, usage) =
+            chat_wrapper_llama_vision(&llm_config, "You describe images", message_contents, 100)
+                .await
+                .unwrap();
+
+        assert!(format!("{:?}", llm_message).contains("a cat wearing a hat"));
+        assert_eq!(usage.completion_tokens, 5);
+        let sent = &server.requests()[0].body;
+        assert_eq!(sent["messages"][1]["content"][1]["type"], "image_url");
+    }
+}
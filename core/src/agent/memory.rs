@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::agent::llm_backend::embeddings::EmbeddingClient;
+
+/// Number of memories `LlmCompletionAgent` adds to the prompt per turn.
+pub const DEFAULT_TOP_K: usize = 3;
+
+/// Inputs per `/v1/embeddings` request when adding documents in bulk.
+const EMBED_BATCH_SIZE: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MemoryEntry {
+    pub id: String,
+    pub text: String,
+    pub embedding: Vec<f32>,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryHit {
+    pub text: String,
+    pub score: f32,
+    pub metadata: HashMap<String, String>,
+}
+
+/// In-process vector store with brute-force cosine search. A store opened with a
+/// path is written back as JSON after every change.
+#[derive(Debug, Default)]
+pub struct VectorStore {
+    entries: Vec<MemoryEntry>,
+    path: Option<PathBuf>,
+}
+
+impl VectorStore {
+    pub fn new() -> Self {
+        VectorStore::default()
+    }
+
+    /// Loads the store from `path` if the file exists, otherwise starts empty.
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let entries = if path.exists() {
+            let raw = std::fs::read_to_string(&path).map_err(|e| {
+                anyhow::anyhow!("Error reading vector store {}: {}", path.display(), e)
+            })?;
+            serde_json::from_str(&raw)?
+        } else {
+            Vec::new()
+        };
+        Ok(VectorStore {
+            entries,
+            path: Some(path),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn add(&mut self, entry: MemoryEntry) -> anyhow::Result<()> {
+        self.extend(vec![entry])
+    }
+
+    pub fn contains_text(&self, text: &str) -> bool {
+        self.entries.iter().any(|entry| entry.text == text)
+    }
+
+    pub fn contains(&self, text: &str, metadata: &HashMap<String, String>) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| entry.text == text && &entry.metadata == metadata)
+    }
+
+    /// Adds all entries and persists once, or none if any has the wrong dimension.
+    pub fn extend(&mut self, entries: Vec<MemoryEntry>) -> anyhow::Result<()> {
+        let expected = self
+            .entries
+            .first()
+            .or(entries.first())
+            .map(|e| e.embedding.len());
+        if let Some(entry) = entries.iter().find(|e| Some(e.embedding.len()) != expected) {
+            return Err(anyhow::anyhow!(
+                "Embedding has {} dimensions, store holds {}",
+                entry.embedding.len(),
+                expected.unwrap_or_default()
+            ));
+        }
+        self.entries.extend(entries);
+        self.save()
+    }
+
+    /// The `k` entries most similar to `query`, best first.
+    pub fn search(&self, query: &[f32], k: usize) -> Vec<(f32, &MemoryEntry)> {
+        let mut scored: Vec<(f32, &MemoryEntry)> = self
+            .entries
+            .iter()
+            .map(|entry| (cosine_similarity(query, &entry.embedding), entry))
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.truncate(k);
+        scored
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        if let Some(path) = &self.path {
+            std::fs::write(path, serde_json::to_string(&self.entries)?).map_err(|e| {
+                anyhow::anyhow!("Error writing vector store {}: {}", path.display(), e)
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// 0.0 when either vector is all zeros or the lengths differ.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+/// Long-term memory an agent consults before answering, held as `Arc<dyn Memory>`.
+#[async_trait]
+pub trait Memory: Send + Sync {
+    async fn add(&self, text: &str, metadata: HashMap<String, String>) -> anyhow::Result<()>;
+
+    async fn query(&self, text: &str, k: usize) -> anyhow::Result<Vec<MemoryHit>>;
+}
+
+/// `Memory` backed by an embedding model and a `VectorStore`.
+pub struct VectorMemory {
+    pub embedder: Arc<dyn EmbeddingClient>,
+    store: Mutex<VectorStore>,
+    /// Hits scoring below this are dropped.
+    pub min_score: f32,
+}
+
+impl VectorMemory {
+    pub fn new(embedder: Arc<dyn EmbeddingClient>, store: VectorStore) -> Self {
+        VectorMemory {
+            embedder,
+            store: Mutex::new(store),
+            min_score: 0.0,
+        }
+    }
+
+    /// Embeds and stores many documents, batching the embedding requests.
+    pub async fn add_documents(&self, documents: &[String]) -> anyhow::Result<()> {
+        let documents = documents
+            .iter()
+            .map(|text| (text.clone(), HashMap::new()))
+            .collect::<Vec<_>>();
+        self.add_documents_with_metadata(&documents).await
+    }
+
+    pub async fn add_documents_with_metadata(
+        &self,
+        documents: &[(String, HashMap<String, String>)],
+    ) -> anyhow::Result<()> {
+        for batch in documents.chunks(EMBED_BATCH_SIZE) {
+            let texts = batch
+                .iter()
+                .map(|(text, _)| text.clone())
+                .collect::<Vec<_>>();
+            let embeddings = self.embedder.embed(&texts).await?;
+            let entries = batch
+                .iter()
+                .zip(embeddings)
+                .map(|((text, metadata), embedding)| MemoryEntry {
+                    id: Uuid::new_v4().to_string(),
+                    text: text.clone(),
+                    embedding,
+                    metadata: metadata.clone(),
+                })
+                .collect();
+            self.store.lock().unwrap().extend(entries)?;
+        }
+        Ok(())
+    }
+
+    /// Whether an entry with exactly this text and metadata is already stored.
+    pub fn contains(&self, text: &str, metadata: &HashMap<String, String>) -> bool {
+        self.store.lock().unwrap().contains(text, metadata)
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[async_trait]
+impl Memory for VectorMemory {
+    /// Text that is already stored is not added again.
+    async fn add(&self, text: &str, metadata: HashMap<String, String>) -> anyhow::Result<()> {
+        if self.store.lock().unwrap().contains_text(text) {
+            return Ok(());
+        }
+        let embedding = self
+            .embedder
+            .embed(&[text.to_string()])
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("Embedding request returned nothing"))?;
+        self.store.lock().unwrap().add(MemoryEntry {
+            id: Uuid::new_v4().to_string(),
+            text: text.to_string(),
+            embedding,
+            metadata,
+        })
+    }
+
+    async fn query(&self, text: &str, k: usize) -> anyhow::Result<Vec<MemoryHit>> {
+        if self.is_empty() {
+            return Ok(Vec::new());
+        }
+        let query = self
+            .embedder
+            .embed(&[text.to_string()])
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("Embedding request returned nothing"))?;
+        let store = self.store.lock().unwrap();
+        Ok(store
+            .search(&query, k)
+            .into_iter()
+            .filter(|(score, _)| *score >= self.min_score)
+            .map(|(score, entry)| MemoryHit {
+                text: entry.text.clone(),
+                score,
+                metadata: entry.metadata.clone(),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::llm_backend::{LlmConfig, OPENAI_EMBEDDING_CONFIG};
+
+    /// Counts a few keywords, so related sentences land close together.
+    struct KeywordEmbedder;
+
+    #[async_trait]
+    impl EmbeddingClient for KeywordEmbedder {
+        fn llm_config(&self) -> &LlmConfig {
+            &OPENAI_EMBEDDING_CONFIG
+        }
+
+        async fn embed(&self, inputs: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+            Ok(inputs
+                .iter()
+                .map(|text| {
+                    ["dog", "weather", "rust"]
+                        .iter()
+                        .map(|word| text.matches(word).count() as f32)
+                        .collect()
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query_returns_most_similar_first() {
+        let memory = VectorMemory::new(Arc::new(KeywordEmbedder), VectorStore::new());
+        memory
+            .add_documents(&[
+                "my dog is called Rex".to_string(),
+                "the weather is sunny".to_string(),
+                "rust has no garbage collector".to_string(),
+            ])
+            .await
+            .unwrap();
+
+        let hits = memory.query("what is the dog's name?", 2).await.unwrap();
+
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].text, "my dog is called Rex");
+        assert!(hits[0].score > hits[1].score);
+    }
+
+    #[test]
+    fn test_store_persists_to_disk() {
+        let path = std::env::temp_dir().join(format!("vector-store-{}.json", Uuid::new_v4()));
+        let mut store = VectorStore::open(&path).unwrap();
+        store
+            .add(MemoryEntry {
+                id: "1".to_string(),
+                text: "remember me".to_string(),
+                embedding: vec![0.6, 0.8],
+                metadata: HashMap::new(),
+            })
+            .unwrap();
+        assert!(store
+            .add(MemoryEntry {
+                id: "2".to_string(),
+                text: "wrong size".to_string(),
+                embedding: vec![1.0],
+                metadata: HashMap::new(),
+            })
+            .is_err());
+
+        let reopened = VectorStore::open(&path).unwrap();
+        assert_eq!(reopened.len(), 1);
+        let (score, entry) = reopened.search(&[0.6, 0.8], 1)[0];
+        assert_eq!(entry.text, "remember me");
+        assert!((score - 1.0).abs() < 1e-6);
+        std::fs::remove_file(path).unwrap();
+    }
+}
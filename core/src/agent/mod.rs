@@ -1,3 +1,7 @@
 pub mod agent_runtime;
+pub mod budget;
 pub mod chat_agent;
+pub mod context_policy;
 pub mod llm_backend;
+pub mod memory;
+pub mod retrieval;
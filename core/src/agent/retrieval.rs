@@ -0,0 +1,400 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::agent::chat_agent::{Agent, ResultContent};
+use crate::agent::llm_backend::{CreateRequest, LlmClient};
+use crate::agent::memory::{Memory, MemoryHit, VectorMemory};
+use crate::msg_types::chat_msg_types::{ChatMessage, TextMessage};
+use crate::msg_types::{llm_msg_types::LlmMessage, AgentId, ChatMessageContext, MultiModalContent};
+
+/// Directories never worth indexing.
+const SKIPPED_DIRS: &[&str] = &["target", "node_modules", "__pycache__", "venv"];
+
+const RETRIEVAL_SYSTEM_PROMPT: &str = "Answer the question using only the numbered \
+context excerpts. Cite the excerpts you used by their file path in square brackets, \
+e.g. [src/lib.rs]. If the excerpts do not contain the answer, say so.";
+
+#[derive(Debug, Clone)]
+pub struct ChunkingConfig {
+    /// Target chunk length in characters. Chunks break at line ends, so a single
+    /// very long line can exceed it.
+    pub chunk_size: usize,
+    /// Characters of trailing lines repeated at the start of the next chunk.
+    pub overlap: usize,
+    /// File extensions to index, without the dot.
+    pub extensions: Vec<String>,
+}
+
+impl Default for ChunkingConfig {
+    fn default() -> Self {
+        ChunkingConfig {
+            chunk_size: 1500,
+            overlap: 200,
+            extensions: ["txt", "md", "rs", "py", "js", "ts", "toml", "json", "yaml"]
+                .iter()
+                .map(|ext| ext.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// A piece of a file, with 1-based inclusive line numbers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub text: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+/// Splits `text` at line boundaries into chunks of about `chunk_size` characters,
+/// each starting with up to `overlap` characters of the previous chunk's lines.
+pub fn chunk_text(text: &str, config: &ChunkingConfig) -> Vec<Chunk> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < lines.len() {
+        let mut end = start;
+        let mut len = 0;
+        while end < lines.len() && (end == start || len + lines[end].len() < config.chunk_size) {
+            len += lines[end].len() + 1;
+            end += 1;
+        }
+        let chunk = lines[start..end].join("\n");
+        if !chunk.trim().is_empty() {
+            chunks.push(Chunk {
+                text: chunk,
+                start_line: start + 1,
+                end_line: end,
+            });
+        }
+        if end == lines.len() {
+            break;
+        }
+
+        // Step back over trailing lines that fit in the overlap, but always advance.
+        let mut next = end;
+        let mut carried = 0;
+        while next > start + 1 && carried + lines[next - 1].len() < config.overlap {
+            carried += lines[next - 1].len() + 1;
+            next -= 1;
+        }
+        start = next;
+    }
+    chunks
+}
+
+/// Answers questions about a folder of documents: files are chunked and embedded
+/// into a `VectorMemory`, the best `top_k` chunks go into the prompt, and the
+/// reply lists the files it drew on.
+pub struct RetrievalAgent {
+    pub agent_base: Agent,
+    pub memory: Arc<VectorMemory>,
+    pub llm: Arc<dyn LlmClient>,
+    pub chunking: ChunkingConfig,
+    pub top_k: usize,
+    pub max_tokens: u16,
+}
+
+impl RetrievalAgent {
+    pub fn new(agent_base: Agent, memory: Arc<VectorMemory>, llm: Arc<dyn LlmClient>) -> Self {
+        RetrievalAgent {
+            agent_base,
+            memory,
+            llm,
+            chunking: ChunkingConfig::default(),
+            top_k: 5,
+            max_tokens: 1000,
+        }
+    }
+
+    /// Indexes every matching file under `dir`, recording paths relative to it.
+    /// Returns the number of chunks added. Hidden directories, build output,
+    /// symlinked directories and files that aren't UTF-8 are skipped, as are
+    /// chunks already stored with the same text, path and lines, so indexing a
+    /// directory again only adds what changed.
+    pub async fn index_dir(&self, dir: impl AsRef<Path>) -> anyhow::Result<usize> {
+        let dir = dir.as_ref();
+        let mut files = Vec::new();
+        collect_files(dir, &self.chunking.extensions, &mut files)?;
+        files.sort();
+
+        let mut documents = Vec::new();
+        for file in files {
+            let Ok(text) = std::fs::read_to_string(&file) else {
+                println!("Skipping non-UTF-8 file {}", file.display());
+                continue;
+            };
+            let relative = file
+                .strip_prefix(dir)
+                .unwrap_or(&file)
+                .display()
+                .to_string();
+            for chunk in chunk_text(&text, &self.chunking) {
+                let metadata = HashMap::from([
+                    ("path".to_string(), relative.clone()),
+                    (
+                        "lines".to_string(),
+                        format!("{}-{}", chunk.start_line, chunk.end_line),
+                    ),
+                ]);
+                if !self.memory.contains(&chunk.text, &metadata) {
+                    documents.push((chunk.text, metadata));
+                }
+            }
+        }
+
+        self.memory.add_documents_with_metadata(&documents).await?;
+        Ok(documents.len())
+    }
+
+    pub async fn on_message(
+        &mut self,
+        message: ChatMessage,
+        ctx: ChatMessageContext,
+    ) -> ChatMessage {
+        let query = match message {
+            ChatMessage::TextMessage(tex) => tex.content.text,
+            other => {
+                return ChatMessage::StopMessage(format!(
+                    "{} only answers text questions, got {:?}",
+                    self.agent_base.name, other
+                ))
+            }
+        };
+
+        match self.answer(&query).await {
+            Ok(answer) => ChatMessage::TextMessage(TextMessage {
+                content: answer.into(),
+                source: ctx.sender,
+            }),
+            Err(e) => ChatMessage::StopMessage(format!("Retrieval failed: {}", e)),
+        }
+    }
+
+    /// Retrieves context for `query`, asks the model, and appends the sources.
+    pub async fn answer(&self, query: &str) -> anyhow::Result<String> {
+        let hits = self.memory.query(query, self.top_k).await?;
+        if hits.is_empty() {
+            return Ok("No indexed documents match this question.".to_string());
+        }
+
+        let source = AgentId::new(Some(&self.agent_base.name));
+        let messages = vec![
+            LlmMessage::system(RETRIEVAL_SYSTEM_PROMPT, source.clone()),
+            LlmMessage::user_text(
+                format!("{}\n\nQuestion: {}", format_context(&hits), query),
+                source,
+            ),
+        ];
+        let result = self
+            .llm
+            .create(&CreateRequest::new(messages, self.max_tokens))
+            .await?;
+        let answer = match result.content {
+            ResultContent::TextContent(text) => text.text,
+            ResultContent::MultiModalContent(MultiModalContent::Text(text)) => text.text,
+            other => return Err(anyhow::anyhow!("Expected a text answer, got {:?}", other)),
+        };
+
+        let mut sources: Vec<String> = Vec::new();
+        for hit in &hits {
+            let citation = citation(hit);
+            if !sources.contains(&citation) {
+                sources.push(citation);
+            }
+        }
+        Ok(format!(
+            "{}\n\nSources:\n- {}",
+            answer,
+            sources.join("\n- ")
+        ))
+    }
+}
+
+fn citation(hit: &MemoryHit) -> String {
+    match (hit.metadata.get("path"), hit.metadata.get("lines")) {
+        (Some(path), Some(lines)) => format!("{}:{}", path, lines),
+        (Some(path), None) => path.clone(),
+        _ => "(unknown source)".to_string(),
+    }
+}
+
+fn format_context(hits: &[MemoryHit]) -> String {
+    hits.iter()
+        .enumerate()
+        .map(|(i, hit)| format!("[{}] {}\n{}", i + 1, citation(hit), hit.text))
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
+
+fn collect_files(dir: &Path, extensions: &[String], out: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| anyhow::anyhow!("Error reading directory {}: {}", dir.display(), e))?;
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        // `file_type` doesn't follow symlinks, so a link back up the tree can't loop.
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if !name.starts_with('.') && !SKIPPED_DIRS.contains(&name.as_str()) {
+                collect_files(&path, extensions, out)?;
+            }
+        } else if file_type.is_symlink() && path.is_dir() {
+            println!("Skipping symlinked directory {}", path.display());
+        } else if path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+        {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::llm_backend::embeddings::EmbeddingClient;
+    use crate::agent::llm_backend::mock_server::{MockLlmServer, MockReply};
+    use crate::agent::llm_backend::openai::OpenAiClient;
+    use crate::agent::llm_backend::{LlmConfig, OPENAI_CONFIG, OPENAI_EMBEDDING_CONFIG};
+    use crate::agent::memory::VectorStore;
+    use async_trait::async_trait;
+
+    struct KeywordEmbedder;
+
+    #[async_trait]
+    impl EmbeddingClient for KeywordEmbedder {
+        fn llm_config(&self) -> &LlmConfig {
+            &OPENAI_EMBEDDING_CONFIG
+        }
+
+        async fn embed(&self, inputs: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+            Ok(inputs
+                .iter()
+                .map(|text| {
+                    ["budget", "retry", "install"]
+                        .iter()
+                        .map(|word| text.matches(word).count() as f32)
+                        .collect()
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_chunks_overlap_and_cover_every_line() {
+        let text = (1..=10)
+            .map(|i| format!("line {:02}", i))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let config = ChunkingConfig {
+            chunk_size: 30,
+            overlap: 10,
+            ..Default::default()
+        };
+
+        let chunks = chunk_text(&text, &config);
+
+        assert_eq!(chunks[0].start_line, 1);
+        assert_eq!(chunks.last().unwrap().end_line, 10);
+        for pair in chunks.windows(2) {
+            assert_eq!(pair[1].start_line, pair[0].end_line);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_answers_with_cited_sources() {
+        let dir = std::env::temp_dir().join(format!("retrieval-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("docs")).unwrap();
+        std::fs::create_dir_all(dir.join("target")).unwrap();
+        std::fs::write(
+            dir.join("docs/budget.md"),
+            "A budget stops an agent once its budget is spent.",
+        )
+        .unwrap();
+        std::fs::write(dir.join("README.md"), "Run cargo install to install it.").unwrap();
+        std::fs::write(dir.join("target/budget.md"), "build output budget").unwrap();
+        std::fs::write(dir.join("image.png"), [0x89, b'P', b'N', b'G']).unwrap();
+
+        let server = MockLlmServer::start(vec![MockReply::text(
+            "The agent stops once its budget is spent [docs/budget.md].",
+        )])
+        .await
+        .unwrap();
+        let memory = Arc::new(VectorMemory::new(
+            Arc::new(KeywordEmbedder),
+            VectorStore::new(),
+        ));
+        let mut agent = RetrievalAgent::new(
+            Agent {
+                name: "docs".to_string(),
+                description: "answers questions about the docs".to_string(),
+                chat_context: Vec::new(),
+            },
+            memory,
+            Arc::new(OpenAiClient::new(server.config(&OPENAI_CONFIG))),
+        );
+        agent.top_k = 1;
+
+        assert_eq!(agent.index_dir(&dir).await.unwrap(), 2);
+        let reply = agent
+            .on_message(
+                ChatMessage::TextMessage(TextMessage {
+                    content: "What does a budget do?".into(),
+                    source: AgentId::new(None),
+                }),
+                ChatMessageContext {
+                    sender: AgentId::new(None),
+                    topic_id: crate::msg_types::TopicId::new(None),
+                    is_rpc: false,
+                },
+            )
+            .await;
+
+        match reply {
+            ChatMessage::TextMessage(tm) => {
+                assert!(tm.content.text.ends_with("Sources:\n- docs/budget.md:1-1"))
+            }
+            other => panic!("expected a text answer, got {:?}", other),
+        }
+        let prompt = server.requests()[0].body["messages"][1]["content"].to_string();
+        assert!(prompt.contains("[1] docs/budget.md:1-1"));
+        assert!(!prompt.contains("README"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_reindexing_skips_stored_chunks_and_symlinked_dirs() {
+        let dir = std::env::temp_dir().join(format!("retrieval-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("docs")).unwrap();
+        std::fs::write(dir.join("docs/budget.md"), "A budget stops an agent.").unwrap();
+        // A link back to the root would recurse forever if followed.
+        std::os::unix::fs::symlink(&dir, dir.join("docs/loop")).unwrap();
+
+        let memory = Arc::new(VectorMemory::new(
+            Arc::new(KeywordEmbedder),
+            VectorStore::new(),
+        ));
+        let agent = RetrievalAgent::new(
+            Agent {
+                name: "docs".to_string(),
+                description: "answers questions about the docs".to_string(),
+                chat_context: Vec::new(),
+            },
+            memory.clone(),
+            Arc::new(OpenAiClient::new(OPENAI_CONFIG)),
+        );
+
+        assert_eq!(agent.index_dir(&dir).await.unwrap(), 1);
+        assert_eq!(agent.index_dir(&dir).await.unwrap(), 0);
+        std::fs::write(dir.join("README.md"), "Run cargo install.").unwrap();
+        assert_eq!(agent.index_dir(&dir).await.unwrap(), 1);
+        assert_eq!(memory.len(), 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,4 +1,5 @@
 use crate::agent::agent_runtime::AgentRuntime;
+use crate::agent::budget::{Budget, BudgetLimit};
 use crate::msg_types::AgentId;
 
 pub struct TerminationCondition;
@@ -12,6 +13,7 @@ pub struct GroupChat {
     pub group_topic_type: String,
     pub participant_topic_types: Vec<String>, // Store a list of participant topic types
     pub participant_descriptions: Vec<String>, // Store descriptions if needed
+    pub budget: Budget, // Child of the runtime budget, shared by all participants
 }
 
 impl GroupChat {
@@ -25,6 +27,9 @@ impl GroupChat {
         participant_topic_types: Vec<String>,
         participant_descriptions: Vec<String>,
     ) -> Self {
+        let budget = runtime
+            .budget
+            .child(group_topic_type.clone(), BudgetLimit::default());
         GroupChat {
             runtime,
             participants,
@@ -34,8 +39,21 @@ impl GroupChat {
             parent_topic_type,
             participant_topic_types,
             participant_descriptions,
+            budget,
         }
     }
+
+    pub fn set_budget_limit(&mut self, limit: BudgetLimit) {
+        self.budget.set_limit(limit);
+    }
+
+    /// Budget for a single participant; its usage also counts against the group and the runtime.
+    pub fn participant_budget(&self, participant: &AgentId, limit: BudgetLimit) -> Budget {
+        let scope = participant
+            .get_text()
+            .unwrap_or_else(|| format!("{:?}", participant));
+        self.budget.child(scope, limit)
+    }
 }
 
 impl GroupChat {
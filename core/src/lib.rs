@@ -1,4 +1,5 @@
 pub mod msg_types;
 pub mod agent;
 pub mod tool_types;
-pub mod group_chat;
\ No newline at end of file
+pub mod group_chat;
+pub mod mcp;
\ No newline at end of file
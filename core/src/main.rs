@@ -1,6 +1,62 @@
+use autogen_core::agent::chat_agent::{Agent, LlmCompletionAgent, LlmCompletionClient};
 use autogen_core::agent::llm_backend::vision_llama::run_test;
+use autogen_core::agent::llm_backend::OPENAI_CONFIG;
+use autogen_core::mcp::McpServer;
+use autogen_core::tool_types::{get_current_weather_tool, process_values_tool, ToolRegistry};
+use std::sync::Arc;
 
+/// `autogen mcp` serves the built-in tools over MCP on stdin and stdout.
+/// `--with-agent` adds an assistant tool that answers with the help of the others.
 #[tokio::main]
 async fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    if args.first().map(String::as_str) == Some("mcp") {
+        if let Err(e) = serve_mcp(args.iter().any(|arg| arg == "--with-agent")).await {
+            eprintln!("MCP server failed: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
     let _ = run_test().await;
 }
+
+async fn serve_mcp(with_agent: bool) -> anyhow::Result<()> {
+    let tools = ToolRegistry::new()
+        .with_tool(get_current_weather_tool())
+        .with_tool(process_values_tool());
+    let mut registry = tools.clone();
+    if with_agent {
+        let mut assistant = LlmCompletionAgent::new(
+            Agent {
+                name: "assistant".to_string(),
+                description: "Answers questions, using the other tools when needed".to_string(),
+                chat_context: Vec::new(),
+            },
+            LlmCompletionClient::new(OPENAI_CONFIG, None),
+        );
+        assistant.registered_tools = tools;
+        registry.register(assistant.into_tool());
+    }
+
+    let output = protocol_stdout()?;
+    Arc::new(McpServer::new(registry))
+        .serve(tokio::io::stdin(), output)
+        .await
+}
+
+/// Only MCP messages may appear on stdout, so fd 1 is pointed at stderr and all
+/// logging lands there; replies go to a duplicate of the original stdout.
+#[cfg(unix)]
+fn protocol_stdout() -> anyhow::Result<tokio::fs::File> {
+    use std::os::fd::AsFd;
+    let stdout = std::io::stdout().as_fd().try_clone_to_owned()?;
+    if unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(tokio::fs::File::from_std(std::fs::File::from(stdout)))
+}
+
+#[cfg(not(unix))]
+fn protocol_stdout() -> anyhow::Result<tokio::io::Stdout> {
+    Ok(tokio::io::stdout())
+}
@@ -0,0 +1,533 @@
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context};
+use base64::Engine;
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::{oneshot, Mutex as AsyncMutex};
+
+use crate::mcp::{error_response, notification, request, response, PROTOCOL_VERSION};
+use crate::msg_types::{ImageContent, TextContent};
+use crate::tool_types::{
+    CompiledSchema, Tool, ToolFuture, ToolOutput, ToolRegistry, DEFAULT_TOOL_TIMEOUT,
+};
+
+/// Limit for everything but `tools/call`, which is bounded by the tool's timeout.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+type Pending = Arc<Mutex<HashMap<u64, oneshot::Sender<Value>>>>;
+
+/// Forgets a stdio request once its caller stops waiting, whether it was
+/// answered, timed out or dropped, so a late reply finds nobody to deliver to.
+struct PendingGuard<'a> {
+    pending: &'a Pending,
+    id: u64,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.pending.lock().unwrap().remove(&self.id);
+    }
+}
+
+enum Transport {
+    Stdio {
+        stdin: Arc<AsyncMutex<ChildStdin>>,
+        pending: Pending,
+        /// Killed when the client is dropped.
+        _child: Child,
+    },
+    Http {
+        client: reqwest::Client,
+        url: String,
+        session_id: Mutex<Option<String>>,
+    },
+}
+
+/// A connection to one MCP server. Its tools are wrapped as ordinary `Tool`s, so
+/// calls are validated, timed out and gated by policies like any other tool.
+pub struct McpClient {
+    transport: Transport,
+    next_id: AtomicU64,
+    /// What the server said about itself when the connection was set up.
+    pub server_info: Value,
+}
+
+impl McpClient {
+    /// Starts `command` and talks to it over stdin and stdout, one JSON-RPC
+    /// message per line.
+    pub async fn spawn(command: &str, args: &[&str]) -> anyhow::Result<Self> {
+        let mut child = Command::new(command)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Could not start MCP server {}", command))?;
+        let stdin = Arc::new(AsyncMutex::new(
+            child
+                .stdin
+                .take()
+                .ok_or_else(|| anyhow!("MCP server {} has no stdin", command))?,
+        ));
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow!("MCP server {} has no stdout", command))?;
+        let pending = Pending::default();
+        tokio::spawn(read_stdio(stdout, stdin.clone(), pending.clone()));
+
+        McpClient::initialize(Transport::Stdio {
+            stdin,
+            pending,
+            _child: child,
+        })
+        .await
+    }
+
+    /// Connects with the streamable HTTP transport: every message is POSTed to
+    /// `url`, and the server answers with JSON or a short SSE stream.
+    pub async fn connect_http(url: &str) -> anyhow::Result<Self> {
+        McpClient::initialize(Transport::Http {
+            client: reqwest::Client::new(),
+            url: url.to_string(),
+            session_id: Mutex::new(None),
+        })
+        .await
+    }
+
+    async fn initialize(transport: Transport) -> anyhow::Result<Self> {
+        let mut client = McpClient {
+            transport,
+            next_id: AtomicU64::new(1),
+            server_info: Value::Null,
+        };
+        let params = json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": {},
+            "clientInfo": {"name": "autogen", "version": env!("CARGO_PKG_VERSION")}
+        });
+        let result = client.request("initialize", params).await?;
+        client.server_info = result.get("serverInfo").cloned().unwrap_or_default();
+        client
+            .send(&notification("notifications/initialized", json!({})))
+            .await?;
+        Ok(client)
+    }
+
+    /// The raw `tools/list` entries, following pagination to the end.
+    pub async fn list_tools(&self) -> anyhow::Result<Vec<Value>> {
+        let mut tools = Vec::new();
+        let mut cursor: Option<Value> = None;
+        loop {
+            let params = match cursor {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({}),
+            };
+            let mut result = self.request("tools/list", params).await?;
+            if let Some(Value::Array(page)) = result.get_mut("tools").map(Value::take) {
+                tools.extend(page);
+            }
+            cursor = result.get("nextCursor").filter(|c| !c.is_null()).cloned();
+            if cursor.is_none() {
+                return Ok(tools);
+            }
+        }
+    }
+
+    /// Calls a tool on the server. Text content becomes the value unless the
+    /// server sent `structuredContent`; images become attachments.
+    pub async fn call_tool(&self, name: &str, arguments: Value) -> anyhow::Result<ToolOutput> {
+        let params = json!({"name": name, "arguments": arguments});
+        let result = self.request("tools/call", params).await?;
+
+        let mut texts = Vec::new();
+        let mut attachments = Vec::new();
+        for part in result["content"].as_array().into_iter().flatten() {
+            match part["type"].as_str() {
+                Some("text") => texts.push(part["text"].as_str().unwrap_or_default().to_string()),
+                Some("image") => {
+                    let data = base64::engine::general_purpose::STANDARD
+                        .decode(part["data"].as_str().unwrap_or_default())?;
+                    attachments.push(ImageContent::from_bytes(data)?.into());
+                }
+                Some("resource") => {
+                    if let Some(text) = part["resource"]["text"].as_str() {
+                        attachments.push(TextContent::from(text).into());
+                    }
+                }
+                other => println!("Skipping MCP content of type {:?} from {}", other, name),
+            }
+        }
+        let text = texts.join("\n");
+        if result["isError"].as_bool().unwrap_or(false) {
+            bail!("{}", text);
+        }
+
+        Ok(ToolOutput {
+            value: match result.get("structuredContent") {
+                Some(structured) if !structured.is_null() => structured.clone(),
+                _ => Value::String(text),
+            },
+            attachments,
+        })
+    }
+
+    /// Wraps every tool the server lists as a `Tool` that calls back into this
+    /// client. Tools with an input schema that does not compile are skipped.
+    pub async fn tools(self: &Arc<Self>) -> anyhow::Result<Vec<Tool>> {
+        let mut tools = Vec::new();
+        for listed in self.list_tools().await? {
+            match mcp_tool(self.clone(), &listed) {
+                Ok(tool) => tools.push(tool),
+                Err(e) => println!("Skipping MCP tool {}: {}", listed["name"], e),
+            }
+        }
+        Ok(tools)
+    }
+
+    /// Adds the server's tools to `registry`, replacing tools of the same name.
+    pub async fn register_tools(
+        self: &Arc<Self>,
+        registry: &mut ToolRegistry,
+    ) -> anyhow::Result<()> {
+        for tool in self.tools().await? {
+            registry.register(tool);
+        }
+        Ok(())
+    }
+
+    async fn request(&self, method: &str, params: Value) -> anyhow::Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let message = request(id, method, params);
+        let response = match &self.transport {
+            Transport::Stdio { pending, .. } => {
+                let (tx, rx) = oneshot::channel();
+                pending.lock().unwrap().insert(id, tx);
+                let _waiting = PendingGuard { pending, id };
+                self.send(&message).await?;
+                let answer = async {
+                    rx.await
+                        .map_err(|_| anyhow!("MCP server closed the connection"))
+                };
+                if method == "tools/call" {
+                    answer.await?
+                } else {
+                    tokio::time::timeout(REQUEST_TIMEOUT, answer)
+                        .await
+                        .map_err(|_| anyhow!("MCP {} timed out", method))??
+                }
+            }
+            Transport::Http { .. } => self
+                .send(&message)
+                .await?
+                .ok_or_else(|| anyhow!("MCP server sent no response to {}", method))?,
+        };
+
+        if let Some(error) = response.get("error") {
+            bail!(
+                "MCP {} failed: {}",
+                method,
+                error["message"].as_str().unwrap_or("unknown error")
+            );
+        }
+        Ok(response.get("result").cloned().unwrap_or_default())
+    }
+
+    /// Writes `message`. Over HTTP the reply comes back on the same exchange and
+    /// is returned; over stdio replies arrive through `read_stdio`.
+    async fn send(&self, message: &Value) -> anyhow::Result<Option<Value>> {
+        match &self.transport {
+            Transport::Stdio { stdin, .. } => {
+                write_line(stdin, message).await?;
+                Ok(None)
+            }
+            Transport::Http {
+                client,
+                url,
+                session_id,
+            } => {
+                let mut builder = client
+                    .post(url)
+                    .header(ACCEPT, "application/json, text/event-stream")
+                    .json(message);
+                if let Some(session) = session_id.lock().unwrap().clone() {
+                    builder = builder.header("Mcp-Session-Id", session);
+                }
+                let timeout = match message["method"].as_str() {
+                    Some("tools/call") => DEFAULT_TOOL_TIMEOUT,
+                    _ => REQUEST_TIMEOUT,
+                };
+                let http_response = builder.timeout(timeout).send().await?;
+
+                if let Some(session) = http_response.headers().get("mcp-session-id") {
+                    *session_id.lock().unwrap() = Some(session.to_str()?.to_string());
+                }
+                let status = http_response.status();
+                let is_sse = http_response
+                    .headers()
+                    .get(CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .is_some_and(|value| value.starts_with("text/event-stream"));
+                let body = http_response.text().await?;
+                if !status.is_success() {
+                    bail!("MCP server returned {}: {}", status, body);
+                }
+                if message.get("id").is_none() || body.trim().is_empty() {
+                    return Ok(None);
+                }
+                if !is_sse {
+                    return Ok(Some(serde_json::from_str(&body)?));
+                }
+                // The stream may carry notifications before the response itself.
+                Ok(sse_messages(&body)
+                    .into_iter()
+                    .find(|event| event.get("id") == message.get("id")))
+            }
+        }
+    }
+}
+
+fn mcp_tool(client: Arc<McpClient>, listed: &Value) -> anyhow::Result<Tool> {
+    let name = listed["name"]
+        .as_str()
+        .ok_or_else(|| anyhow!("tool has no name"))?
+        .to_string();
+    let input_schema = match listed.get("inputSchema") {
+        Some(schema) if schema.is_object() => schema.clone(),
+        _ => json!({"type": "object"}),
+    };
+    let schema = CompiledSchema::compile(&input_schema)
+        .map_err(|e| anyhow!("input schema does not compile: {}", e))?;
+
+    let required = input_schema["required"]
+        .as_array()
+        .map(|names| names.iter().filter_map(Value::as_str).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let properties = input_schema["properties"]
+        .as_object()
+        .cloned()
+        .unwrap_or_default();
+    let arg_names = properties.keys().cloned().collect::<Vec<String>>();
+    let arg_types = properties
+        .values()
+        .map(|property| property["type"].as_str().unwrap_or("any").to_string())
+        .collect();
+    let arg_optional = arg_names
+        .iter()
+        .map(|arg| !required.contains(&arg.as_str()))
+        .collect::<Vec<bool>>();
+
+    let function = {
+        let name = name.clone();
+        Arc::new(move |mut args: Vec<Value>| -> ToolFuture {
+            let client = client.clone();
+            let name = name.clone();
+            // The validated object comes last and is sent as it is, so properties
+            // the schema allows beyond `properties` reach the server too.
+            let arguments = args.pop().unwrap_or_else(|| json!({}));
+            Box::pin(async move { Ok(client.call_tool(&name, arguments).await?) })
+        })
+    };
+
+    Ok(Tool {
+        tool_def_obj: json!({
+            "name": name,
+            "description": listed["description"].as_str().unwrap_or_default(),
+            "parameters": input_schema
+        })
+        .to_string(),
+        name,
+        function,
+        arg_names,
+        arg_types,
+        arg_optional,
+        timeout: DEFAULT_TOOL_TIMEOUT,
+        schema,
+    })
+}
+
+async fn write_line(stdin: &AsyncMutex<ChildStdin>, message: &Value) -> anyhow::Result<()> {
+    let mut line = message.to_string();
+    line.push('\n');
+    let mut stdin = stdin.lock().await;
+    stdin
+        .write_all(line.as_bytes())
+        .await
+        .context("Could not write to MCP server")?;
+    stdin.flush().await?;
+    Ok(())
+}
+
+/// Routes responses to their waiting requests and answers the server's own
+/// requests. Dropping `pending` when the server exits fails every open request.
+async fn read_stdio(stdout: ChildStdout, stdin: Arc<AsyncMutex<ChildStdin>>, pending: Pending) {
+    let mut lines = BufReader::new(stdout).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let message: Value = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(e) => {
+                println!("Ignoring malformed MCP message: {}", e);
+                continue;
+            }
+        };
+        let id = message.get("id").cloned().unwrap_or_default();
+        match message.get("method").and_then(Value::as_str) {
+            // Notifications such as logs and progress are not needed here.
+            Some(_) if id.is_null() => {}
+            Some("ping") => {
+                let _ = write_line(&stdin, &response(id, json!({}))).await;
+            }
+            Some(method) => {
+                let reply = error_response(id, -32601, &format!("Method not found: {}", method));
+                let _ = write_line(&stdin, &reply).await;
+            }
+            None => {
+                let waiting = id
+                    .as_u64()
+                    .and_then(|id| pending.lock().unwrap().remove(&id));
+                if let Some(waiting) = waiting {
+                    let _ = waiting.send(message);
+                }
+            }
+        }
+    }
+    pending.lock().unwrap().clear();
+}
+
+/// The JSON payloads of the `data:` lines in an SSE body, one per event.
+fn sse_messages(body: &str) -> Vec<Value> {
+    body.split("\n\n")
+        .filter_map(|event| {
+            let data = event
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(str::trim_start)
+                .collect::<Vec<&str>>()
+                .join("\n");
+            serde_json::from_str(&data).ok()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::msg_types::MultiModalContent;
+    use crate::tool_types::FunctionCallInput;
+
+    const SERVER: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../assets/mcp_test_server.py");
+
+    fn call(name: &str, arguments: Value) -> FunctionCallInput {
+        FunctionCallInput {
+            id: "call_1".to_string(),
+            arguments_obj: arguments,
+            function_name: name.to_string(),
+            return_type: "".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stdio_server_tools_run_through_the_registry() {
+        let client = Arc::new(McpClient::spawn("python3", &[SERVER]).await.unwrap());
+        assert_eq!(client.server_info["name"], "test-server");
+
+        let mut registry = ToolRegistry::new();
+        client.register_tools(&mut registry).await.unwrap();
+        assert_eq!(registry.names(), ["add", "echo", "fail", "pixel", "tag"]);
+        let def: Value = serde_json::from_str(&registry.get("echo").unwrap().tool_def_obj).unwrap();
+        assert_eq!(def["description"], "Repeat the text back.");
+        assert_eq!(registry.get("echo").unwrap().arg_optional, [false, true]);
+
+        let sum = registry
+            .run(&call("add", json!({"a": 2, "b": 3})))
+            .await
+            .unwrap();
+        assert_eq!(sum.value, json!({"sum": 5}));
+
+        let echo = registry
+            .run(&call(
+                "echo",
+                json!({"arguments": {"text": "hi", "times": null}}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(echo.value, "hi");
+
+        // Checked against the server's schema before anything is sent.
+        let invalid = registry
+            .run(&call("echo", json!({"text": "hi", "times": 0})))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            invalid.to_string(),
+            "Invalid arguments for echo:\n- times: 0 is less than the minimum of 1"
+        );
+
+        // An open schema lists no properties; every argument is still sent.
+        let tagged = registry
+            .run(&call("tag", json!({"env": "prod", "team": "core"})))
+            .await
+            .unwrap();
+        assert_eq!(tagged.value, json!({"env": "prod", "team": "core"}));
+
+        let failed = registry.run(&call("fail", json!({}))).await.unwrap_err();
+        assert_eq!(failed.to_string(), "the disk is full");
+
+        let pixel = registry.run(&call("pixel", json!({}))).await.unwrap();
+        assert_eq!(pixel.value, "one pixel");
+        assert!(matches!(
+            &pixel.attachments[..],
+            [MultiModalContent::Image(image)] if image.mime_type == "image/png"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_requests_are_forgotten() {
+        let client = McpClient::spawn("python3", &[SERVER]).await.unwrap();
+
+        // Polled once, so the request is sent, then dropped before the reply is read.
+        tokio::select! {
+            biased;
+            _ = client.request("ping", json!({})) => panic!("answered without waiting"),
+            _ = async {} => {}
+        }
+
+        let Transport::Stdio { pending, .. } = &client.transport else {
+            unreachable!()
+        };
+        assert!(pending.lock().unwrap().is_empty());
+        assert_eq!(client.request("ping", json!({})).await.unwrap(), json!({}));
+    }
+
+    #[tokio::test]
+    async fn test_http_server_answers_over_sse() {
+        let mut server = Command::new("python3")
+            .args([SERVER, "--http"])
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        let mut lines = BufReader::new(server.stdout.take().unwrap()).lines();
+        let url = lines.next_line().await.unwrap().unwrap();
+
+        let client = Arc::new(McpClient::connect_http(&url).await.unwrap());
+        let tools = client.tools().await.unwrap();
+        assert_eq!(tools.len(), 5);
+
+        let echo = tools.iter().find(|tool| tool.name == "echo").unwrap();
+        let output = echo.run(json!({"text": "ho", "times": 2})).await.unwrap();
+        assert_eq!(output.value, "ho ho");
+        let missing = client.call_tool("nope", json!({})).await.unwrap_err();
+        assert_eq!(
+            missing.to_string(),
+            "MCP tools/call failed: Unknown tool: 'nope'"
+        );
+    }
+}
@@ -0,0 +1,26 @@
+pub mod client;
+pub mod server;
+
+pub use client::McpClient;
+pub use server::McpServer;
+
+use serde_json::{json, Value};
+
+/// The protocol revision we ask for; servers answer with the one they speak.
+pub const PROTOCOL_VERSION: &str = "2025-03-26";
+
+pub(crate) fn request(id: u64, method: &str, params: Value) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params})
+}
+
+pub(crate) fn notification(method: &str, params: Value) -> Value {
+    json!({"jsonrpc": "2.0", "method": method, "params": params})
+}
+
+pub(crate) fn response(id: Value, result: Value) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "result": result})
+}
+
+pub(crate) fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
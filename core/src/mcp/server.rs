@@ -0,0 +1,266 @@
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::Mutex;
+use tokio::task::JoinSet;
+
+use crate::mcp::{error_response, response, PROTOCOL_VERSION};
+use crate::msg_types::MultiModalContent;
+use crate::tool_types::{FunctionCallInput, ToolRegistry, ToolRenderMode};
+
+/// Serves the tools in a registry to MCP clients. Schemas come from each tool's
+/// `tool_def_obj` and calls go through `Tool::run`.
+pub struct McpServer {
+    registry: ToolRegistry,
+    /// How tool values are written into the text content of a result.
+    pub render: ToolRenderMode,
+}
+
+impl McpServer {
+    pub fn new(registry: ToolRegistry) -> Self {
+        McpServer {
+            registry,
+            render: ToolRenderMode::CompactJson,
+        }
+    }
+
+    /// Answers newline-delimited JSON-RPC from `input` on `output` until `input`
+    /// ends. Requests are handled concurrently, so replies may come out of order.
+    pub async fn serve<R, W>(self: Arc<Self>, input: R, output: W) -> anyhow::Result<()>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let output = Arc::new(Mutex::new(output));
+        let mut lines = BufReader::new(input).lines();
+        let mut in_flight = JoinSet::new();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let server = self.clone();
+            let output = output.clone();
+            in_flight.spawn(async move {
+                let reply = match serde_json::from_str(&line) {
+                    Ok(message) => server.handle(message).await,
+                    Err(e) => Some(error_response(
+                        Value::Null,
+                        -32700,
+                        &format!("Parse error: {}", e),
+                    )),
+                };
+                if let Some(reply) = reply {
+                    let mut output = output.lock().await;
+                    let written = output.write_all(format!("{}\n", reply).as_bytes()).await;
+                    if let Err(e) = written.and(output.flush().await) {
+                        println!("Could not write MCP reply: {}", e);
+                    }
+                }
+            });
+        }
+        while in_flight.join_next().await.is_some() {}
+        Ok(())
+    }
+
+    /// The reply to one message, or `None` for notifications.
+    pub async fn handle(&self, message: Value) -> Option<Value> {
+        let id = message.get("id").cloned()?;
+        let params = message.get("params").cloned().unwrap_or_default();
+        let result = match message["method"].as_str().unwrap_or_default() {
+            "initialize" => json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": {"tools": {"listChanged": false}},
+                "serverInfo": {"name": "autogen", "version": env!("CARGO_PKG_VERSION")}
+            }),
+            "ping" => json!({}),
+            "tools/list" => self.list_tools(),
+            "tools/call" => {
+                let Some(name) = params["name"].as_str() else {
+                    return Some(error_response(id, -32602, "tools/call needs a tool name"));
+                };
+                if self.registry.get(name).is_none() {
+                    return Some(error_response(
+                        id,
+                        -32602,
+                        &format!("Unknown tool: {}", name),
+                    ));
+                }
+                self.call_tool(name, params["arguments"].clone()).await
+            }
+            method => {
+                return Some(error_response(
+                    id,
+                    -32601,
+                    &format!("Method not found: {}", method),
+                ))
+            }
+        };
+        Some(response(id, result))
+    }
+
+    fn list_tools(&self) -> Value {
+        let tools = self
+            .registry
+            .to_vec()
+            .iter()
+            .filter_map(|tool| {
+                let def: Value = serde_json::from_str(&tool.tool_def_obj).ok()?;
+                Some(json!({
+                    "name": tool.name,
+                    "description": def["description"],
+                    "inputSchema": def["parameters"]
+                }))
+            })
+            .collect::<Vec<Value>>();
+        json!({ "tools": tools })
+    }
+
+    /// Failures are results with `isError` set, so the calling model can see them.
+    async fn call_tool(&self, name: &str, arguments: Value) -> Value {
+        let call = FunctionCallInput {
+            id: String::new(),
+            arguments_obj: match arguments {
+                Value::Null => json!({}),
+                arguments => arguments,
+            },
+            function_name: name.to_string(),
+            return_type: String::new(),
+        };
+        let output = match self.registry.run(&call).await {
+            Ok(output) => output,
+            Err(e) => {
+                return json!({
+                    "content": [{"type": "text", "text": e.to_string()}],
+                    "isError": true
+                })
+            }
+        };
+
+        let mut content = vec![json!({"type": "text", "text": output.render(self.render)})];
+        content.extend(output.attachments.iter().map(|part| match part {
+            MultiModalContent::Text(text) => json!({"type": "text", "text": text.text}),
+            MultiModalContent::Image(image) => json!({
+                "type": "image",
+                "data": image.to_base64(),
+                "mimeType": image.mime_type
+            }),
+        }));
+        let mut result = json!({ "content": content, "isError": false });
+        if output.value.is_object() {
+            result["structuredContent"] = output.value;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::request;
+    use crate::tool_types::get_current_weather_tool;
+
+    fn server() -> Arc<McpServer> {
+        Arc::new(McpServer::new(
+            ToolRegistry::new().with_tool(get_current_weather_tool()),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_tools_are_listed_and_called() {
+        let server = server();
+
+        let list = server
+            .handle(request(1, "tools/list", json!({})))
+            .await
+            .unwrap();
+        let tool = &list["result"]["tools"][0];
+        assert_eq!(tool["name"], "get_current_weather");
+        assert_eq!(
+            tool["description"],
+            "Get the current weather in a given location"
+        );
+        assert_eq!(
+            tool["inputSchema"]["properties"]["unit"]["enum"],
+            json!(["celsius", "fahrenheit"])
+        );
+
+        let called = server
+            .handle(request(
+                2,
+                "tools/call",
+                json!({
+                    "name": "get_current_weather",
+                    "arguments": {"location": "New York", "unit": "celsius"}
+                }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            called["result"],
+            json!({
+                "content": [{"type": "text", "text": "Weather for New York in celsius"}],
+                "isError": false
+            })
+        );
+
+        let invalid = server
+            .handle(request(
+                3,
+                "tools/call",
+                json!({"name": "get_current_weather", "arguments": {"location": "Oslo"}}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(invalid["result"]["isError"], true);
+        assert!(invalid["result"]["content"][0]["text"]
+            .as_str()
+            .unwrap()
+            .contains("\"unit\" is a required property"));
+
+        let unknown = server
+            .handle(request(4, "tools/call", json!({"name": "nope"})))
+            .await
+            .unwrap();
+        assert_eq!(unknown["error"]["message"], "Unknown tool: nope");
+
+        let notification = json!({"jsonrpc": "2.0", "method": "notifications/initialized"});
+        assert!(server.handle(notification).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_serve_answers_each_line() {
+        let (client, server_end) = tokio::io::duplex(4096);
+        let (server_input, server_output) = tokio::io::split(server_end);
+        let serving = tokio::spawn(server().serve(server_input, server_output));
+
+        let (client_input, mut client_output) = tokio::io::split(client);
+        client_output
+            .write_all(
+                concat!(
+                    r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+                    "\n",
+                    r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+                    "\n",
+                    "not json\n",
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+        client_output.shutdown().await.unwrap();
+
+        let mut replies = Vec::new();
+        let mut lines = BufReader::new(client_input).lines();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            replies.push(serde_json::from_str::<Value>(&line).unwrap());
+            if replies.len() == 2 {
+                break;
+            }
+        }
+        replies.sort_by_key(|reply| reply["id"].is_null());
+        assert_eq!(replies[0]["result"]["serverInfo"]["name"], "autogen");
+        assert_eq!(replies[1]["error"]["code"], -32700);
+        serving.await.unwrap().unwrap();
+    }
+}
@@ -1,8 +1,8 @@
 use serde::{Deserialize, Serialize};
 
 use crate::msg_types::{
-    AgentId, ContentData, Func, FunctionExecutionResult, GetContent, ImageContent,
-    MultiModalContent, TextContent
+    AgentId, AudioContent, ContentData, Func, FunctionExecutionResult, GetContent,
+    MultiModalContent, TextContent,
 };
 use crate::tool_types::FunctionCallInput;
 
@@ -10,6 +10,7 @@ use crate::tool_types::FunctionCallInput;
 pub enum ChatMessage {
     TextMessage(TextMessage),
     MultiModalMessage(MultiModalMessage),
+    AudioMessage(AudioMessage),
     ToolCallMessage(ToolCallMessage),
     ToolCallResultMessage(ToolCallResultMessage),
     StopMessage(String),
@@ -19,12 +20,8 @@ impl GetContent for ChatMessage {
     fn get_content(&self) -> ContentData<'_> {
         match self {
             ChatMessage::TextMessage(msg) => ContentData::Text( msg.content.text.to_string()),
-            ChatMessage::MultiModalMessage(msg) => match &msg.content {
-                MultiModalContent::Text(TextContent { text: tex }) => {
-                    ContentData::Text(tex.clone())
-                }
-                MultiModalContent::Image(ImageContent { image: img }) => ContentData::Image(img),
-            },
+            ChatMessage::MultiModalMessage(msg) => msg.content.get_content(),
+            ChatMessage::AudioMessage(msg) => msg.content.get_content(),
             ChatMessage::ToolCallMessage(msg) => {
                 ContentData::Text(format!("{:?}", msg.content.content))
             }
@@ -47,7 +44,14 @@ pub struct TextMessage {
 }
 #[derive(Clone, Debug)]
 pub struct MultiModalMessage {
-    pub content: MultiModalContent,
+    pub content: Vec<MultiModalContent>,
+    pub source: AgentId,
+}
+/// A voice message, or an agent's spoken reply together with its text.
+#[derive(Clone, Debug)]
+pub struct AudioMessage {
+    pub content: AudioContent,
+    pub transcript: Option<String>,
     pub source: AgentId,
 }
 #[derive(Clone, Debug)]
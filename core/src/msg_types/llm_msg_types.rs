@@ -2,10 +2,8 @@ use crate::msg_types::{
     chat_msg_types::AssistantMessageContent, FunctionExecutionResult, ImageContent,
     MultiModalContent, TextContent,
 };
-use crate::{
-    msg_types::{AgentId, TopicId},
-    tool_types::FunctionCallInput,
-};
+use crate::{msg_types::AgentId, tool_types::FunctionCallInput};
+use serde_json::Value;
 
 #[derive(Debug, Clone)]
 pub enum LlmMessage {
@@ -22,7 +20,7 @@ pub struct SystemMessage {
 }
 #[derive(Clone, Debug)]
 pub struct UserMessage {
-    pub content: MultiModalContent,
+    pub content: Vec<MultiModalContent>,
     pub source: AgentId,
 }
 #[derive(Debug, Clone)]
@@ -48,20 +46,28 @@ impl LlmMessage {
 
     pub fn user_text(content: impl Into<String>, source: AgentId) -> Self {
         LlmMessage::UserMessage(UserMessage {
-            content: MultiModalContent::Text(TextContent {
+            content: vec![MultiModalContent::Text(TextContent {
                 text: content.into(),
-            }),
+            })],
             source: source.into(),
         })
     }
 
-    pub fn user_image(image_data: &'static [u8], source: AgentId) -> Self {
+    pub fn user_image(image: ImageContent, source: AgentId) -> Self {
         LlmMessage::UserMessage(UserMessage {
-            content: MultiModalContent::Image(ImageContent { image: image_data }),
+            content: vec![MultiModalContent::Image(image)],
             source: source.into(),
         })
     }
 
+    /// A user turn mixing text and images, e.g. a question about a picture.
+    pub fn user_multimodal(parts: Vec<MultiModalContent>, source: AgentId) -> Self {
+        LlmMessage::UserMessage(UserMessage {
+            content: parts,
+            source,
+        })
+    }
+
     pub fn assistant_text(content: impl Into<String>, source: AgentId) -> Self {
         LlmMessage::AssistantMessage(AssistantMessage {
             content: AssistantMessageContent::TextContent(TextContent {
@@ -78,11 +84,19 @@ impl LlmMessage {
         })
     }
 
-    pub fn function_result(content: impl Into<String>, call_id: TopicId, source: AgentId) -> Self {
+    pub fn function_result(
+        content: impl Into<String>,
+        call_id: impl Into<String>,
+        source: AgentId,
+    ) -> Self {
+        let content = content.into();
         LlmMessage::FunctionExecutionResultMessage(FunctionExecutionResultMessage {
             content: vec![FunctionExecutionResult {
-                content: content.into(),
-                call_id: call_id,
+                value: Value::String(content.clone()),
+                content,
+                call_id: call_id.into(),
+                is_error: false,
+                attachments: Vec::new(),
             }],
             source: source.into(),
         })
@@ -0,0 +1,296 @@
+use std::io::Cursor;
+use std::path::Path;
+
+use base64::Engine;
+use image::{imageops::FilterType, ImageFormat};
+
+/// An owned image with its MIME type. Build it from a file, raw bytes, a URL or a
+/// base64 data URI; the format is sniffed from the file signature, so the bytes
+/// always agree with the MIME type the backends send.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageContent {
+    pub data: Vec<u8>,
+    pub mime_type: String,
+}
+
+impl ImageContent {
+    pub fn from_bytes(data: impl Into<Vec<u8>>) -> anyhow::Result<Self> {
+        let data = data.into();
+        let mime_type = sniff_image_mime(&data)
+            .ok_or_else(|| anyhow::anyhow!("Unsupported or unrecognised image format"))?;
+        Ok(ImageContent {
+            data,
+            mime_type: mime_type.to_string(),
+        })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read(path)
+            .map_err(|e| anyhow::anyhow!("Error reading image file {}: {}", path.display(), e))?;
+        let mime_type = sniff_image_mime(&data)
+            .or_else(|| mime_from_extension(path))
+            .ok_or_else(|| {
+                anyhow::anyhow!("Unsupported image format for file {}", path.display())
+            })?;
+        Ok(ImageContent {
+            data,
+            mime_type: mime_type.to_string(),
+        })
+    }
+
+    /// Parses `data:<mime>;base64,<payload>`.
+    pub fn from_data_uri(uri: &str) -> anyhow::Result<Self> {
+        let rest = uri
+            .strip_prefix("data:")
+            .ok_or_else(|| anyhow::anyhow!("Not a data URI"))?;
+        let (header, payload) = rest
+            .split_once(',')
+            .ok_or_else(|| anyhow::anyhow!("Malformed data URI"))?;
+        let declared = header
+            .strip_suffix(";base64")
+            .ok_or_else(|| anyhow::anyhow!("Only base64 data URIs are supported"))?;
+
+        let data = base64::engine::general_purpose::STANDARD.decode(payload.trim())?;
+        let mime_type = sniff_image_mime(&data)
+            .map(str::to_string)
+            .or_else(|| declared.starts_with("image/").then(|| declared.to_string()))
+            .ok_or_else(|| anyhow::anyhow!("Data URI does not contain an image"))?;
+        Ok(ImageContent { data, mime_type })
+    }
+
+    /// Downloads the image; `data:` URIs are decoded in place.
+    pub async fn from_url(url: &str) -> anyhow::Result<Self> {
+        if url.starts_with("data:") {
+            return Self::from_data_uri(url);
+        }
+        let response = reqwest::get(url).await?.error_for_status()?;
+        let declared = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.split(';').next().unwrap_or_default().trim().to_string());
+        let data = response.bytes().await?.to_vec();
+
+        let mime_type = sniff_image_mime(&data)
+            .map(str::to_string)
+            .or_else(|| declared.filter(|d| d.starts_with("image/")))
+            .ok_or_else(|| anyhow::anyhow!("{} did not return an image", url))?;
+        Ok(ImageContent { data, mime_type })
+    }
+
+    pub fn to_base64(&self) -> String {
+        base64::engine::general_purpose::STANDARD.encode(&self.data)
+    }
+
+    pub fn to_data_uri(&self) -> String {
+        format!("data:{};base64,{}", self.mime_type, self.to_base64())
+    }
+
+    /// File extension matching the MIME type, e.g. `png`.
+    pub fn extension(&self) -> &str {
+        match self.mime_type.as_str() {
+            "image/jpeg" => "jpg",
+            mime => mime.strip_prefix("image/").unwrap_or("bin"),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, &self.data)
+            .map_err(|e| anyhow::anyhow!("Error writing image file {}: {}", path.display(), e))
+    }
+
+    pub fn dimensions(&self) -> anyhow::Result<(u32, u32)> {
+        let image = image::load_from_memory(&self.data)?;
+        Ok((image.width(), image.height()))
+    }
+
+    /// Shrinks the image so neither side exceeds `max_side`, keeping the aspect
+    /// ratio. JPEGs stay JPEG; everything else is re-encoded as PNG. Images that
+    /// already fit are returned unchanged.
+    pub fn downscale(&self, max_side: u32) -> anyhow::Result<Self> {
+        let image = image::load_from_memory(&self.data)?;
+        if image.width() <= max_side && image.height() <= max_side {
+            return Ok(self.clone());
+        }
+
+        let resized = image.resize(max_side, max_side, FilterType::Triangle);
+        let (format, mime_type) = if self.mime_type == "image/jpeg" {
+            (ImageFormat::Jpeg, "image/jpeg")
+        } else {
+            (ImageFormat::Png, "image/png")
+        };
+        let mut data = Vec::new();
+        let resized = if format == ImageFormat::Jpeg {
+            image::DynamicImage::ImageRgb8(resized.to_rgb8())
+        } else {
+            resized
+        };
+        resized.write_to(&mut Cursor::new(&mut data), format)?;
+        Ok(ImageContent {
+            data,
+            mime_type: mime_type.to_string(),
+        })
+    }
+}
+
+/// An owned audio clip, e.g. a voice message. Like `ImageContent`, the MIME type
+/// is sniffed from the bytes when possible.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioContent {
+    pub data: Vec<u8>,
+    pub mime_type: String,
+}
+
+impl AudioContent {
+    pub fn from_bytes(data: impl Into<Vec<u8>>) -> anyhow::Result<Self> {
+        let data = data.into();
+        let mime_type = sniff_audio_mime(&data)
+            .ok_or_else(|| anyhow::anyhow!("Unsupported or unrecognised audio format"))?;
+        Ok(AudioContent {
+            data,
+            mime_type: mime_type.to_string(),
+        })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read(path)
+            .map_err(|e| anyhow::anyhow!("Error reading audio file {}: {}", path.display(), e))?;
+        let mime_type = sniff_audio_mime(&data)
+            .or_else(|| audio_mime_from_extension(path))
+            .ok_or_else(|| {
+                anyhow::anyhow!("Unsupported audio format for file {}", path.display())
+            })?;
+        Ok(AudioContent {
+            data,
+            mime_type: mime_type.to_string(),
+        })
+    }
+
+    pub fn to_base64(&self) -> String {
+        base64::engine::general_purpose::STANDARD.encode(&self.data)
+    }
+
+    /// File extension matching the MIME type, e.g. `mp3`. Transcription APIs use
+    /// the uploaded file name to pick a decoder.
+    pub fn extension(&self) -> &str {
+        match self.mime_type.as_str() {
+            "audio/mpeg" => "mp3",
+            "audio/mp4" => "m4a",
+            mime => mime.strip_prefix("audio/").unwrap_or("bin"),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, &self.data)
+            .map_err(|e| anyhow::anyhow!("Error writing audio file {}: {}", path.display(), e))
+    }
+}
+
+/// MIME type from the file signature, for the formats the vision APIs accept.
+pub fn sniff_image_mime(data: &[u8]) -> Option<&'static str> {
+    match data {
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some("image/png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        _ => None,
+    }
+}
+
+fn mime_from_extension(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    match ext.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+/// MIME type from the file signature, for the formats the transcription APIs accept.
+pub fn sniff_audio_mime(data: &[u8]) -> Option<&'static str> {
+    match data {
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some("audio/wav"),
+        [b'I', b'D', b'3', ..] | [0xFF, 0xFB | 0xF3 | 0xF2, ..] => Some("audio/mpeg"),
+        [b'O', b'g', b'g', b'S', ..] => Some("audio/ogg"),
+        [b'f', b'L', b'a', b'C', ..] => Some("audio/flac"),
+        [0x1A, 0x45, 0xDF, 0xA3, ..] => Some("audio/webm"),
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => Some("audio/mp4"),
+        _ => None,
+    }
+}
+
+fn audio_mime_from_extension(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    match ext.as_str() {
+        "wav" => Some("audio/wav"),
+        "mp3" | "mpga" | "mpeg" => Some("audio/mpeg"),
+        "ogg" | "oga" | "opus" => Some("audio/ogg"),
+        "flac" => Some("audio/flac"),
+        "webm" => Some("audio/webm"),
+        "m4a" | "mp4" => Some("audio/mp4"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = image::DynamicImage::new_rgb8(width, height);
+        let mut data = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_sniffs_and_round_trips_data_uri() {
+        let image = ImageContent::from_bytes(png(2, 2)).unwrap();
+        assert_eq!(image.mime_type, "image/png");
+
+        let decoded = ImageContent::from_data_uri(&image.to_data_uri()).unwrap();
+        assert_eq!(decoded, image);
+
+        assert!(ImageContent::from_bytes(b"not an image".to_vec()).is_err());
+        assert!(ImageContent::from_data_uri("data:text/plain;base64,aGk=").is_err());
+    }
+
+    #[test]
+    fn test_from_file_unknown_extension_is_an_error() {
+        let path = std::env::temp_dir().join(format!("image-{}.xyz", uuid::Uuid::new_v4()));
+        std::fs::write(&path, b"plain text").unwrap();
+        assert!(ImageContent::from_file(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_downscale_keeps_aspect_ratio() {
+        let image = ImageContent::from_bytes(png(400, 200)).unwrap();
+        let small = image.downscale(100).unwrap();
+        assert_eq!(small.dimensions().unwrap(), (100, 50));
+        assert_eq!(small.mime_type, "image/png");
+
+        let unchanged = small.downscale(100).unwrap();
+        assert_eq!(unchanged, small);
+    }
+
+    #[test]
+    fn test_sniffs_audio() {
+        let wav = AudioContent::from_bytes(b"RIFF\x24\x00\x00\x00WAVEfmt ".to_vec()).unwrap();
+        assert_eq!(wav.mime_type, "audio/wav");
+        assert_eq!(wav.extension(), "wav");
+
+        let mp3 = AudioContent::from_bytes(b"ID3\x04\x00".to_vec()).unwrap();
+        assert_eq!(mp3.extension(), "mp3");
+
+        assert!(AudioContent::from_bytes(b"not audio".to_vec()).is_err());
+    }
+}
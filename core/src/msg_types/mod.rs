@@ -1,11 +1,14 @@
 pub mod chat_msg_types;
 pub mod llm_msg_types;
+pub mod media;
+
+pub use media::{AudioContent, ImageContent};
 
 use serde::{Deserialize, Serialize};
 use std::error::Error;
 use uuid::Uuid;
 
-use crate::tool_types::SupportedType;
+use serde_json::Value;
 
 // pub type Func = Box<dyn Fn(&[u8]) -> Result<String, Box<dyn Error>> + Send + Sync>;
 
@@ -23,7 +26,7 @@ impl AgentId {
         match text {
             Some(tex) => {
                 let encoded = GeneralPurpose::new(&STANDARD, NO_PAD).encode(tex);
-                let padded = encoded[..36].to_string();
+                let padded = encoded.chars().take(36).collect::<String>();
                 AgentId(padded)
             }
             None => AgentId(Uuid::new_v4().to_string()),
@@ -48,7 +51,7 @@ impl TopicId {
         match text {
             Some(tex) => {
                 let encoded = GeneralPurpose::new(&STANDARD, NO_PAD).encode(tex);
-                let padded = encoded[..36].to_string();
+                let padded = encoded.chars().take(36).collect::<String>();
                 TopicId(padded)
             }
             None => TopicId(Uuid::new_v4().to_string()),
@@ -78,18 +81,14 @@ impl std::fmt::Display for FunctionToolError {
 impl Error for FunctionToolError {}
 
 pub trait CloneableFn:
-    Fn(&[SupportedType]) -> Result<String, Box<dyn Error + Send + Sync>> + Send + Sync
+    Fn(&[Value]) -> Result<String, Box<dyn Error + Send + Sync>> + Send + Sync
 {
     fn clone_box(&self) -> Box<dyn CloneableFn>;
 }
 
 impl<T> CloneableFn for T
 where
-    T: Fn(&[SupportedType]) -> Result<String, Box<dyn Error + Send + Sync>>
-        + Send
-        + Sync
-        + Clone
-        + 'static,
+    T: Fn(&[Value]) -> Result<String, Box<dyn Error + Send + Sync>> + Send + Sync + Clone + 'static,
 {
     fn clone_box(&self) -> Box<dyn CloneableFn> {
         Box::new(self.clone())
@@ -117,17 +116,6 @@ impl<T: ToString> From<T> for TextContent {
     }
 }
 
-impl From<&'static [u8]> for ImageContent {
-    fn from(image: &'static [u8]) -> Self {
-        ImageContent { image }
-    }
-}
-
-#[derive(Clone, Debug)]
-pub struct ImageContent {
-    pub image: &'static [u8],
-}
-
 impl GetContent for TextContent {
     fn get_content(&self) -> ContentData<'_> {
         ContentData::Text(self.text.clone())
@@ -136,7 +124,13 @@ impl GetContent for TextContent {
 
 impl GetContent for ImageContent {
     fn get_content(&self) -> ContentData<'_> {
-        ContentData::Image(self.image)
+        ContentData::Image(&self.data)
+    }
+}
+
+impl GetContent for AudioContent {
+    fn get_content(&self) -> ContentData<'_> {
+        ContentData::Audio(&self.data)
     }
 }
 
@@ -150,6 +144,8 @@ pub enum Content {
 pub enum ContentData<'a> {
     Text(String),
     Image(&'a [u8]),
+    Audio(&'a [u8]),
+    MultiModal(Vec<ContentData<'a>>),
 }
 
 pub trait GetContent {
@@ -174,16 +170,44 @@ impl GetContent for MultiModalContent {
     }
 }
 
+/// One part of a multimodal message; a message holds these in order.
 #[derive(Clone, Debug)]
 pub enum MultiModalContent {
     Text(TextContent),
     Image(ImageContent),
 }
 
+impl GetContent for [MultiModalContent] {
+    fn get_content(&self) -> ContentData<'_> {
+        match self {
+            [single] => single.get_content(),
+            parts => ContentData::MultiModal(parts.iter().map(|p| p.get_content()).collect()),
+        }
+    }
+}
+
+impl From<TextContent> for MultiModalContent {
+    fn from(text: TextContent) -> Self {
+        MultiModalContent::Text(text)
+    }
+}
+
+impl From<ImageContent> for MultiModalContent {
+    fn from(image: ImageContent) -> Self {
+        MultiModalContent::Image(image)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct FunctionExecutionResult {
+    /// What the model is shown: the rendered `value`, or the error.
     pub content: String,
-    pub call_id: TopicId,
+    pub call_id: String,
+    /// `content` describes why the call failed rather than its output.
+    pub is_error: bool,
+    /// The tool's return value, `Null` when the call failed.
+    pub value: Value,
+    pub attachments: Vec<MultiModalContent>,
 }
 
 #[derive(PartialEq)]
@@ -191,6 +215,7 @@ pub enum ResponseFormat {
     Text,
     JsonObject,
 }
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum FinishReason {
     Stop,
     Length,
@@ -198,9 +223,31 @@ pub enum FinishReason {
     ContentFilter,
 }
 
+impl FinishReason {
+    /// Maps the provider-specific `finish_reason` / `stop_reason` strings.
+    pub fn from_api(reason: &str) -> Self {
+        match reason {
+            "length" | "max_tokens" => FinishReason::Length,
+            "tool_calls" | "function_call" | "tool_use" => FinishReason::FunctionCall,
+            "content_filter" => FinishReason::ContentFilter,
+            _ => FinishReason::Stop,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub struct RequestUsage {
+    /// All prompt tokens, including any read from or written to a prompt cache.
     pub prompt_tokens: i32,
     pub completion_tokens: i32,
+    pub cache_creation_tokens: i32,
+    pub cache_read_tokens: i32,
+}
+
+impl RequestUsage {
+    pub fn total_tokens(&self) -> i64 {
+        self.prompt_tokens as i64 + self.completion_tokens as i64
+    }
 }
 
 pub struct CodeBlock {
@@ -1,15 +1,20 @@
 // pub mod tool;
+mod output;
+mod policy;
+mod schema;
 
-use ctor::ctor;
-use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
 use tool_builder::create_tool_with_function;
 
-pub static STORE: Lazy<Mutex<HashMap<String, Tool>>> = Lazy::new(|| Mutex::new(HashMap::new()));
-
+pub use output::{IntoToolOutput, ToolOutput, ToolRenderMode};
+pub use policy::{ApprovalRequest, Approver, ToolPermission, ToolPolicy, DEFAULT_APPROVAL_TIMEOUT};
+pub use schema::CompiledSchema;
 
 pub struct  AgentType(String);
 
@@ -25,6 +30,8 @@ impl std::fmt::Display for FunctionToolError {
 impl std::error::Error for FunctionToolError {}
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionCallInput {
+    #[serde(default)]
+    pub id: String,
     pub arguments_obj: Value,
     pub function_name: String,
     pub return_type: String,
@@ -38,7 +45,7 @@ pub struct FunctionCallInput {
 
 // impl FunctionCall {
 //     pub fn run(self) {
-//         let bindings = &STORE;
+//         let bindings = &REGISTRY;
 //         let function = bindings.get(&self.name).unwrap();
 
 //         function(self.args);
@@ -47,7 +54,21 @@ pub struct FunctionCallInput {
 
 type MyResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
-#[create_tool_with_function(PROCESS_VALUE_TOOL_DEF_OBJ)]
+/// What a tool's function returns; synchronous tools run on the blocking pool.
+pub type ToolFuture = Pin<Box<dyn Future<Output = MyResult<ToolOutput>> + Send>>;
+
+/// Used unless the tool sets `timeout_secs` or `Tool::with_timeout` is called.
+pub const DEFAULT_TOOL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Processes up to 5 different types of values
+///
+/// # Arguments
+/// * `a` - An integer value
+/// * `b` - A floating-point value
+/// * `c` - A boolean value
+/// * `d` - A string value
+/// * `e` - Another integer value
+#[create_tool_with_function]
 fn process_values(a: i32, b: f32, c: bool, d: String, e: i32) -> MyResult<String> {
     if a > 10 {
         Ok(format!(
@@ -63,8 +84,17 @@ fn process_values(a: i32, b: f32, c: bool, d: String, e: i32) -> MyResult<String
     }
 }
 
-#[create_tool_with_function(GET_WEATHER_TOOL_DEF_OBJ)]
-fn get_current_weather(location: String, unit: String) -> MyResult<String> {
+/// Get the current weather in a given location
+///
+/// # Arguments
+/// * `location` - The city and state, e.g. San Francisco, CA
+/// * `unit` - The unit of measurement
+#[create_tool_with_function]
+fn get_current_weather(location: String, unit: TemperatureUnit) -> MyResult<String> {
+    let unit = match unit {
+        TemperatureUnit::Celsius => "celsius",
+        TemperatureUnit::Fahrenheit => "fahrenheit",
+    };
     if location.contains("New") {
         Ok(format!("Weather for {} in {}", location, unit))
     } else {
@@ -72,265 +102,478 @@ fn get_current_weather(location: String, unit: String) -> MyResult<String> {
     }
 }
 
-pub const GET_WEATHER_TOOL_DEF_OBJ: &str = r#"
-        {
-            "name": "get_current_weather",
-            "description": "Get the current weather in a given location",
-            "parameters": {
-                "type": "object",
-                "properties": {
-                    "location": {
-                        "type": "string",
-                        "description": "The city and state, e.g. San Francisco, CA"
-                    },
-                    "unit": {
-                        "type": "string",
-                        "enum": ["celsius", "fahrenheit"],
-                        "description": "The unit of measurement"
-                    }
-                },
-                "required": ["location", "unit"]
-            }
-        }
-        "#;
-
-pub const PROCESS_VALUE_TOOL_DEF_OBJ: &str = r#"
-{
-    "name": "process_values",
-    "description": "Processes up to 5 different types of values",
-    "parameters": {
-        "type": "object",
-        "properties": {
-            "a": {
-                "type": "i32",
-                "description": "An integer value"
-            },
-            "b": {
-                "type": "f32",
-                "description": "A floating-point value"
-            },
-            "c": {
-                "type": "bool",
-                "description": "A boolean value"
-            },
-            "d": {
-                "type": "string",
-                "description": "A string value"
-            },
-            "e": {
-                "type": "i32",
-                "description": "Another integer value"
-            }
-        },
-        "required": ["a", "b", "c", "d", "e"]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TemperatureUnit {
+    Celsius,
+    Fahrenheit,
+}
+
+impl ToolArgSchema for TemperatureUnit {
+    fn tool_schema() -> Value {
+        serde_json::json!({"type": "string", "enum": ["celsius", "fahrenheit"]})
     }
 }
-"#;
-
-#[derive(Debug, Clone)]
-pub enum SupportedType {
-    I32(i32),
-    F32(f32),
-    Bool(bool),
-    String(String),
+
+/// JSON schema of a tool argument type that `create_tool_with_function` cannot
+/// work out from the signature alone, such as a struct or enum. With the
+/// `schemars` feature every `schemars::JsonSchema` type implements it.
+pub trait ToolArgSchema {
+    fn tool_schema() -> Value;
+}
+
+#[cfg(feature = "schemars")]
+impl<T: schemars::JsonSchema> ToolArgSchema for T {
+    fn tool_schema() -> Value {
+        // Tool parameters are a single object, so nested definitions are inlined
+        // rather than referenced.
+        let settings = schemars::gen::SchemaSettings::draft07().with(|settings| {
+            settings.inline_subschemas = true;
+            settings.meta_schema = None;
+        });
+        let root = settings.into_generator().into_root_schema_for::<T>();
+        let mut schema = serde_json::to_value(root.schema).unwrap_or_default();
+        if let Some(map) = schema.as_object_mut() {
+            map.remove("title");
+        }
+        schema
+    }
 }
 
 // Define Tool struct
 #[derive(Clone)]
 pub struct Tool {
     pub name: String,
-    pub function: Arc<dyn Fn(&[SupportedType]) -> MyResult<String> + Send + Sync>,
+    /// Takes the arguments in `arg_names` order, `Null` for omitted optional ones,
+    /// followed by the whole validated argument object for tools whose schema
+    /// allows properties beyond `arg_names`.
+    pub function: Arc<dyn Fn(Vec<Value>) -> ToolFuture + Send + Sync>,
     pub tool_def_obj: String,
     pub arg_names: Vec<String>,
     pub arg_types: Vec<String>,
+    /// Whether each argument is an `Option` the model may leave out.
+    pub arg_optional: Vec<bool>,
+    pub timeout: Duration,
+    /// The `parameters` of `tool_def_obj`, checked before every call.
+    pub schema: CompiledSchema,
 }
 impl Tool {
-    pub fn run(&self, arguments_w_val: Value) -> MyResult<String> {
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Checks the arguments against `schema`, then calls the function. Fails once
+    /// `timeout` has passed; a synchronous tool that is still running then
+    /// finishes in the background and its output is dropped.
+    pub async fn run(&self, arguments_w_val: Value) -> MyResult<ToolOutput> {
+        // Backends pass on arguments they could not parse as the raw string.
+        let arguments_w_val = match arguments_w_val {
+            Value::String(raw) => serde_json::from_str(&raw)
+                .map_err(|e| format!("arguments are not valid JSON: {}", e))?,
+            arguments => arguments,
+        };
         let arguments = arguments_w_val
             .as_object()
             .ok_or("Invalid arguments format")?;
-        let mut ordered_vals = Vec::new();
+        let mut found_args = match arguments.get("arguments") {
+            // Handle the case where "arguments" is an array of objects
+            Some(Value::Array(array)) => array
+                .iter()
+                .filter_map(Value::as_object)
+                .flat_map(|item| item.clone())
+                .collect(),
+            // Handle the case where "arguments" is an object
+            Some(Value::Object(obj)) => obj.clone(),
+            Some(_) => return Err("Invalid arguments format".into()),
+            // Otherwise the arguments are at the top level
+            None => arguments.clone(),
+        };
+        // An explicit null for an optional argument means it was left out.
+        for (i, arg_name) in self.arg_names.iter().enumerate() {
+            let optional = self.arg_optional.get(i).copied().unwrap_or(false);
+            if optional && found_args.get(arg_name) == Some(&Value::Null) {
+                found_args.remove(arg_name);
+            }
+        }
+
+        let found_args = Value::Object(found_args);
+        if let Err(violations) = self.schema.validate(&found_args) {
+            return Err(format!(
+                "Invalid arguments for {}:\n- {}",
+                self.name,
+                violations.join("\n- ")
+            )
+            .into());
+        }
 
+        let mut ordered_vals = Vec::new();
         for (i, arg_name) in self.arg_names.iter().enumerate() {
-            let arg_value = if let Some(args) = arguments.get("arguments") {
-                // Handle the case where "arguments" is an array
-                if let Some(array) = args.as_array() {
-                    // Search for the argument in the array
-                    let mut found = None;
-                    for item in array {
-                        if let Some(obj) = item.as_object() {
-                            if let Some(value) = obj.get(arg_name) {
-                                found = Some(value.clone());
-                                break;
-                            }
-                        }
-                    }
-                    found.ok_or(format!("Missing argument: {}", arg_name))?
-                } else if let Some(obj) = args.as_object() {
-                    // Handle the case where "arguments" is an object
-                    obj.get(arg_name)
-                        .ok_or(format!("Missing argument: {}", arg_name))?
-                        .clone()
-                } else {
-                    return Err("Invalid arguments format".into());
-                }
-            } else {
-                // Try to get the argument from the top level
-                arguments
-                    .get(arg_name)
-                    .ok_or(format!("Missing argument: {}", arg_name))?
-                    .clone()
+            let arg_value = match found_args.get(arg_name) {
+                Some(value) => value.clone(),
+                None if self.arg_optional.get(i).copied().unwrap_or(false) => Value::Null,
+                None => return Err(format!("Missing argument: {}", arg_name).into()),
             };
-
-            let parsed_arg = parse_argument(&self.arg_types[i], &arg_value);
-            ordered_vals.push(parsed_arg);
+            ordered_vals.push(arg_value);
         }
+        ordered_vals.push(found_args);
 
-        (self.function)(&ordered_vals)
+        match tokio::time::timeout(self.timeout, (self.function)(ordered_vals)).await {
+            Ok(outcome) => outcome,
+            Err(_) => Err(format!("Tool {} timed out after {:?}", self.name, self.timeout).into()),
+        }
     }
 }
 
-pub fn parse_argument(arg_type: &str, arg_value: &Value) -> SupportedType {
-    match arg_type {
-        "i32" => {
-            if let Some(n) = arg_value.as_i64() {
-                SupportedType::I32(n as i32)
-            } else {
-                panic!("Expected i32 for argument");
-            }
-        }
-        "f32" => {
-            if let Some(f) = arg_value.as_f64() {
-                SupportedType::F32(f as f32)
-            } else {
-                panic!("Expected f32 for argument");
-            }
-        }
-        "bool" => {
-            if let Some(b) = arg_value.as_bool() {
-                SupportedType::Bool(b)
-            } else if let Some(s) = arg_value.as_str() {
-                match s {
-                    "true" => SupportedType::Bool(true),
-                    "false" => SupportedType::Bool(false),
-                    _ => panic!("Expected bool for argument"),
-                }
-            } else {
-                panic!("Expected bool for argument");
-            }
-        }
-        "String" | "&str" => {
-            if let Some(s) = arg_value.as_str() {
-                SupportedType::String(s.to_string())
-            } else {
-                panic!("Expected String for argument");
-            }
-        }
-        _ => panic!("Invalid type"),
-    }
+/// The tools one agent may call, keyed by name. Build it from the `<fn>_tool()`
+/// constructors generated by `create_tool_with_function`; clones share the
+/// underlying functions.
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: BTreeMap<String, Tool>,
 }
 
-type ParserFn = dyn Fn(SupportedType) -> MyResult<Box<dyn std::any::Any>> + Send + Sync;
+impl ToolRegistry {
+    pub fn new() -> Self {
+        ToolRegistry::default()
+    }
 
-pub fn get_parsers() -> HashMap<&'static str, Box<ParserFn>> {
-    let mut parsers = HashMap::new();
+    pub fn with_tool(mut self, tool: Tool) -> Self {
+        self.register(tool);
+        self
+    }
 
-    parsers.insert(
-        "i32",
-        Box::new(|v| {
-            if let SupportedType::I32(val) = v {
-                Ok(Box::new(val) as Box<dyn std::any::Any>)
-            } else {
-                Err("Type mismatch".into())
-            }
-        }) as Box<ParserFn>,
-    );
-
-    parsers.insert(
-        "f32",
-        Box::new(|v| {
-            if let SupportedType::F32(val) = v {
-                Ok(Box::new(val) as Box<dyn std::any::Any>)
-            } else {
-                Err("Type mismatch".into())
-            }
-        }) as Box<ParserFn>,
-    );
-
-    parsers.insert(
-        "bool",
-        Box::new(|v| {
-            if let SupportedType::Bool(val) = v {
-                Ok(Box::new(val) as Box<dyn std::any::Any>)
-            } else {
-                Err("Type mismatch".into())
-            }
-        }) as Box<ParserFn>,
-    );
-
-    parsers.insert(
-        "String",
-        Box::new(|v| {
-            if let SupportedType::String(val) = v {
-                Ok(Box::new(val.clone()) as Box<dyn std::any::Any>)
-            } else {
-                Err("Type mismatch".into())
-            }
-        }) as Box<ParserFn>,
-    );
-
-    parsers.insert(
-        "&str",
-        Box::new(|v| {
-            if let SupportedType::String(val) = v {
-                Ok(Box::new(val.to_string()) as Box<dyn std::any::Any>)
-            } else {
-                Err("Type mismatch".into())
-            }
-        }) as Box<ParserFn>,
-    );
+    /// Adds `tool`, returning the one it replaced if the name was taken.
+    pub fn register(&mut self, tool: Tool) -> Option<Tool> {
+        self.tools.insert(tool.name.clone(), tool)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Tool> {
+        self.tools.get(name)
+    }
 
-    parsers
+    pub fn names(&self) -> Vec<&str> {
+        self.tools.keys().map(|name| name.as_str()).collect()
+    }
+
+    /// All tools, ordered by name so prompts stay stable.
+    pub fn to_vec(&self) -> Vec<Tool> {
+        self.tools.values().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tools.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    pub async fn run(&self, call: &FunctionCallInput) -> MyResult<ToolOutput> {
+        match self.get(&call.function_name) {
+            Some(tool) => tool.run(call.arguments_obj.clone()).await,
+            None => Err(format!(
+                "Unknown tool {}. Available tools: {}",
+                call.function_name,
+                self.names().join(", ")
+            )
+            .into()),
+        }
+    }
+}
+
+impl FromIterator<Tool> for ToolRegistry {
+    fn from_iter<I: IntoIterator<Item = Tool>>(iter: I) -> Self {
+        let mut registry = ToolRegistry::new();
+        for tool in iter {
+            registry.register(tool);
+        }
+        registry
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_get_current_weather() {
-        let store = STORE.lock().unwrap();
+    #[tokio::test]
+    async fn test_get_current_weather() {
+        let store = ToolRegistry::new()
+            .with_tool(get_current_weather_tool())
+            .with_tool(process_values_tool());
 
         let llm_output = serde_json::json!({
-            "location": "York, NY",
+            "location": "New York, NY",
             "unit": "fahrenheit"
-        }); // Use your tools as needed
+        });
+        let weather = store
+            .get("get_current_weather")
+            .unwrap()
+            .run(llm_output)
+            .await
+            .unwrap();
+        assert_eq!(weather.value, "Weather for New York, NY in fahrenheit");
+
+        // Values must have their schema types; "true" is no longer taken for a bool.
+        let arguments = |c: Value| {
+            serde_json::json!({
+                "arguments": [
+                    { "a": 20 },
+                    { "b": 2.5 },
+                    { "c": c },
+                    { "d": "example" },
+                    { "e": 100 }
+                ]
+            })
+        };
+        let tool = store.get("process_values").unwrap();
+        let err = tool.run(arguments(Value::from("true"))).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid arguments for process_values:\n- c: \"true\" is not of type \"boolean\""
+        );
+        let processed = tool.run(arguments(Value::from(true))).await.unwrap();
+        assert_eq!(
+            processed.value,
+            "Processed: a = 20, b = 2.5, c = true, d = example, e = 100"
+        );
+    }
 
-        if let Some(tool) = store.get("get_current_weather") {
-            println!("tool sig : {:?}", tool.name.clone());
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Units {
+        Metric,
+        Imperial,
+    }
 
-            match tool.run(llm_output) {
-                Ok(result) => println!("Result: {}", result),
-                Err(e) => eprintln!("Error: {e}"),
-            }
+    #[derive(Debug, Deserialize)]
+    struct Coordinates {
+        lat: f64,
+        lon: f64,
+    }
+
+    impl ToolArgSchema for Units {
+        fn tool_schema() -> Value {
+            serde_json::json!({"type": "string", "enum": ["metric", "imperial"]})
         }
+    }
 
-        let json_input = serde_json::json!({
-            "arguments": [
-                { "a": 20 },
-                { "b": 3.14 },
-                { "c": "true" },
-                { "d": "example" },
-                { "e": 100 }
-            ]
-        });
+    impl ToolArgSchema for Coordinates {
+        fn tool_schema() -> Value {
+            serde_json::json!({
+                "type": "object",
+                "properties": {"lat": {"type": "number"}, "lon": {"type": "number"}},
+                "required": ["lat", "lon"]
+            })
+        }
+    }
 
-        if let Some(tool) = store.get("process_values") {
-            println!("tool sig : {:?}", tool.name.clone());
-            match tool.run(json_input) {
-                Ok(result) => println!("Result: {}", result),
-                Err(e) => eprintln!("Error: {e}"),
-            }
+    /// Hourly forecast for a weather station.
+    ///
+    /// # Arguments
+    /// * `at` - Where the station is
+    /// * `hours` - Hours from now to forecast,
+    ///   at most 48
+    /// * `units` - Defaults to metric
+    #[create_tool_with_function]
+    fn get_forecast(
+        at: Coordinates,
+        hours: Vec<u32>,
+        units: Option<Units>,
+        station: &str,
+    ) -> MyResult<String> {
+        Ok(format!(
+            "{} ({}, {}) {:?} in {:?}",
+            station,
+            at.lat,
+            at.lon,
+            hours,
+            units.unwrap_or(Units::Metric)
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_serde_arguments_and_optional_parameters() {
+        let tool = get_forecast_tool();
+        assert_eq!(tool.arg_optional, vec![false, false, true, false]);
+
+        let with_units = tool
+            .run(serde_json::json!({
+                "at": {"lat": 52.5, "lon": 13.4},
+                "hours": [6, 12],
+                "units": "imperial",
+                "station": "Tegel"
+            }))
+            .await;
+        assert_eq!(
+            with_units.unwrap().value,
+            "Tegel (52.5, 13.4) [6, 12] in Imperial"
+        );
+
+        let without_units = tool
+            .run(serde_json::json!({
+                "arguments": {
+                    "at": {"lat": 0.0, "lon": 0.0},
+                    "hours": [],
+                    "units": null,
+                    "station": "Null Island"
+                }
+            }))
+            .await;
+        assert_eq!(
+            without_units.unwrap().value,
+            "Null Island (0, 0) [] in Metric"
+        );
+
+        let negative = tool
+            .run(serde_json::json!({
+                "at": {"lat": 0.0, "lon": 0.0}, "hours": [-1], "station": "x"
+            }))
+            .await;
+        assert_eq!(
+            negative.unwrap_err().to_string(),
+            "Invalid arguments for get_forecast:\n- hours.0: -1 is less than the minimum of 0"
+        );
+    }
+
+    #[create_tool_with_function(schema = r#"{
+        "name": "shout",
+        "description": "Repeats the text in capitals",
+        "parameters": {
+            "type": "object",
+            "properties": {"text": {"type": "string", "maxLength": 100}},
+            "required": ["text"]
         }
+    }"#)]
+    fn shout(text: String) -> MyResult<String> {
+        Ok(text.to_uppercase())
+    }
+
+    #[tokio::test]
+    async fn test_schema_is_generated_from_signature_and_docs() {
+        let schema: Value = serde_json::from_str(&get_forecast_tool().tool_def_obj).unwrap();
+
+        assert_eq!(schema["name"], "get_forecast");
+        assert_eq!(
+            schema["description"],
+            "Hourly forecast for a weather station."
+        );
+        let properties = &schema["parameters"]["properties"];
+        assert_eq!(properties["at"]["description"], "Where the station is");
+        assert_eq!(
+            properties["at"]["required"],
+            serde_json::json!(["lat", "lon"])
+        );
+        assert_eq!(
+            properties["hours"],
+            serde_json::json!({
+                "type": "array",
+                "items": {"type": "integer", "minimum": 0},
+                "description": "Hours from now to forecast, at most 48"
+            })
+        );
+        assert_eq!(properties["units"]["enum"][1], "imperial");
+        assert_eq!(properties["station"], serde_json::json!({"type": "string"}));
+        assert_eq!(
+            schema["parameters"]["required"],
+            serde_json::json!(["at", "hours", "station"])
+        );
+
+        let values: Value = serde_json::from_str(&process_values_tool().tool_def_obj).unwrap();
+        assert_eq!(values["parameters"]["properties"]["a"]["type"], "integer");
+        assert_eq!(values["parameters"]["properties"]["b"]["type"], "number");
+
+        let tool = shout_tool();
+        let schema: Value = serde_json::from_str(&tool.tool_def_obj).unwrap();
+        assert_eq!(schema["parameters"]["properties"]["text"]["maxLength"], 100);
+        assert_eq!(
+            tool.run(serde_json::json!({"text": "hi"}))
+                .await
+                .unwrap()
+                .value,
+            "HI"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bad_arguments_are_errors_not_panics() {
+        let tool = process_values_tool();
+
+        let wrong_types = tool
+            .run(serde_json::json!({
+                "a": "twenty", "b": 2.5, "c": "yes", "d": "x", "e": 1
+            }))
+            .await;
+        assert_eq!(
+            wrong_types.unwrap_err().to_string(),
+            "Invalid arguments for process_values:\n\
+             - a: \"twenty\" is not of type \"integer\"\n\
+             - c: \"yes\" is not of type \"boolean\""
+        );
+
+        let missing = tool.run(serde_json::json!({"a": 1, "c": true})).await;
+        let error = missing.unwrap_err().to_string();
+        assert!(error.contains("\"b\" is a required property"));
+        assert!(error.contains("\"e\" is a required property"));
+
+        // Valid JSON schema integers can still be out of range for the Rust type.
+        let overflow = tool
+            .run(serde_json::json!({
+                "a": 10_000_000_000i64, "b": 2.5, "c": true, "d": "x", "e": 1
+            }))
+            .await;
+        let error = overflow.unwrap_err().to_string();
+        assert!(error.starts_with("Invalid argument a: invalid value: integer `10000000000`"));
+        assert!(error.ends_with("expected i32"));
+    }
+
+    #[tokio::test]
+    async fn test_registries_are_independent() {
+        let weather = ToolRegistry::from_iter([get_current_weather_tool()]);
+        let mut values = ToolRegistry::new().with_tool(process_values_tool());
+        assert!(values.register(process_values_tool()).is_some());
+
+        let call = FunctionCallInput {
+            id: "call_0".to_string(),
+            arguments_obj: serde_json::json!({"location": "New York", "unit": "celsius"}),
+            function_name: "get_current_weather".to_string(),
+            return_type: "".to_string(),
+        };
+
+        assert_eq!(
+            weather.run(&call).await.unwrap().value,
+            "Weather for New York in celsius"
+        );
+        assert_eq!(
+            values.run(&call).await.unwrap_err().to_string(),
+            "Unknown tool get_current_weather. Available tools: process_values"
+        );
+        assert_eq!(values.len(), 1);
+    }
+
+    /// Waits the given number of milliseconds.
+    ///
+    /// # Arguments
+    /// * `millis` - How long to wait
+    #[create_tool_with_function(timeout_secs = 1)]
+    async fn wait(millis: u64) -> MyResult<String> {
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+        Ok(format!("waited {}ms", millis))
+    }
+
+    #[tokio::test]
+    async fn test_async_tool_times_out() {
+        let tool = wait_tool();
+        assert_eq!(tool.timeout, Duration::from_secs(1));
+
+        let quick = tool.run(serde_json::json!({"millis": 10})).await;
+        assert_eq!(quick.unwrap().value, "waited 10ms");
+
+        let impatient = tool.with_timeout(Duration::from_millis(50));
+        let slow = impatient.run(serde_json::json!({"millis": 5000})).await;
+        assert_eq!(
+            slow.unwrap_err().to_string(),
+            "Tool wait timed out after 50ms"
+        );
     }
 }
@@ -0,0 +1,207 @@
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::msg_types::{ImageContent, MultiModalContent, TextContent};
+
+use super::MyResult;
+
+/// Strings longer than this are cut short in `ToolRenderMode::Summary`.
+const SUMMARY_FIELD_CHARS: usize = 200;
+
+/// What a tool produced: a JSON value, plus images or file contents that are
+/// passed to the model as multimodal content.
+#[derive(Debug, Clone)]
+pub struct ToolOutput {
+    pub value: Value,
+    pub attachments: Vec<MultiModalContent>,
+}
+
+impl ToolOutput {
+    pub fn new(value: impl Serialize) -> MyResult<Self> {
+        Ok(ToolOutput {
+            value: serde_json::to_value(value)?,
+            attachments: Vec::new(),
+        })
+    }
+
+    pub fn with_image(mut self, image: ImageContent) -> Self {
+        self.attachments.push(MultiModalContent::Image(image));
+        self
+    }
+
+    /// Attaches an image file as an image and anything else as UTF-8 text.
+    pub fn with_file(mut self, path: impl AsRef<Path>) -> MyResult<Self> {
+        let path = path.as_ref();
+        let attachment = match ImageContent::from_file(path) {
+            Ok(image) => MultiModalContent::Image(image),
+            Err(_) => {
+                let text = std::fs::read_to_string(path)
+                    .map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+                MultiModalContent::Text(TextContent {
+                    text: format!("{}:\n{}", path.display(), text),
+                })
+            }
+        };
+        self.attachments.push(attachment);
+        Ok(self)
+    }
+
+    /// The text the model sees in place of the value. Plain strings are passed
+    /// through as is in both modes.
+    pub fn render(&self, mode: ToolRenderMode) -> String {
+        let rendered = match (&self.value, mode) {
+            (Value::String(text), ToolRenderMode::CompactJson) => text.clone(),
+            (value, ToolRenderMode::CompactJson) => value.to_string(),
+            (value, ToolRenderMode::Summary { max_chars }) => {
+                truncate(&summarize(value, 0), max_chars)
+            }
+        };
+        if self.attachments.is_empty() {
+            rendered
+        } else {
+            format!(
+                "{}\n[{} attachment(s) follow]",
+                rendered,
+                self.attachments.len()
+            )
+        }
+    }
+}
+
+/// How an agent turns `ToolOutput::value` into text for the model.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToolRenderMode {
+    /// Exact, and cheapest for small values.
+    CompactJson,
+    /// One `key: value` line per field, with long strings and arrays shortened,
+    /// cut off at `max_chars`.
+    Summary { max_chars: usize },
+}
+
+/// Lets a tool function return `MyResult<T>` for any serializable `T`, or a
+/// `ToolOutput` when it has attachments.
+pub trait IntoToolOutput {
+    fn into_tool_output(self) -> MyResult<ToolOutput>;
+}
+
+impl<T: Serialize> IntoToolOutput for T {
+    fn into_tool_output(self) -> MyResult<ToolOutput> {
+        ToolOutput::new(self)
+    }
+}
+
+impl IntoToolOutput for ToolOutput {
+    fn into_tool_output(self) -> MyResult<ToolOutput> {
+        Ok(self)
+    }
+}
+
+fn summarize(value: &Value, depth: usize) -> String {
+    let indent = "  ".repeat(depth);
+    match value {
+        Value::String(text) => truncate(text, SUMMARY_FIELD_CHARS),
+        Value::Array(items) if items.iter().all(|item| !item.is_object()) => {
+            let shown = items
+                .iter()
+                .take(5)
+                .map(|item| summarize(item, depth))
+                .collect::<Vec<String>>()
+                .join(", ");
+            match items.len() {
+                0..=5 => format!("[{}]", shown),
+                n => format!("[{}, ... {} more]", shown, n - 5),
+            }
+        }
+        Value::Array(items) => items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| {
+                format!(
+                    "\n{}- item {}:{}",
+                    indent,
+                    i + 1,
+                    summarize(item, depth + 1)
+                )
+            })
+            .collect(),
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, value)| {
+                let value = summarize(value, depth + 1);
+                if value.starts_with('\n') {
+                    format!("\n{}{}:{}", indent, key, value)
+                } else {
+                    format!("\n{}{}: {}", indent, key, value)
+                }
+            })
+            .collect(),
+        other => other.to_string(),
+    }
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    let text = text.trim_start_matches('\n');
+    match text.char_indices().nth(max_chars) {
+        Some((cut, _)) => format!("{}... (truncated)", &text[..cut]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Forecast {
+        city: String,
+        temperatures: Vec<i32>,
+        stations: Vec<Station>,
+    }
+
+    #[derive(Serialize)]
+    struct Station {
+        name: String,
+        online: bool,
+    }
+
+    fn forecast() -> ToolOutput {
+        Forecast {
+            city: "Oslo".to_string(),
+            temperatures: vec![3, 4, 2, 1, 0, -1, -2],
+            stations: vec![Station {
+                name: "Blindern".to_string(),
+                online: true,
+            }],
+        }
+        .into_tool_output()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_render_modes() {
+        let output = forecast();
+        assert_eq!(output.value["temperatures"][6], -2);
+
+        assert_eq!(
+            output.render(ToolRenderMode::CompactJson),
+            r#"{"city":"Oslo","stations":[{"name":"Blindern","online":true}],"temperatures":[3,4,2,1,0,-1,-2]}"#
+        );
+        assert_eq!(
+            output.render(ToolRenderMode::Summary { max_chars: 1000 }),
+            "city: Oslo\nstations:\n  - item 1:\n    name: Blindern\n    online: true\ntemperatures: [3, 4, 2, 1, 0, ... 2 more]"
+        );
+        assert_eq!(
+            output.render(ToolRenderMode::Summary { max_chars: 10 }),
+            "city: Oslo... (truncated)"
+        );
+        assert_eq!(
+            "plain text"
+                .into_tool_output()
+                .unwrap()
+                .render(ToolRenderMode::CompactJson),
+            "plain text"
+        );
+    }
+}
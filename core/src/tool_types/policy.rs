@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::sync::{mpsc, oneshot};
+
+use crate::msg_types::chat_msg_types::{ChatMessage, TextMessage};
+use crate::msg_types::AgentId;
+
+use super::{FunctionCallInput, MyResult};
+
+/// How long a tool call waits for an answer unless `with_approval_timeout` is used.
+pub const DEFAULT_APPROVAL_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolPermission {
+    Allow,
+    /// Each call waits for a yes from the policy's approver.
+    RequireApproval,
+    /// Never run, and not offered to the model.
+    Deny,
+}
+
+/// Sent to the approver for every call that needs approval. Answer through
+/// `reply` with a `TextMessage` whose first word is "yes" or "y" to let it run;
+/// anything else rejects it, and the text is passed to the model as the reason.
+pub struct ApprovalRequest {
+    /// A readable description of the call, sent from the requesting agent.
+    pub message: ChatMessage,
+    pub call: FunctionCallInput,
+    /// The agent that should answer, as named by the `Approver`.
+    pub approver: AgentId,
+    pub reply: oneshot::Sender<ChatMessage>,
+}
+
+impl ApprovalRequest {
+    pub fn approve(self) {
+        self.answer("yes");
+    }
+
+    pub fn reject(self, reason: &str) {
+        self.answer(&format!("no: {}", reason));
+    }
+
+    fn answer(self, text: &str) {
+        let message = ChatMessage::TextMessage(TextMessage {
+            content: text.into(),
+            source: self.approver,
+        });
+        // The caller may have timed out already; the answer is then moot.
+        let _ = self.reply.send(message);
+    }
+}
+
+/// The user or proxy agent that decides on calls marked `RequireApproval`.
+#[derive(Clone)]
+pub struct Approver {
+    pub agent: AgentId,
+    requests: mpsc::Sender<ApprovalRequest>,
+}
+
+impl Approver {
+    /// Returns the approver and the receiver its agent should answer from.
+    pub fn channel(agent: AgentId) -> (Self, mpsc::Receiver<ApprovalRequest>) {
+        let (requests, rx) = mpsc::channel(16);
+        (Approver { agent, requests }, rx)
+    }
+}
+
+/// Decides which tool calls run. Permissions are looked up by tool name, falling
+/// back to the default for tools that are not listed.
+#[derive(Clone)]
+pub struct ToolPolicy {
+    default: ToolPermission,
+    permissions: HashMap<String, ToolPermission>,
+    approver: Option<Approver>,
+    approval_timeout: Duration,
+}
+
+impl Default for ToolPolicy {
+    fn default() -> Self {
+        ToolPolicy::new(ToolPermission::Allow)
+    }
+}
+
+impl ToolPolicy {
+    pub fn new(default: ToolPermission) -> Self {
+        ToolPolicy {
+            default,
+            permissions: HashMap::new(),
+            approver: None,
+            approval_timeout: DEFAULT_APPROVAL_TIMEOUT,
+        }
+    }
+
+    pub fn with_permission(mut self, tool: &str, permission: ToolPermission) -> Self {
+        self.permissions.insert(tool.to_string(), permission);
+        self
+    }
+
+    pub fn with_approver(mut self, approver: Approver) -> Self {
+        self.approver = Some(approver);
+        self
+    }
+
+    pub fn with_approval_timeout(mut self, timeout: Duration) -> Self {
+        self.approval_timeout = timeout;
+        self
+    }
+
+    pub fn permission(&self, tool: &str) -> ToolPermission {
+        self.permissions.get(tool).copied().unwrap_or(self.default)
+    }
+
+    /// Succeeds if `call` may run, asking the approver first when the tool
+    /// requires it. A missing approver, a timeout or any answer but yes rejects it.
+    pub async fn check(&self, call: &FunctionCallInput, requester: &AgentId) -> MyResult<()> {
+        let name = &call.function_name;
+        match self.permission(name) {
+            ToolPermission::Allow => return Ok(()),
+            ToolPermission::Deny => return Err(format!("Tool {} is not allowed", name).into()),
+            ToolPermission::RequireApproval => {}
+        }
+        let approver = self
+            .approver
+            .as_ref()
+            .ok_or_else(|| format!("Tool {} needs approval but nobody can give it", name))?;
+
+        let (reply, answer) = oneshot::channel();
+        let request = ApprovalRequest {
+            message: ChatMessage::TextMessage(TextMessage {
+                content: format!(
+                    "{} wants to run {} with {}. Approve? (yes/no)",
+                    requester.get_text().unwrap_or_default(),
+                    name,
+                    call.arguments_obj
+                )
+                .into(),
+                source: requester.clone(),
+            }),
+            call: call.clone(),
+            approver: approver.agent.clone(),
+            reply,
+        };
+        // The approver's queue may be full, so sending counts against the timeout too.
+        let answered = tokio::time::timeout(self.approval_timeout, async {
+            approver
+                .requests
+                .send(request)
+                .await
+                .map_err(|_| format!("Approver for tool {} has gone away", name))?;
+            answer
+                .await
+                .map_err(|_| format!("Approver dropped the request for tool {}", name))
+        })
+        .await;
+
+        match answered {
+            Err(_) => Err(format!(
+                "No approval for tool {} within {:?}",
+                name, self.approval_timeout
+            )
+            .into()),
+            Ok(Err(e)) => Err(e.into()),
+            Ok(Ok(ChatMessage::TextMessage(tm))) if is_yes(&tm.content.text) => Ok(()),
+            Ok(Ok(ChatMessage::TextMessage(tm))) => {
+                Err(format!("Tool {} was not approved: {}", name, tm.content.text.trim()).into())
+            }
+            Ok(Ok(_)) => Err(format!("Tool {} was not approved", name).into()),
+        }
+    }
+}
+
+/// True if the first word, without surrounding punctuation, is exactly "yes" or "y".
+fn is_yes(text: &str) -> bool {
+    let first = text.split_whitespace().next().unwrap_or_default();
+    let word = first.trim_matches(|c: char| c.is_ascii_punctuation());
+    word.eq_ignore_ascii_case("yes") || word.eq_ignore_ascii_case("y")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str) -> FunctionCallInput {
+        FunctionCallInput {
+            id: "call_1".to_string(),
+            arguments_obj: serde_json::json!({"command": "rm -rf build"}),
+            function_name: name.to_string(),
+            return_type: "".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_policy_permissions_and_approvals() {
+        let requester = AgentId::new(Some("coder"));
+        let (approver, mut requests) = Approver::channel(AgentId::new(Some("user")));
+        let policy = ToolPolicy::new(ToolPermission::RequireApproval)
+            .with_permission("get_current_weather", ToolPermission::Allow)
+            .with_permission("write_file", ToolPermission::Deny)
+            .with_approver(approver)
+            .with_approval_timeout(Duration::from_millis(50));
+
+        assert!(policy
+            .check(&call("get_current_weather"), &requester)
+            .await
+            .is_ok());
+        assert_eq!(
+            policy
+                .check(&call("write_file"), &requester)
+                .await
+                .unwrap_err()
+                .to_string(),
+            "Tool write_file is not allowed"
+        );
+
+        let answers = tokio::spawn(async move {
+            let first = requests.recv().await.unwrap();
+            let text = match &first.message {
+                ChatMessage::TextMessage(tm) => tm.content.text.clone(),
+                other => panic!("expected a text message, got {:?}", other),
+            };
+            assert_eq!(
+                text,
+                r#"coder wants to run run_shell with {"command":"rm -rf build"}. Approve? (yes/no)"#
+            );
+            first.approve();
+            requests.recv().await.unwrap().reject("not on main");
+            // Left unanswered so the call times out.
+            requests.recv().await.unwrap()
+        });
+        assert!(policy.check(&call("run_shell"), &requester).await.is_ok());
+        assert_eq!(
+            policy
+                .check(&call("run_shell"), &requester)
+                .await
+                .unwrap_err()
+                .to_string(),
+            "Tool run_shell was not approved: no: not on main"
+        );
+        assert_eq!(
+            policy
+                .check(&call("run_shell"), &requester)
+                .await
+                .unwrap_err()
+                .to_string(),
+            "No approval for tool run_shell within 50ms"
+        );
+        answers.await.unwrap();
+
+        let unattended = ToolPolicy::new(ToolPermission::RequireApproval);
+        assert!(unattended
+            .check(&call("run_shell"), &requester)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_full_approver_queue_times_out() {
+        let requester = AgentId::new(Some("coder"));
+        let (approver, _requests) = Approver::channel(AgentId::new(Some("user")));
+        let policy = ToolPolicy::new(ToolPermission::RequireApproval)
+            .with_approver(approver)
+            .with_approval_timeout(Duration::from_millis(10));
+
+        // Nobody reads the queue, so the last call cannot even send its request.
+        for _ in 0..17 {
+            assert_eq!(
+                policy
+                    .check(&call("run_shell"), &requester)
+                    .await
+                    .unwrap_err()
+                    .to_string(),
+                "No approval for tool run_shell within 10ms"
+            );
+        }
+    }
+
+    #[test]
+    fn test_only_a_plain_yes_approves() {
+        for answer in ["yes", "Y", "yes, go ahead", " YES."] {
+            assert!(is_yes(answer), "{:?} should approve", answer);
+        }
+        for answer in [
+            "yesterday I said no",
+            "yes-no?",
+            "y'know, no",
+            "no",
+            "",
+            "ok",
+        ] {
+            assert!(!is_yes(answer), "{:?} should not approve", answer);
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::sync::Arc;
+
+use jsonschema::Validator;
+use serde_json::Value;
+
+use super::MyResult;
+
+/// A tool's parameter schema, compiled once and shared by clones of the tool.
+#[derive(Clone, Debug)]
+pub struct CompiledSchema {
+    validator: Arc<Validator>,
+}
+
+impl CompiledSchema {
+    pub fn compile(schema: &Value) -> MyResult<Self> {
+        let validator =
+            jsonschema::validator_for(schema).map_err(|e| format!("Invalid JSON schema: {}", e))?;
+        Ok(CompiledSchema {
+            validator: Arc::new(validator),
+        })
+    }
+
+    /// Compiles the `parameters` of a tool definition such as `Tool::tool_def_obj`.
+    pub fn for_tool_def(tool_def_obj: &str) -> MyResult<Self> {
+        let def: Value = serde_json::from_str(tool_def_obj)?;
+        let parameters = def
+            .get("parameters")
+            .ok_or("Tool definition has no parameters")?;
+        CompiledSchema::compile(parameters)
+    }
+
+    /// Checks `arguments` against the schema, returning every violation as
+    /// "<argument path>: <problem>".
+    pub fn validate(&self, arguments: &Value) -> Result<(), Vec<String>> {
+        let violations = self
+            .validator
+            .iter_errors(arguments)
+            .map(|error| {
+                let path = error.instance_path.as_str().trim_start_matches('/');
+                match path {
+                    "" => error.to_string(),
+                    path => format!("{}: {}", path.replace('/', "."), error),
+                }
+            })
+            .collect::<Vec<String>>();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
@@ -1,100 +1,523 @@
+use std::collections::{BTreeSet, HashMap};
+
 use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
 use quote::{format_ident, quote};
-use syn::{parse_macro_input, Expr, ItemFn};
+use serde_json::Value;
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::{parse_macro_input, ItemFn, LitStr, Token};
 
+/// Turns a function into a tool by generating `<fn>_tool() -> Tool`. The JSON
+/// schema the model sees is built from the signature and the doc comment:
+///
+/// ```ignore
+/// /// Get the current weather in a given location.
+/// ///
+/// /// # Arguments
+/// /// * `location` - The city and state, e.g. San Francisco, CA
+/// #[create_tool_with_function]
+/// fn get_current_weather(location: String, unit: Option<String>) -> MyResult<String> {
+/// ```
+///
+/// `Option` arguments are left out of `required`. Types other than primitives,
+/// strings, collections and `serde_json::Value` describe themselves through
+/// `ToolArgSchema`. `#[create_tool_with_function(schema = r#"{...}"#)]` replaces
+/// the generated schema; it must be a literal so it can be checked against the
+/// signature here. `timeout_secs = N` overrides `DEFAULT_TOOL_TIMEOUT`. The
+/// schema is compiled on first use and arguments are checked against it before
+/// every call.
+///
+/// The function may return `MyResult<T>` for any `Serialize` type `T`, or
+/// `MyResult<ToolOutput>` to attach images or files. `async fn` tools are
+/// awaited on the runtime; plain functions run on tokio's blocking pool.
 #[proc_macro_attribute]
 pub fn create_tool_with_function(attr: TokenStream, item: TokenStream) -> TokenStream {
     let input_fn = parse_macro_input!(item as ItemFn);
+    match expand(attr.into(), &input_fn) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
 
-    let attr_expr = parse_macro_input!(attr as Expr);
+struct Arg {
+    name: syn::Ident,
+    ty: syn::Type,
+    optional: bool,
+}
+
+fn expand(attr: TokenStream2, input_fn: &ItemFn) -> syn::Result<TokenStream2> {
+    let options = parse_options(attr)?;
 
     let fn_name = &input_fn.sig.ident;
     let fn_name_str = fn_name.to_string();
+    let constructor_name = format_ident!("{}_tool", fn_name_str);
+
+    let mut args = Vec::new();
+    for arg in &input_fn.sig.inputs {
+        let syn::FnArg::Typed(pat_type) = arg else {
+            return Err(syn::Error::new_spanned(arg, "tools must be free functions"));
+        };
+        let syn::Pat::Ident(pat_ident) = &*pat_type.pat else {
+            return Err(syn::Error::new_spanned(
+                &pat_type.pat,
+                "tool arguments must be plain identifiers",
+            ));
+        };
+        args.push(Arg {
+            name: pat_ident.ident.clone(),
+            ty: (*pat_type.ty).clone(),
+            optional: is_option(&pat_type.ty),
+        });
+    }
+
+    let tool_def_obj = match &options.schema {
+        Some(schema) => {
+            check_override(schema, &fn_name_str, &args)?;
+            quote! { #schema.to_string() }
+        }
+        None => generated_schema(input_fn, &fn_name_str, &args)?,
+    };
 
-    let register_fn_name = format_ident!("register_tool_{}", fn_name_str);
+    let arg_names = args.iter().map(|arg| &arg.name).collect::<Vec<_>>();
+    let arg_types = args.iter().map(|arg| &arg.ty);
+    let optional = args.iter().map(|arg| arg.optional);
+    // Borrowed arguments are deserialized into their owned form and lent out.
+    let owned_types = args.iter().map(|arg| match &arg.ty {
+        syn::Type::Reference(reference) => owned_type(&reference.elem),
+        other => quote! { #other },
+    });
+    let borrows = args.iter().map(|arg| match &arg.ty {
+        syn::Type::Reference(_) => quote! { & },
+        _ => quote! {},
+    });
 
-    let inputs = &input_fn.sig.inputs;
-    let mut arg_names = Vec::new();
-    let mut arg_type_tokens = Vec::new();
+    // Synchronous tools run on the blocking pool so they cannot stall the runtime.
+    let call = if input_fn.sig.asyncness.is_some() {
+        quote! { #fn_name(#(#borrows #arg_names),*).await }
+    } else {
+        quote! {
+            tokio::task::spawn_blocking(move || #fn_name(#(#borrows #arg_names),*))
+                .await
+                .map_err(|e| format!("Tool {} panicked: {}", #fn_name_str, e))?
+        }
+    };
+    let timeout = match &options.timeout_secs {
+        Some(secs) => quote! { std::time::Duration::from_secs(#secs) },
+        None => quote! { DEFAULT_TOOL_TIMEOUT },
+    };
+
+    Ok(quote! {
+        #input_fn
+
+        /// Builds the `Tool` wrapping this function, ready to add to a `ToolRegistry`.
+        pub fn #constructor_name() -> Tool {
+            let tool_def_obj: String = #tool_def_obj;
+            // Overrides were compiled by the macro; a generated schema can only
+            // fail here through a broken `ToolArgSchema` impl.
+            static SCHEMA: std::sync::OnceLock<CompiledSchema> = std::sync::OnceLock::new();
+            let schema = SCHEMA
+                .get_or_init(|| {
+                    CompiledSchema::for_tool_def(&tool_def_obj).unwrap_or_else(|e| {
+                        panic!("Invalid schema for tool {}: {}", #fn_name_str, e)
+                    })
+                })
+                .clone();
+            let arg_names = vec![#(stringify!(#arg_names).to_string()),*];
+            let arg_types = vec![#(stringify!(#arg_types).to_string()),*];
+            let arg_optional = vec![#(#optional),*];
 
-    for arg in inputs {
-        if let syn::FnArg::Typed(pat_type) = arg {
-            if let syn::Pat::Ident(pat_ident) = &*pat_type.pat {
-                let arg_name = &pat_ident.ident;
-                arg_names.push(quote! { #arg_name });
+            let func = {
+                use std::sync::Arc;
+                let func = Arc::new(move |args: Vec<serde_json::Value>| -> ToolFuture {
+                    Box::pin(async move {
+                        let mut iter = args.into_iter();
+                        #(
+                            let #arg_names = {
+                                let arg = iter.next().ok_or("Not enough arguments")?;
+                                serde_json::from_value::<#owned_types>(arg).map_err(|e| {
+                                    format!("Invalid argument {}: {}", stringify!(#arg_names), e)
+                                })?
+                            };
+                        )*
+                        let output = #call?;
+                        IntoToolOutput::into_tool_output(output)
+                    })
+                }) as Arc<dyn Fn(Vec<serde_json::Value>) -> ToolFuture + Send + Sync>;
+                func
+            };
+
+            Tool {
+                name: (#fn_name_str).to_string(),
+                function: func,
+                tool_def_obj,
+                arg_names,
+                arg_types,
+                arg_optional,
+                timeout: #timeout,
+                schema,
             }
-            let arg_type = &*pat_type.ty;
-            arg_type_tokens.push(arg_type.clone());
         }
+    })
+}
+
+#[derive(Default)]
+struct Options {
+    schema: Option<LitStr>,
+    timeout_secs: Option<syn::LitInt>,
+}
+
+fn parse_options(attr: TokenStream2) -> syn::Result<Options> {
+    let mut options = Options::default();
+    let parser = Punctuated::<syn::MetaNameValue, Token![,]>::parse_terminated;
+    let metas = parser.parse2(attr.clone()).map_err(|_| {
+        syn::Error::new_spanned(
+            &attr,
+            "expected `schema = r#\"...\"#` and/or `timeout_secs = <seconds>`",
+        )
+    })?;
+    for meta in metas {
+        let syn::Expr::Lit(syn::ExprLit { lit, .. }) = &meta.value else {
+            return Err(syn::Error::new_spanned(
+                &meta.value,
+                "tool options must be literals so they can be checked at compile time",
+            ));
+        };
+        match (meta.path.get_ident().map(|i| i.to_string()).as_deref(), lit) {
+            (Some("schema"), syn::Lit::Str(schema)) => options.schema = Some(schema.clone()),
+            (Some("timeout_secs"), syn::Lit::Int(secs)) => {
+                secs.base10_parse::<u64>()?;
+                options.timeout_secs = Some(secs.clone());
+            }
+            (Some("schema"), _) => {
+                return Err(syn::Error::new_spanned(lit, "`schema` must be a string"))
+            }
+            (Some("timeout_secs"), _) => {
+                return Err(syn::Error::new_spanned(
+                    lit,
+                    "`timeout_secs` must be an integer",
+                ))
+            }
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &meta.path,
+                    "unknown option, expected `schema` or `timeout_secs`",
+                ))
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// The override must be a valid JSON Schema, describe exactly the function's
+/// arguments and require every argument that is not an `Option`.
+fn check_override(schema: &LitStr, fn_name: &str, args: &[Arg]) -> syn::Result<()> {
+    let fail = |message: String| Err(syn::Error::new_spanned(schema, message));
+    let value = match serde_json::from_str::<Value>(&schema.value()) {
+        Ok(value) => value,
+        Err(e) => return fail(format!("schema is not valid JSON: {}", e)),
+    };
+
+    if value["name"] != fn_name {
+        return fail(format!(
+            "schema name {} does not match function {}",
+            value["name"], fn_name
+        ));
+    }
+    let Some(properties) = value["parameters"]["properties"].as_object() else {
+        return fail("schema has no parameters.properties object".to_string());
+    };
+    let documented = properties.keys().cloned().collect::<BTreeSet<String>>();
+    let declared = args
+        .iter()
+        .map(|arg| arg.name.to_string())
+        .collect::<BTreeSet<String>>();
+    if documented != declared {
+        return fail(format!(
+            "schema properties {:?} do not match the arguments {:?}",
+            documented, declared
+        ));
+    }
+
+    // The same compilation `CompiledSchema` does when the tool is built, so a
+    // type such as "i32" fails the build instead of the first `<fn>_tool()` call.
+    if let Err(e) = jsonschema::validator_for(&value["parameters"]) {
+        return fail(format!("parameters is not a valid JSON Schema: {}", e));
     }
 
-    let gen = quote! {
-                #input_fn
-
-                #[ctor]
-                fn #register_fn_name() {
-                    let arg_names = vec![#(stringify!(#arg_names).to_string()),*];
-                    let arg_types = vec![#(stringify!(#arg_type_tokens).to_string()),*];
-
-                    let func = {
-                        use std::sync::Arc;
-                        let func = Arc::new(move |args: &[SupportedType]| -> MyResult<String> {
-                            let parsers = get_parsers();
-
-                            let mut iter = args.iter();
-                            #(
-                                let arg_type = stringify!(#arg_type_tokens);
-                                let #arg_names = {
-                                    let arg = iter.next().ok_or("Not enough arguments")?.clone();
-                                    let parser = parsers.get(arg_type)
-                                        .ok_or(format!("Parser not found for type {}", arg_type))?;
-                                    let any_val = parser(arg)?;
-                                    let val = any_val.downcast::<#arg_type_tokens>()
-                                        .map_err(|_| "Type mismatch")?;
-                                    *val
-                                };
-                            )*
-
-                            #fn_name(#(#arg_names),*)
-                        }) as Arc<dyn Fn(&[SupportedType]) -> MyResult<String> + Send + Sync>;
-                        func
-                    };
-
-                    let tool_def_obj: String = (#attr_expr).to_string();
-
-    use serde::Deserialize;
-
-        #[derive(Deserialize)]
-        struct ToolMeta {
-            name: String,
+    let required = match &value["parameters"]["required"] {
+        Value::Null => Vec::new(),
+        Value::Array(required) => required.iter().filter_map(Value::as_str).collect(),
+        _ => return fail("parameters.required must be an array".to_string()),
+    };
+    for arg in args {
+        let name = arg.name.to_string();
+        if !arg.optional && !required.contains(&name.as_str()) {
+            return fail(format!(
+                "argument {} is not an Option, so it must be listed in parameters.required",
+                name
+            ));
         }
+    }
+    Ok(())
+}
 
-        let json_name = match serde_json::from_str::<ToolMeta>(&tool_def_obj) {
-            Ok(tool_meta) => tool_meta.name,
-            Err(_) => "Unknown".to_string(),
-        };
-                    let fn_name_str = stringify!(#fn_name);
+fn generated_schema(input_fn: &ItemFn, fn_name: &str, args: &[Arg]) -> syn::Result<TokenStream2> {
+    let docs = parse_docs(&input_fn.attrs);
+    if docs.description.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input_fn.sig.ident,
+            "document the tool with a `///` comment, it becomes the description the model sees",
+        ));
+    }
+    for (name, (attr, _)) in &docs.args {
+        if !args.iter().any(|arg| arg.name == name) {
+            return Err(syn::Error::new_spanned(
+                attr,
+                format!("documented argument `{}` is not in the signature", name),
+            ));
+        }
+    }
 
-                    if fn_name_str != json_name {
-                        panic!(
-                            "Function name '{}' does not match 'name' field '{}' in tool definition",
-                            fn_name_str, json_name
-                        );
+    let description = &docs.description;
+    let properties = args.iter().map(|arg| {
+        let name = arg.name.to_string();
+        let schema = schema_expr(&arg.ty);
+        match docs.args.get(&name) {
+            Some((_, text)) => quote! {
+                properties.insert(#name.to_string(), {
+                    let mut schema = #schema;
+                    if let Some(map) = schema.as_object_mut() {
+                        map.insert("description".to_string(), serde_json::Value::from(#text));
                     }
+                    schema
+                });
+            },
+            None => quote! {
+                properties.insert(#name.to_string(), #schema);
+            },
+        }
+    });
+    let required = args
+        .iter()
+        .filter(|arg| !arg.optional)
+        .map(|arg| arg.name.to_string());
 
-                    let tool = Tool {
-                        name: (#fn_name_str).to_string(),
-                        function: func,
-                        tool_def_obj: tool_def_obj,
-                        arg_names: arg_names,
-                        arg_types: arg_types,
-                    };
+    Ok(quote! {
+        {
+            let mut properties = serde_json::Map::new();
+            #(#properties)*
+            serde_json::json!({
+                "name": #fn_name,
+                "description": #description,
+                "parameters": {
+                    "type": "object",
+                    "properties": properties,
+                    "required": [#(#required),*]
+                }
+            })
+            .to_string()
+        }
+    })
+}
 
-                    {
-                        STORE.lock().unwrap().insert(tool.name.clone(), tool.clone());
+struct Docs<'a> {
+    description: String,
+    /// Argument name to the doc line that describes it and the description.
+    args: HashMap<String, (&'a syn::Attribute, String)>,
+}
+
+/// Splits a rustdoc comment into the summary paragraphs and the bullets of its
+/// `# Arguments` section (`` * `name` - description ``).
+fn parse_docs(attrs: &[syn::Attribute]) -> Docs<'_> {
+    let mut paragraphs: Vec<String> = vec![String::new()];
+    let mut args: HashMap<String, (&syn::Attribute, String)> = HashMap::new();
+    let mut section: Option<String> = None;
+    let mut last_arg: Option<String> = None;
+
+    for attr in attrs {
+        let syn::Meta::NameValue(meta) = &attr.meta else {
+            continue;
+        };
+        if !meta.path.is_ident("doc") {
+            continue;
+        }
+        let syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Str(text),
+            ..
+        }) = &meta.value
+        else {
+            continue;
+        };
+        let text = text.value();
+        let line = text.trim();
+
+        if let Some(heading) = line.strip_prefix("# ") {
+            section = Some(heading.trim().to_lowercase());
+            last_arg = None;
+            continue;
+        }
+        match section.as_deref() {
+            None if line.is_empty() => {
+                if !paragraphs.last().is_some_and(String::is_empty) {
+                    paragraphs.push(String::new());
+                }
+            }
+            None => {
+                let paragraph = paragraphs.last_mut().expect("never empty");
+                if !paragraph.is_empty() {
+                    paragraph.push(' ');
+                }
+                paragraph.push_str(line);
+            }
+            Some("arguments" | "parameters") => {
+                if let Some((name, description)) = parse_arg_line(line) {
+                    args.insert(name.clone(), (attr, description));
+                    last_arg = Some(name);
+                } else if let Some((_, description)) =
+                    last_arg.as_ref().and_then(|name| args.get_mut(name))
+                {
+                    if !line.is_empty() {
+                        description.push(' ');
+                        description.push_str(line);
                     }
                 }
-            };
+            }
+            Some(_) => {}
+        }
+    }
+
+    let description = paragraphs
+        .into_iter()
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect::<Vec<String>>()
+        .join("\n\n");
+    Docs { description, args }
+}
+
+fn parse_arg_line(line: &str) -> Option<(String, String)> {
+    let rest = line
+        .strip_prefix('*')
+        .or_else(|| line.strip_prefix('-'))?
+        .trim_start()
+        .strip_prefix('`')?;
+    let (name, description) = rest.split_once('`')?;
+    let description = description
+        .trim_start()
+        .trim_start_matches(['-', ':'])
+        .trim();
+    Some((name.to_string(), description.to_string()))
+}
+
+/// An expression evaluating to the `serde_json::Value` schema of `ty`.
+fn schema_expr(ty: &syn::Type) -> TokenStream2 {
+    let fallback = quote! { <#ty as ToolArgSchema>::tool_schema() };
+    let path = match ty {
+        syn::Type::Reference(reference) => return schema_expr(&reference.elem),
+        syn::Type::Paren(paren) => return schema_expr(&paren.elem),
+        syn::Type::Slice(slice) => return array_schema(&slice.elem, false),
+        syn::Type::Array(array) => return array_schema(&array.elem, false),
+        syn::Type::Path(path) if path.qself.is_none() => &path.path,
+        _ => return fallback,
+    };
+    let Some(segment) = path.segments.last() else {
+        return fallback;
+    };
+    let generics = match &segment.arguments {
+        syn::PathArguments::AngleBracketed(angle) => angle
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                syn::GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
 
-    gen.into()
+    match (segment.ident.to_string().as_str(), &generics[..]) {
+        ("i8" | "i16" | "i32" | "i64" | "i128" | "isize", []) => {
+            quote! { serde_json::json!({"type": "integer"}) }
+        }
+        ("u8" | "u16" | "u32" | "u64" | "u128" | "usize", []) => {
+            quote! { serde_json::json!({"type": "integer", "minimum": 0}) }
+        }
+        ("f32" | "f64", []) => quote! { serde_json::json!({"type": "number"}) },
+        ("bool", []) => quote! { serde_json::json!({"type": "boolean"}) },
+        ("String" | "str" | "char", []) => quote! { serde_json::json!({"type": "string"}) },
+        ("Value", []) => quote! { serde_json::json!({}) },
+        ("Option" | "Box" | "Arc" | "Rc", [inner]) => schema_expr(inner),
+        ("Vec" | "VecDeque", [inner]) => array_schema(inner, false),
+        ("HashSet" | "BTreeSet", [inner]) => array_schema(inner, true),
+        ("HashMap" | "BTreeMap", [_, value]) => {
+            let values = schema_expr(value);
+            quote! { serde_json::json!({"type": "object", "additionalProperties": (#values)}) }
+        }
+        _ => fallback,
+    }
+}
+
+fn array_schema(item: &syn::Type, unique: bool) -> TokenStream2 {
+    let items = schema_expr(item);
+    if unique {
+        quote! { serde_json::json!({"type": "array", "items": (#items), "uniqueItems": true}) }
+    } else {
+        quote! { serde_json::json!({"type": "array", "items": (#items)}) }
+    }
+}
+
+/// `Option<T>` arguments may be left out by the model.
+fn is_option(ty: &syn::Type) -> bool {
+    match ty {
+        syn::Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Option"),
+        _ => false,
+    }
+}
+
+fn owned_type(ty: &syn::Type) -> TokenStream2 {
+    match ty {
+        syn::Type::Path(path) if path.path.is_ident("str") => quote! { String },
+        syn::Type::Slice(slice) => {
+            let elem = &slice.elem;
+            quote! { Vec<#elem> }
+        }
+        other => quote! { #other },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(schema: &str) -> syn::Result<()> {
+        let args = [Arg {
+            name: format_ident!("a"),
+            ty: syn::parse_quote!(i32),
+            optional: false,
+        }];
+        let schema = LitStr::new(schema, proc_macro2::Span::call_site());
+        check_override(&schema, "process_values", &args)
+    }
+
+    #[test]
+    fn test_override_types_are_checked() {
+        assert!(check(
+            r#"{"name": "process_values", "parameters": {"type": "object",
+                "properties": {"a": {"type": "integer"}}, "required": ["a"]}}"#
+        )
+        .is_ok());
+
+        let err = check(
+            r#"{"name": "process_values", "parameters": {"type": "object",
+                "properties": {"a": {"type": "i32"}}, "required": ["a"]}}"#,
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("parameters is not a valid JSON Schema"));
+    }
 }